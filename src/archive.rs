@@ -0,0 +1,366 @@
+//! Files inside zip and tar archives, searched like regular files with
+//! `SearchOptions::archives`. An entry's path is the archive's followed by
+//! `!` and the entry's name, e.g. `release.zip!src/main.rs`.
+
+use crate::inflate;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Kind {
+    /// Recognizes archives by their extension: `.zip`, `.tar`, `.tar.gz`
+    /// and `.tgz`.
+    pub fn from_path(path: &Path) -> Option<Kind> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Kind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Kind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Kind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// The path matches in the entry `name` of `archive` are reported under.
+pub fn entry_path(archive: &Path, name: &str) -> PathBuf {
+    let mut path = OsString::from(archive);
+    path.push("!");
+    path.push(name);
+    PathBuf::from(path)
+}
+
+/// Splits a path made by [`entry_path`] into the archive and the entry's
+/// name, or returns `None` for the path of a regular file.
+pub fn split_entry_path(path: &Path) -> Option<(&Path, &str)> {
+    let text = path.to_str()?;
+    text.match_indices('!').find_map(|(i, _)| {
+        let archive = Path::new(&text[..i]);
+        (Kind::from_path(archive).is_some() && archive.is_file()).then(|| (archive, &text[i + 1..]))
+    })
+}
+
+/// Reads the contents of the entry at `path`, a path made by
+/// [`entry_path`]. `None` if it isn't in an archive.
+pub fn read_entry(path: &Path) -> Option<io::Result<Vec<u8>>> {
+    let (archive, wanted) = split_entry_path(path)?;
+    let kind = Kind::from_path(archive)?;
+    let read = || {
+        let bytes = fs::read(archive)?;
+        let mut found = None;
+        for_each_entry(kind, &bytes, &mut |name, contents| {
+            if name == wanted {
+                found = Some(contents.to_vec());
+            }
+            found.is_none()
+        })?;
+        found.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such archive entry"))
+    };
+    Some(read())
+}
+
+/// Calls `visit` with the name and contents of every regular file in the
+/// archive `bytes`, stopping early once it returns `false`. Entries that
+/// can't be decompressed are skipped; an error means the archive itself
+/// is unreadable. Returns whether `visit` wanted to go on.
+pub(crate) fn for_each_entry(
+    kind: Kind,
+    bytes: &[u8],
+    visit: &mut dyn FnMut(&str, &[u8]) -> bool,
+) -> io::Result<bool> {
+    match kind {
+        Kind::Zip => zip_entries(bytes, visit),
+        Kind::Tar => tar_entries(bytes, visit),
+        Kind::TarGz => tar_entries(&inflate::gunzip(bytes)?, visit),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn u16_at(bytes: &[u8], pos: usize) -> io::Result<usize> {
+    bytes
+        .get(pos..pos + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+        .ok_or_else(|| invalid("zip file ends early"))
+}
+
+fn u32_at(bytes: &[u8], pos: usize) -> io::Result<usize> {
+    bytes
+        .get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        .ok_or_else(|| invalid("zip file ends early"))
+}
+
+/// Reads a zip file through its central directory, which has the sizes
+/// that local headers may leave out. Entries that are neither stored nor
+/// deflated, and zip64 ones, are skipped.
+fn zip_entries(bytes: &[u8], visit: &mut dyn FnMut(&str, &[u8]) -> bool) -> io::Result<bool> {
+    const END_SIGNATURE: &[u8] = b"PK\x05\x06";
+    const ENTRY_SIGNATURE: &[u8] = b"PK\x01\x02";
+    const LOCAL_SIGNATURE: &[u8] = b"PK\x03\x04";
+
+    // the end record is last, but for a trailing comment of up to 64 KiB
+    let search_from = bytes.len().saturating_sub(22 + 0xFFFF);
+    let end = memchr::memmem::rfind(&bytes[search_from..], END_SIGNATURE)
+        .map(|i| search_from + i)
+        .ok_or_else(|| invalid("not a zip file"))?;
+    let count = u16_at(bytes, end + 10)?;
+    let mut pos = u32_at(bytes, end + 16)?;
+
+    for _ in 0..count {
+        if bytes.get(pos..pos + 4) != Some(ENTRY_SIGNATURE) {
+            return Err(invalid("corrupt zip central directory"));
+        }
+        let method = u16_at(bytes, pos + 10)?;
+        let compressed = u32_at(bytes, pos + 20)?;
+        let name_len = u16_at(bytes, pos + 28)?;
+        let header_len = 46 + name_len + u16_at(bytes, pos + 30)? + u16_at(bytes, pos + 32)?;
+        let local = u32_at(bytes, pos + 42)?;
+        let name = bytes
+            .get(pos + 46..pos + 46 + name_len)
+            .ok_or_else(|| invalid("zip file ends early"))?;
+        pos += header_len;
+
+        let name = String::from_utf8_lossy(name);
+        if name.ends_with('/') || compressed == 0xFFFF_FFFF || local == 0xFFFF_FFFF {
+            continue;
+        }
+        if bytes.get(local..local + 4) != Some(LOCAL_SIGNATURE) {
+            return Err(invalid("corrupt zip entry"));
+        }
+        let start = local + 30 + u16_at(bytes, local + 26)? + u16_at(bytes, local + 28)?;
+        let Some(data) = bytes.get(start..start + compressed) else {
+            return Err(invalid("zip file ends early"));
+        };
+        let contents = match method {
+            0 => data.to_vec(),
+            8 => match inflate::inflate(data) {
+                Ok((contents, _)) => contents,
+                Err(_) => continue,
+            },
+            _ => continue,
+        };
+        if !visit(&name, &contents) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Reads ustar and GNU tar files, with GNU and pax long names.
+fn tar_entries(bytes: &[u8], visit: &mut dyn FnMut(&str, &[u8]) -> bool) -> io::Result<bool> {
+    const BLOCK: usize = 512;
+
+    let field = |header: &[u8], range: std::ops::Range<usize>| {
+        let field = &header[range];
+        let end = memchr::memchr(0, field).unwrap_or(field.len());
+        String::from_utf8_lossy(&field[..end]).into_owned()
+    };
+    let mut long_name: Option<String> = None;
+    let mut pos = 0;
+    while pos + BLOCK <= bytes.len() {
+        let header = &bytes[pos..pos + BLOCK];
+        // the archive ends with zeroed blocks
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size_field = field(header, 124..136);
+        let size = usize::from_str_radix(size_field.trim(), 8)
+            .map_err(|_| invalid("corrupt tar header"))?;
+        let start = pos + BLOCK;
+        let contents = bytes
+            .get(start..start + size)
+            .ok_or_else(|| invalid("tar file ends early"))?;
+        pos = start + size.div_ceil(BLOCK) * BLOCK;
+
+        match header[156] {
+            // GNU: the next entry's name
+            b'L' => {
+                let end = memchr::memchr(0, contents).unwrap_or(contents.len());
+                long_name = Some(String::from_utf8_lossy(&contents[..end]).into_owned());
+            }
+            // pax: `<len> key=value\n` records, of which the path matters
+            b'x' => {
+                for record in String::from_utf8_lossy(contents).lines() {
+                    if let Some((_, pair)) = record.split_once(' ')
+                        && let Some(path) = pair.strip_prefix("path=")
+                    {
+                        long_name = Some(path.to_string());
+                    }
+                }
+            }
+            b'0' | 0 | b'7' => {
+                let name = long_name.take().unwrap_or_else(|| {
+                    let prefix = field(header, 345..500);
+                    let name = field(header, 0..100);
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{}/{}", prefix, name)
+                    } else {
+                        name
+                    }
+                });
+                if !visit(&name, contents) {
+                    return Ok(false);
+                }
+            }
+            _ => long_name = None,
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tar header block for an entry of `size` bytes.
+    fn tar_header(name: &str, size: usize, kind: u8, prefix: &str) -> Vec<u8> {
+        let mut header = vec![0; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        header
+    }
+
+    /// `contents` padded to whole blocks, after its header.
+    fn tar_entry(name: &str, kind: u8, prefix: &str, contents: &[u8]) -> Vec<u8> {
+        let mut entry = tar_header(name, contents.len(), kind, prefix);
+        entry.extend_from_slice(contents);
+        entry.resize(512 + contents.len().div_ceil(512) * 512, 0);
+        entry
+    }
+
+    /// A zip file of `(name, method, data)` entries, stored or already
+    /// deflated.
+    fn zip(entries: &[(&str, u16, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut central = Vec::new();
+        for (name, method, data) in entries {
+            let local = out.len() as u32;
+            out.extend_from_slice(b"PK\x03\x04");
+            out.extend_from_slice(&[20, 0, 0, 0]);
+            out.extend_from_slice(&method.to_le_bytes());
+            out.extend_from_slice(&[0; 16]);
+            out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            out.extend_from_slice(&[0, 0]);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(data);
+
+            central.extend_from_slice(b"PK\x01\x02");
+            central.extend_from_slice(&[20, 0, 20, 0, 0, 0]);
+            central.extend_from_slice(&method.to_le_bytes());
+            central.extend_from_slice(&[0; 8]);
+            central.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            central.extend_from_slice(&[0; 4]);
+            central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0; 12]);
+            central.extend_from_slice(&local.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+        let directory = out.len() as u32;
+        out.extend_from_slice(&central);
+        out.extend_from_slice(b"PK\x05\x06");
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        out.extend_from_slice(&directory.to_le_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out
+    }
+
+    fn entries(kind: Kind, bytes: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+        let mut found = Vec::new();
+        for_each_entry(kind, bytes, &mut |name, contents| {
+            found.push((name.to_string(), contents.to_vec()));
+            true
+        })?;
+        Ok(found)
+    }
+
+    #[test]
+    fn reads_tar_entries_with_long_names() {
+        let long = "d/".repeat(60) + "long.txt";
+        let pax = b"16 path=pax.txt\n";
+        let tar = [
+            tar_entry("a.txt", b'0', "", b"alpha\n"),
+            tar_entry("dir/", b'5', "", b""),
+            tar_entry("b.txt", b'0', "nested", &[b'b'; 600]),
+            tar_entry("././@LongLink", b'L', "", format!("{}\0", long).as_bytes()),
+            tar_entry("truncated", b'0', "", b"gnu\n"),
+            tar_entry("PaxHeader", b'x', "", pax),
+            tar_entry("short", 0, "", b"pax\n"),
+            vec![0; 1024],
+        ]
+        .concat();
+        let found = entries(Kind::Tar, &tar).unwrap();
+        let names: Vec<&str> = found.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["a.txt", "nested/b.txt", long.as_str(), "pax.txt"]);
+        assert_eq!(found[0].1, b"alpha\n");
+        assert_eq!(found[1].1, [b'b'; 600]);
+        assert_eq!(found[3].1, b"pax\n");
+    }
+
+    #[test]
+    fn rejects_truncated_tar_files() {
+        let tar = tar_entry("a.txt", b'0', "", &[b'a'; 600]);
+        assert!(entries(Kind::Tar, &tar[..1000]).is_err());
+    }
+
+    #[test]
+    fn reads_stored_and_deflated_zip_entries() {
+        // "hello hello hello", deflated with a fixed Huffman block
+        let deflated = b"\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x90\x00";
+        let zip = zip(&[
+            ("stored.txt", 0, b"plain\n"),
+            ("dir/", 0, b""),
+            ("dir/deflated.txt", 8, deflated),
+            ("other.bin", 12, b"bzip2"),
+        ]);
+        assert_eq!(
+            entries(Kind::Zip, &zip).unwrap(),
+            [
+                ("stored.txt".to_string(), b"plain\n".to_vec()),
+                (
+                    "dir/deflated.txt".to_string(),
+                    b"hello hello hello".to_vec()
+                ),
+            ]
+        );
+        assert!(entries(Kind::Zip, b"not a zip").is_err());
+    }
+
+    #[test]
+    fn stops_when_visit_says_so() {
+        let zip = zip(&[("a", 0, b"a"), ("b", 0, b"b")]);
+        let mut names = Vec::new();
+        let went_on = for_each_entry(Kind::Zip, &zip, &mut |name, _| {
+            names.push(name.to_string());
+            false
+        });
+        assert!(!went_on.unwrap());
+        assert_eq!(names, ["a"]);
+    }
+
+    #[test]
+    fn entry_paths_name_the_archive_and_the_entry() {
+        let path = entry_path(Path::new("release.zip"), "src/main.rs");
+        assert_eq!(path, Path::new("release.zip!src/main.rs"));
+        assert_eq!(Kind::from_path(Path::new("a.TGZ")), Some(Kind::TarGz));
+        assert_eq!(Kind::from_path(Path::new("a.tar.xz")), None);
+    }
+}
@@ -0,0 +1,98 @@
+//! Blame for the TUI's results, looked up with `git blame` on a thread of
+//! its own as results come into view, and kept once known.
+
+use rstr::git::{self, Blame};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Per file and line: `None` while it's being looked up, then the blame or
+/// `None` if git had none, e.g. outside a repository.
+type Cache = HashMap<(PathBuf, usize), Option<Option<Blame>>>;
+
+pub struct Blames {
+    cache: Arc<Mutex<Cache>>,
+    requests: Sender<(PathBuf, usize)>,
+}
+
+impl Blames {
+    pub fn new() -> Blames {
+        let cache: Arc<Mutex<Cache>> = Arc::default();
+        let (requests, rx) = mpsc::channel::<(PathBuf, usize)>();
+        let filled = cache.clone();
+        thread::spawn(move || {
+            for (path, line_number) in rx {
+                let blame = git::blame(&path, line_number).ok();
+                filled
+                    .lock()
+                    .unwrap()
+                    .insert((path, line_number), Some(blame));
+            }
+        });
+        Blames { cache, requests }
+    }
+
+    /// Looks up who last changed `line_number` of `path`, unless that's
+    /// known or on its way.
+    pub fn request(&self, path: &Path, line_number: usize) {
+        let mut cache = self.cache.lock().unwrap();
+        let key = (path.to_path_buf(), line_number);
+        if !cache.contains_key(&key) {
+            cache.insert(key.clone(), None);
+            let _ = self.requests.send(key);
+        }
+    }
+
+    /// The blame of `line_number` of `path`: `None` until it's known, then
+    /// whatever git said.
+    pub fn get(&self, path: &Path, line_number: usize) -> Option<Option<Blame>> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(&(path.to_path_buf(), line_number))
+            .cloned()
+            .flatten()
+    }
+}
+
+/// The blame column as the results list shows it: the author and how long
+/// ago, e.g. `Ada Lovelace   3mo`, `uncommitted` for changes in the
+/// working tree and `-` where there's no telling.
+pub fn label(blame: Option<Option<Blame>>, width: usize) -> String {
+    let text = match blame {
+        None => "…".to_string(),
+        Some(None) => "-".to_string(),
+        Some(Some(blame)) if !blame.committed => "uncommitted".to_string(),
+        Some(Some(blame)) => {
+            let age = age(blame.time);
+            let room = width.saturating_sub(age.len() + 1);
+            let author: String = blame.author.chars().take(room).collect();
+            format!("{:<room$} {}", author, age)
+        }
+    };
+    format!("{:<width$}", text)
+}
+
+/// How long ago `time` was, in the largest unit that fits, e.g. `5d`.
+fn age(time: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs());
+    let seconds = now.saturating_sub(time);
+    let units = [
+        (365 * 24 * 3600, "y"),
+        (30 * 24 * 3600, "mo"),
+        (7 * 24 * 3600, "w"),
+        (24 * 3600, "d"),
+        (3600, "h"),
+        (60, "m"),
+    ];
+    units
+        .iter()
+        .find(|&&(unit, _)| seconds >= unit)
+        .map_or("now".to_string(), |&(unit, name)| {
+            format!("{}{}", seconds / unit, name)
+        })
+}
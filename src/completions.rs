@@ -0,0 +1,254 @@
+//! `rstr completions SHELL`: completion scripts for bash, zsh, fish and
+//! PowerShell, written from the CLI's own definition so they list every
+//! flag with its help. Values known when the script is made, such as the
+//! `--type` names and `--theme` names, the config file's included, are
+//! completed too.
+
+use clap::{Arg, Command, ValueEnum};
+use std::fmt::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+const SHELLS: &str = "bash zsh fish powershell";
+
+/// A flag as completions offer it.
+struct Flag {
+    short: Option<char>,
+    long: Option<String>,
+    /// The first line of its help.
+    help: String,
+    takes_value: bool,
+    /// What its value can be, when that's known; files otherwise.
+    values: Vec<String>,
+}
+
+/// The completion script for `shell` of `command`, whose flags named in
+/// `values` by their id take those values.
+pub fn script(shell: Shell, command: &Command, values: &[(&str, Vec<String>)]) -> String {
+    let mut command = command.clone();
+    // --help and --version are only there once built
+    command.build();
+    let name = command.get_name().to_string();
+    let flags: Vec<Flag> = command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+        .map(|arg| flag(arg, values))
+        .collect();
+    match shell {
+        Shell::Bash => bash(&name, &flags),
+        Shell::Zsh => zsh(&name, &flags),
+        Shell::Fish => fish(&name, &flags),
+        Shell::Powershell => powershell(&name, &flags),
+    }
+}
+
+fn flag(arg: &Arg, values: &[(&str, Vec<String>)]) -> Flag {
+    let mut known: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    if let Some((_, given)) = values.iter().find(|(id, _)| arg.get_id() == *id) {
+        known.extend(given.iter().cloned());
+    }
+    Flag {
+        short: arg.get_short(),
+        long: arg.get_long().map(str::to_string),
+        help: arg
+            .get_help()
+            .map(|help| help.to_string())
+            .unwrap_or_default()
+            .lines()
+            .next()
+            .unwrap_or("")
+            .to_string(),
+        takes_value: arg.get_action().takes_values(),
+        values: known,
+    }
+}
+
+impl Flag {
+    /// Its spellings, `-t` and `--type`.
+    fn names(&self) -> Vec<String> {
+        let short = self.short.map(|c| format!("-{}", c));
+        let long = self.long.as_ref().map(|long| format!("--{}", long));
+        short.into_iter().chain(long).collect()
+    }
+}
+
+fn bash(name: &str, flags: &[Flag]) -> String {
+    let function = format!("_{}", name.replace('-', "_"));
+    let all: Vec<String> = flags.iter().flat_map(Flag::names).collect();
+    let mut out = String::new();
+    let _ = writeln!(out, "{}() {{", function);
+    out.push_str(
+        "    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n",
+    );
+    let _ = writeln!(
+        out,
+        "    if [[ $COMP_CWORD -eq 2 && ${{COMP_WORDS[1]}} == completions ]]; then\n        \
+         COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n        return\n    fi",
+        SHELLS
+    );
+    out.push_str("    case \"$prev\" in\n");
+    for flag in flags.iter().filter(|flag| flag.takes_value) {
+        let _ = write!(out, "        {})", flag.names().join("|"));
+        if flag.values.is_empty() {
+            // nothing to offer but files, which -o default completes
+            out.push_str(" return ;;\n");
+        } else {
+            let _ = writeln!(
+                out,
+                " COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;",
+                flag.values.join(" ")
+            );
+        }
+    }
+    out.push_str("    esac\n");
+    let _ = writeln!(
+        out,
+        "    if [[ $cur == -* ]]; then\n        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n    fi",
+        all.join(" ")
+    );
+    out.push_str("}\n");
+    let _ = writeln!(out, "complete -o default -F {} {}", function, name);
+    out
+}
+
+fn zsh(name: &str, flags: &[Flag]) -> String {
+    // help goes inside '...' and [...]
+    let escape = |text: &str| {
+        text.replace('\\', "\\\\")
+            .replace('\'', "'\\''")
+            .replace('[', "\\[")
+            .replace(']', "\\]")
+            .replace(':', "\\:")
+    };
+    let mut out = format!("#compdef {}\n\n_{}() {{\n", name, name.replace('-', "_"));
+    let _ = writeln!(
+        out,
+        "    if (( CURRENT == 3 )) && [[ $words[2] == completions ]]; then\n        \
+         _values shell {}\n        return\n    fi",
+        SHELLS
+    );
+    out.push_str("    _arguments -s -S \\\n");
+    for flag in flags {
+        let help = escape(&flag.help);
+        let action = match (flag.takes_value, flag.values.is_empty()) {
+            (false, _) => String::new(),
+            (true, true) => ":value:_files".to_string(),
+            (true, false) => format!(":value:({})", flag.values.join(" ")),
+        };
+        if let Some(short) = flag.short {
+            let takes = if flag.takes_value { "+" } else { "" };
+            let _ = writeln!(out, "        '*-{}{}[{}]{}' \\", short, takes, help, action);
+        }
+        if let Some(long) = &flag.long {
+            let takes = if flag.takes_value { "=" } else { "" };
+            let _ = writeln!(out, "        '*--{}{}[{}]{}' \\", long, takes, help, action);
+        }
+    }
+    out.push_str("        '*::path or pattern:_files'\n}\n\n");
+    let _ = writeln!(out, "_{} \"$@\"", name.replace('-', "_"));
+    out
+}
+
+fn fish(name: &str, flags: &[Flag]) -> String {
+    let quote = |text: &str| format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"));
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "complete -c {} -n '__fish_seen_subcommand_from completions' -f -a {}",
+        name,
+        quote(SHELLS)
+    );
+    for flag in flags {
+        out.push_str(&format!("complete -c {}", name));
+        if let Some(short) = flag.short {
+            let _ = write!(out, " -s {}", short);
+        }
+        if let Some(long) = &flag.long {
+            let _ = write!(out, " -l {}", long);
+        }
+        match (flag.takes_value, flag.values.is_empty()) {
+            (false, _) => {}
+            (true, true) => out.push_str(" -r"),
+            (true, false) => {
+                let _ = write!(out, " -x -a {}", quote(&flag.values.join(" ")));
+            }
+        }
+        if !flag.help.is_empty() {
+            let _ = write!(out, " -d {}", quote(&flag.help));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn powershell(name: &str, flags: &[Flag]) -> String {
+    let quote = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "Register-ArgumentCompleter -Native -CommandName {} -ScriptBlock {{",
+        quote(name)
+    );
+    out.push_str("    param($wordToComplete, $commandAst, $cursorPosition)\n");
+    out.push_str("    $flags = @(\n");
+    for flag in flags {
+        for spelling in flag.names() {
+            // a completion's tooltip can't be empty
+            let help = if flag.help.is_empty() {
+                &spelling
+            } else {
+                &flag.help
+            };
+            let _ = writeln!(out, "        ,@({}, {})", quote(&spelling), quote(help));
+        }
+    }
+    // -t and -T are different flags, which PowerShell's @{} wouldn't tell apart
+    out.push_str("    )\n    $values = [Collections.Hashtable]::new([StringComparer]::Ordinal)\n");
+    let _ = writeln!(
+        out,
+        "    $values['completions'] = @({})",
+        SHELLS.split(' ').map(quote).collect::<Vec<_>>().join(", ")
+    );
+    for flag in flags.iter().filter(|flag| !flag.values.is_empty()) {
+        let values: Vec<String> = flag.values.iter().map(|value| quote(value)).collect();
+        for spelling in flag.names() {
+            let _ = writeln!(
+                out,
+                "    $values[{}] = @({})",
+                quote(&spelling),
+                values.join(", ")
+            );
+        }
+    }
+    out.push_str(
+        "    $before = @($commandAst.CommandElements |
+        Where-Object { $_.Extent.EndOffset -lt $cursorPosition } |
+        ForEach-Object { $_.ToString() })
+    $previous = if ($before.Count -gt 1) { $before[-1] } else { '' }
+    if ($values.ContainsKey($previous)) {
+        $values[$previous] | Where-Object { $_ -like \"$wordToComplete*\" } | ForEach-Object {
+            [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+        }
+        return
+    }
+    if ($wordToComplete -like '-*') {
+        $flags | Where-Object { $_[0] -clike \"$wordToComplete*\" } | ForEach-Object {
+            [System.Management.Automation.CompletionResult]::new($_[0], $_[0], 'ParameterName', $_[1])
+        }
+    }
+}
+",
+    );
+    out
+}
@@ -0,0 +1,83 @@
+//! Compressed files, searched as what they decompress to with
+//! `SearchOptions::compressed`. gzip is decoded in-process; xz, zstd and
+//! bzip2 go through the `xz`, `zstd` and `bzip2` tools when installed.
+
+use crate::inflate;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Recognizes compressed files by their extension: `.gz`, `.xz`,
+    /// `.zst` and `.bz2`.
+    pub fn from_path(path: &Path) -> Option<Compression> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gz" => Some(Compression::Gzip),
+            "xz" => Some(Compression::Xz),
+            "zst" => Some(Compression::Zstd),
+            "bz2" => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+
+    /// The tool that decompresses this format, `None` for those decoded
+    /// in-process.
+    fn tool(self) -> Option<&'static str> {
+        match self {
+            Compression::Gzip => None,
+            Compression::Xz => Some("xz"),
+            Compression::Zstd => Some("zstd"),
+            Compression::Bzip2 => Some("bzip2"),
+        }
+    }
+
+    /// Decompresses `bytes`, the contents of the file at `path`.
+    pub fn decompress(self, path: &Path, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        let Some(tool) = self.tool() else {
+            return inflate::gunzip(bytes);
+        };
+        let output = Command::new(tool)
+            .args(["-d", "-c", "--"])
+            .arg(path)
+            .stdin(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::NotFound => io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("decompressing needs {} installed", tool),
+                ),
+                _ => err,
+            })?;
+        if !output.status.success() {
+            // the tools' messages already name them and the file
+            let message = String::from_utf8_lossy(&output.stderr);
+            let message = message.lines().next().unwrap_or("").trim();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                match message {
+                    "" => format!("{} failed", tool),
+                    message => message.to_string(),
+                },
+            ));
+        }
+        Ok(output.stdout)
+    }
+}
+
+/// Reads and decompresses the file at `path`, or returns `None` if it
+/// isn't compressed.
+pub fn read(path: &Path) -> Option<io::Result<Vec<u8>>> {
+    let compression = Compression::from_path(path)?;
+    Some(fs::read(path).and_then(|bytes| compression.decompress(path, &bytes)))
+}
@@ -0,0 +1,229 @@
+//! `~/.config/rstr/config.toml`: defaults that sit underneath the command
+//! line. Top-level keys are long flag names (`ignore-case = true`,
+//! `threads = 4`, `glob = ["!*.min.js"]`); `ignore` holds extra gitignore
+//! rules, `[themes.NAME]` tables define colour themes and `[keys]` remaps
+//! the TUI's keys. An `ignore` file
+//! next to it holds gitignore rules too, for all searches.
+
+use crate::toml::{self, Table, Value};
+use clap::{ArgAction, Command};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Flags to parse before the real command line, so the latter wins.
+    pub args: Vec<String>,
+    /// Gitignore-syntax rules applied below every ignore file.
+    pub ignore: Vec<String>,
+    /// Custom themes by name, for [`crate::theme::Theme::resolve`].
+    pub themes: BTreeMap<String, Table>,
+    /// Keys for the TUI's commands, for [`crate::keys::Keymap::from_config`].
+    pub keys: Table,
+}
+
+/// `$RSTR_CONFIG`, or `config.toml` in the platform's config directory.
+fn path() -> Option<(PathBuf, bool)> {
+    if let Some(path) = env::var_os("RSTR_CONFIG") {
+        return (!path.is_empty()).then(|| (PathBuf::from(path), true));
+    }
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+    Some((dir.join("rstr").join("config.toml"), false))
+}
+
+/// Loads the config file and the ignore file next to it, if there are
+/// any. A missing default file is fine; a missing `$RSTR_CONFIG` is not.
+pub fn load(command: &Command) -> Result<Config, String> {
+    let Some((path, explicit)) = path() else {
+        return Ok(Config::default());
+    };
+    let mut config = match fs::read_to_string(&path) {
+        Ok(contents) => {
+            let table =
+                toml::parse(&contents).map_err(|err| format!("{}: {}", path.display(), err))?;
+            from_table(command, &table).map_err(|err| format!("{}: {}", path.display(), err))?
+        }
+        Err(_) if !explicit && !path.exists() => Config::default(),
+        Err(err) => return Err(format!("{}: {}", path.display(), err)),
+    };
+
+    // the config file's own `ignore` rules come later, so they win
+    let ignore_file = path.with_file_name("ignore");
+    match fs::read_to_string(&ignore_file) {
+        Ok(contents) => {
+            let mut rules: Vec<String> = contents.lines().map(str::to_string).collect();
+            rules.append(&mut config.ignore);
+            config.ignore = rules;
+        }
+        Err(_) if !ignore_file.exists() => {}
+        Err(err) => return Err(format!("{}: {}", ignore_file.display(), err)),
+    }
+    Ok(config)
+}
+
+fn from_table(command: &Command, table: &Table) -> Result<Config, String> {
+    let mut config = Config::default();
+    for (key, value) in table {
+        if key == "ignore" {
+            config.ignore = strings(key, value)?;
+            continue;
+        }
+        if key == "themes" {
+            let themes = match value {
+                Value::Table(themes) => themes,
+                _ => return Err("'themes' expects [themes.NAME] tables".to_string()),
+            };
+            for (name, theme) in themes {
+                let Value::Table(theme) = theme else {
+                    return Err(format!("'themes.{}' expects a table of styles", name));
+                };
+                config.themes.insert(name.clone(), theme.clone());
+            }
+            continue;
+        }
+        if key == "keys" {
+            let Value::Table(keys) = value else {
+                return Err("'keys' expects a [keys] table".to_string());
+            };
+            config.keys = keys.clone();
+            continue;
+        }
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key.as_str()))
+        else {
+            return Err(format!("unknown key '{}'", key));
+        };
+        let flag = format!("--{}", key);
+        match (arg.get_action(), value) {
+            (ArgAction::SetTrue, Value::Boolean(set)) => {
+                if *set {
+                    config.args.push(flag);
+                }
+            }
+            (ArgAction::SetTrue, _) => {
+                return Err(format!("'{}' expects true or false", key));
+            }
+            (ArgAction::Count, Value::Integer(count)) => {
+                for _ in 0..*count {
+                    config.args.push(flag.clone());
+                }
+            }
+            (ArgAction::Append, Value::Array(_)) => {
+                for item in strings(key, value)? {
+                    config.args.push(format!("{}={}", flag, item));
+                }
+            }
+            (ArgAction::Set | ArgAction::Append, Value::Array(_) | Value::Table(_)) => {
+                return Err(format!("'{}' expects a single value", key));
+            }
+            (ArgAction::Set | ArgAction::Append, value) => {
+                config.args.push(format!("{}={}", flag, value));
+            }
+            _ => return Err(format!("'{}' can't be set in the config file", key)),
+        }
+    }
+    Ok(config)
+}
+
+fn strings(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    let expected = || format!("'{}' expects a list of strings", key);
+    let Value::Array(items) = value else {
+        return Err(expected());
+    };
+    items
+        .iter()
+        .map(|item| item.as_str().map(str::to_string).ok_or_else(expected))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Arg;
+
+    /// A command with one flag of each kind the config file can set.
+    fn command() -> Command {
+        Command::new("rstr")
+            .arg(Arg::new("hidden").long("hidden").action(ArgAction::SetTrue))
+            .arg(
+                Arg::new("unrestricted")
+                    .long("unrestricted")
+                    .action(ArgAction::Count),
+            )
+            .arg(Arg::new("glob").long("glob").action(ArgAction::Append))
+            .arg(Arg::new("threads").long("threads").action(ArgAction::Set))
+            .arg(Arg::new("help").long("help").action(ArgAction::Help))
+    }
+
+    fn parsed(contents: &str) -> Result<Config, String> {
+        from_table(&command(), &toml::parse(contents).unwrap())
+    }
+
+    #[test]
+    fn keys_become_flags() {
+        let config = parsed(
+            "hidden = true\nunrestricted = 2\nglob = [\"*.rs\", \"!target\"]\nthreads = 4\nignore = [\"*.log\"]",
+        )
+        .unwrap();
+        assert_eq!(
+            config.args,
+            [
+                "--glob=*.rs",
+                "--glob=!target",
+                "--hidden",
+                "--threads=4",
+                "--unrestricted",
+                "--unrestricted",
+            ]
+        );
+        assert_eq!(config.ignore, ["*.log"]);
+        assert!(parsed("hidden = false").unwrap().args.is_empty());
+    }
+
+    #[test]
+    fn rejects_keys_and_values_that_fit_no_flag() {
+        let cases = [
+            ("colour = true", "unknown key 'colour'"),
+            ("hidden = 1", "'hidden' expects true or false"),
+            ("threads = [1, 2]", "'threads' expects a single value"),
+            ("glob = [1]", "'glob' expects a list of strings"),
+            ("ignore = \"*.log\"", "'ignore' expects a list of strings"),
+            ("help = true", "'help' can't be set in the config file"),
+        ];
+        for (contents, error) in cases {
+            assert_eq!(parsed(contents).unwrap_err(), error, "{:?}", contents);
+        }
+    }
+
+    #[test]
+    fn themes_are_kept_by_name() {
+        let config = parsed("[themes.dark]\nmatch = \"red\"\n[themes.light]\n").unwrap();
+        assert_eq!(config.themes.keys().collect::<Vec<_>>(), ["dark", "light"]);
+        assert_eq!(config.themes["dark"]["match"].as_str(), Some("red"));
+        assert_eq!(
+            parsed("themes = 1").unwrap_err(),
+            "'themes' expects [themes.NAME] tables"
+        );
+        assert_eq!(
+            parsed("themes.dark = 1").unwrap_err(),
+            "'themes.dark' expects a table of styles"
+        );
+    }
+
+    #[test]
+    fn keys_are_kept_for_the_keymap() {
+        let config = parsed("[keys]\nquit = \"x\"").unwrap();
+        assert_eq!(config.keys["quit"].as_str(), Some("x"));
+        assert_eq!(
+            parsed("keys = 1").unwrap_err(),
+            "'keys' expects a [keys] table"
+        );
+    }
+}
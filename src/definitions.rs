@@ -0,0 +1,139 @@
+//! What function, type or class a match is in, for
+//! `SearchOptions::definitions`. It's a heuristic: the nearest line above
+//! the match that looks like a definition in the file's language, going by
+//! a pattern per file type; nesting and where definitions end are ignored.
+
+use crate::types::{TypeFilter, TypeRegistry};
+use regex::Regex;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The file types with a known look for definitions. The first group that
+/// takes part in a match is what's shown, e.g. `fn parse` or `class Lexer`.
+const PATTERNS: &[(&str, &str)] = &[
+    (
+        "rust",
+        r"^\s*(?:pub(?:\([^)]*\))?\s+)?(?:(?:async|const|unsafe|default|extern\s+\S+)\s+)*((?:fn|struct|enum|trait|union|mod|macro_rules!)\s*\w+|impl\b[^{;]*)",
+    ),
+    ("py", r"^\s*((?:async\s+)?def\s+\w+|class\s+\w+)"),
+    ("go", r"^(func\s+(?:\([^)]*\)\s*)?\w+|type\s+\w+)"),
+    (
+        "js",
+        r"^\s*(?:export\s+)?(?:default\s+)?(?:((?:async\s+)?function\*?\s*\w+|class\s+\w+)|(?:const|let|var)\s+(\w+)\s*=\s*(?:async\s*)?(?:\([^)]*\)|\w+)\s*=>)",
+    ),
+    (
+        "ts",
+        r"^\s*(?:export\s+)?(?:default\s+)?(?:declare\s+)?(?:abstract\s+)?(?:((?:async\s+)?function\*?\s*\w+|class\s+\w+|interface\s+\w+|enum\s+\w+)|(?:const|let|var)\s+(\w+)\s*(?::[^=]*)?=\s*(?:async\s*)?(?:\([^)]*\)|\w+)\s*=>)",
+    ),
+    (
+        "c",
+        r"^(?:((?:struct|enum|union)\s+\w+)\s*\{|(?:[A-Za-z_][\w\s\*]*[\s\*])?([A-Za-z_]\w*)\s*\([^;]*$)",
+    ),
+    (
+        "cpp",
+        r"^\s*(?:template\s*<[^>]*>\s*)?(?:((?:class|struct|enum(?:\s+class)?|union|namespace)\s+\w+)\s*[:{]?[^;]*$|(?:[A-Za-z_][\w\s\*&:<>,]*[\s\*&])?([A-Za-z_][\w:~]*)\s*\([^;]*$)",
+    ),
+    (
+        "java",
+        r"^\s*(?:@\w+\s+)*(?:(?:public|private|protected|static|final|abstract|sealed|synchronized|native|default)\s+)*(?:((?:class|interface|enum|record)\s+\w+)|[\w<>\[\],.?]+(?:\s+[\w<>\[\],.?]+)*\s+(\w+)\s*\([^;]*$)",
+    ),
+    (
+        "cs",
+        r"^\s*(?:\[[^\]]*\]\s*)*(?:(?:public|private|protected|internal|static|sealed|abstract|partial|virtual|override|async|readonly|unsafe|extern)\s+)*(?:((?:class|interface|enum|record|struct|namespace)\s+\w+)|[\w<>\[\],.?]+(?:\s+[\w<>\[\],.?]+)*\s+(\w+)\s*\([^;]*$)",
+    ),
+    (
+        "kotlin",
+        r"^\s*(?:(?:public|private|protected|internal|open|abstract|sealed|data|inline|override|suspend|enum|annotation)\s+)*((?:class|interface|object|fun)\s+[\w.<>]+)",
+    ),
+    (
+        "swift",
+        r"^\s*(?:@\w+\s+)*(?:(?:public|private|internal|fileprivate|open|static|final|override|mutating|class)\s+)*((?:func|class|struct|enum|protocol|extension|actor)\s+\w+)",
+    ),
+    (
+        "php",
+        r"^\s*(?:(?:public|private|protected|static|abstract|final)\s+)*((?:function|class|interface|trait|enum)\s+\w+)",
+    ),
+    ("ruby", r"^\s*((?:def|class|module)\s+[\w.:?!=]+)"),
+    ("lua", r"^\s*(?:local\s+)?(function\s+[\w.:]+)"),
+    ("sh", r"^\s*(?:function\s+(\w+)|(\w+)\s*\(\)\s*\{?)"),
+];
+
+/// Words the looser patterns take for a name, where a statement like
+/// `if (...)` looks like a call.
+const KEYWORDS: &[&str] = &[
+    "if", "else", "for", "while", "do", "switch", "case", "catch", "return", "new", "sizeof",
+];
+
+/// What a match is in: the line that defines it and the part of that line
+/// naming what's defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub line_number: usize,
+    pub name: String,
+}
+
+/// The definition pattern of the file type `path` is, if it has one.
+fn pattern_for(path: &Path) -> Option<&'static Regex> {
+    static LANGUAGES: OnceLock<Vec<(TypeFilter, Regex)>> = OnceLock::new();
+    let languages = LANGUAGES.get_or_init(|| {
+        let types = TypeRegistry::new();
+        PATTERNS
+            .iter()
+            .map(|&(name, pattern)| {
+                let filter = types.select(&[name.to_string()]).unwrap();
+                (filter, Regex::new(pattern).unwrap())
+            })
+            .collect()
+    });
+    languages
+        .iter()
+        .find(|(filter, _)| filter.matches(path))
+        .map(|(_, regex)| regex)
+}
+
+/// Tracks the nearest definition while moving down a file, so finding it
+/// for every match in turn reads the file only once.
+pub struct Scanner<'a> {
+    pattern: &'static Regex,
+    bytes: &'a [u8],
+    /// Start and 1-based number of the next line to look at.
+    pos: usize,
+    line_number: usize,
+    last: Option<Definition>,
+}
+
+impl<'a> Scanner<'a> {
+    /// A scanner for `bytes`, the contents of `path`, or `None` when its
+    /// file type has no definition pattern.
+    pub fn new(path: &Path, bytes: &'a [u8]) -> Option<Scanner<'a>> {
+        Some(Scanner {
+            pattern: pattern_for(path)?,
+            bytes,
+            pos: 0,
+            line_number: 1,
+            last: None,
+        })
+    }
+
+    /// The nearest definition above line `line_number`. Lines must be asked
+    /// for in increasing order.
+    pub fn before(&mut self, line_number: usize) -> Option<Definition> {
+        while self.line_number < line_number && self.pos < self.bytes.len() {
+            let rest = &self.bytes[self.pos..];
+            let end = memchr::memchr(b'\n', rest).unwrap_or(rest.len());
+            if let Ok(line) = std::str::from_utf8(&rest[..end])
+                && let Some(captures) = self.pattern.captures(line)
+                && let Some(name) = captures.iter().skip(1).flatten().next()
+                && !KEYWORDS.contains(&name.as_str())
+            {
+                self.last = Some(Definition {
+                    line_number: self.line_number,
+                    name: name.as_str().trim().to_string(),
+                });
+            }
+            self.pos += end + 1;
+            self.line_number += 1;
+        }
+        self.last.clone()
+    }
+}
@@ -0,0 +1,84 @@
+//! `--baseline`: the matches a search has that a saved session didn't, and
+//! those it no longer has. Matches are told apart by their file and line
+//! text, so lines that only moved aren't changes.
+
+use crate::Format;
+use crate::json;
+use crate::output::{Layout, clip};
+use rstr::{Match, SearchEvent};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+
+/// Prints how the matches among `events` differ from `baseline`, file by
+/// file in path order: `-path:line:text` for those gone and
+/// `+path:line:text` for the new ones, each at its line in its own search.
+/// Returns whether anything changed.
+pub fn print(
+    out: &mut dyn Write,
+    events: Receiver<SearchEvent>,
+    baseline: &[Match],
+    format: Format,
+    layout: Layout,
+) -> io::Result<bool> {
+    let results: Vec<Match> = events
+        .into_iter()
+        .take_while(|event| !matches!(event, SearchEvent::Done))
+        .filter_map(|event| match event {
+            SearchEvent::Match(result) => Some(result),
+            _ => None,
+        })
+        .collect();
+
+    let mut changes: Vec<(bool, &Match)> = missing(baseline, &results)
+        .map(|result| (false, result))
+        .chain(missing(&results, baseline).map(|result| (true, result)))
+        .collect();
+    changes.sort_by(|(a_added, a), (b_added, b)| {
+        (&a.path, a.line_number, a_added).cmp(&(&b.path, b.line_number, b_added))
+    });
+
+    for &(added, result) in &changes {
+        let path = layout.paths.show(&result.path);
+        match format {
+            Format::Json => writeln!(
+                out,
+                r#"{{"change":{},"path":{},"line":{},"text":{}}}"#,
+                json::string(if added { "added" } else { "removed" }),
+                json::string(&path.to_string_lossy()),
+                result.line_number,
+                json::string(&result.line)
+            )?,
+            _ => {
+                write!(out, "{}{}:", if added { '+' } else { '-' }, path.display())?;
+                if layout.line_numbers {
+                    write!(out, "{}:", result.line_number)?;
+                }
+                writeln!(out, "{}", clip(&result.line, layout.max_columns))?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(!changes.is_empty())
+}
+
+/// The matches of `results` that `other` has no counterpart for; a line
+/// `other` has once and `results` twice is missing once.
+fn missing<'a>(results: &'a [Match], other: &[Match]) -> impl Iterator<Item = &'a Match> {
+    let mut left: HashMap<(PathBuf, String), usize> = HashMap::new();
+    for result in other {
+        *left
+            .entry((result.path.clone(), result.line.clone()))
+            .or_default() += 1;
+    }
+    results.iter().filter(move |result| {
+        match left.get_mut(&(result.path.clone(), result.line.clone())) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        }
+    })
+}
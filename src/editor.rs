@@ -0,0 +1,66 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Resolves the editor command: the `--editor` override, then `$VISUAL`,
+/// then `$EDITOR`, falling back to `vi`.
+pub fn resolve(editor: Option<&str>) -> String {
+    editor
+        .map(str::to_string)
+        .or_else(|| std::env::var("VISUAL").ok())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Opens `path` at `line` and `column` and waits for the editor to exit.
+///
+/// `editor` may carry its own arguments (`code -w`). How the position is
+/// passed depends on the editor: most understand `+LINE file`, a few want
+/// `file:LINE:COLUMN`. Editors not known to take a column get the line only.
+pub fn open(editor: &str, path: &Path, line: usize, column: usize) -> io::Result<()> {
+    let mut parts = editor.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty editor command"))?;
+    let mut command = Command::new(program);
+    command.args(parts);
+
+    let name = Path::new(program)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match name.as_str() {
+        "code" | "code-insiders" | "codium" => {
+            command
+                .arg("--goto")
+                .arg(format!("{}:{}:{}", path.display(), line, column));
+        }
+        "subl" | "zed" | "hx" | "helix" => {
+            command.arg(format!("{}:{}:{}", path.display(), line, column));
+        }
+        "vim" | "nvim" | "gvim" | "mvim" => {
+            command
+                .arg(format!("+call cursor({}, {})", line, column))
+                .arg(path);
+        }
+        "nano" => {
+            command.arg(format!("+{},{}", line, column)).arg(path);
+        }
+        "emacs" | "emacsclient" => {
+            command.arg(format!("+{}:{}", line, column)).arg(path);
+        }
+        _ => {
+            command.arg(format!("+{}", line)).arg(path);
+        }
+    }
+
+    let status = command.status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "{} exited with {}",
+            program, status
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+//! Text encodings other than UTF-8: detecting them from a byte-order mark
+//! or the shape of the text, and transcoding to UTF-8 for matching.
+
+use std::borrow::Cow;
+
+/// How much of a file [`Encoding::detect`] looks at.
+const SAMPLE_LEN: usize = 8 * 1024;
+
+/// Characters for the bytes `0x80..=0x9F` in Windows-1252, which is what
+/// files labelled Latin-1 almost always are. The rest map to themselves.
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1 as extended by Windows-1252.
+    Latin1,
+}
+
+impl Encoding {
+    /// Looks up an encoding by one of its usual names, e.g. `utf-16le`,
+    /// `UTF16LE`, `latin1` or `windows-1252`.
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        let name: String = name
+            .chars()
+            .filter(|c| !matches!(c, '-' | '_'))
+            .collect::<String>()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "utf8" => Some(Encoding::Utf8),
+            "utf16le" => Some(Encoding::Utf16Le),
+            "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "iso88591" | "windows1252" | "cp1252" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+        }
+    }
+
+    /// Guesses the encoding from the start of `bytes`: a byte-order mark
+    /// decides, otherwise text where every other byte is zero is UTF-16 and
+    /// text that isn't UTF-8 is Latin-1. Binary data (anything else with
+    /// zero bytes) is left to be treated as UTF-8.
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        if bytes.starts_with(b"\xEF\xBB\xBF") {
+            return Encoding::Utf8;
+        }
+        if bytes.starts_with(b"\xFF\xFE") {
+            return Encoding::Utf16Le;
+        }
+        if bytes.starts_with(b"\xFE\xFF") {
+            return Encoding::Utf16Be;
+        }
+
+        let sample = &bytes[..bytes.len().min(SAMPLE_LEN)];
+        let pairs = sample.len() / 2;
+        if pairs > 0 {
+            let zeros_at = |parity: usize| {
+                sample
+                    .iter()
+                    .skip(parity)
+                    .step_by(2)
+                    .take(pairs)
+                    .filter(|&&b| b == 0)
+                    .count()
+            };
+            let (even, odd) = (zeros_at(0), zeros_at(1));
+            // mostly ASCII text then has a zero high byte in most pairs
+            if odd * 10 >= pairs * 4 && even * 20 < pairs {
+                return Encoding::Utf16Le;
+            }
+            if even * 10 >= pairs * 4 && odd * 20 < pairs {
+                return Encoding::Utf16Be;
+            }
+        }
+
+        match std::str::from_utf8(sample) {
+            Ok(_) => Encoding::Utf8,
+            // the sample may end in the middle of a character
+            Err(err) if err.error_len().is_none() => Encoding::Utf8,
+            Err(_) if memchr::memchr(0, sample).is_some() => Encoding::Utf8,
+            Err(_) => Encoding::Latin1,
+        }
+    }
+}
+
+/// Transcodes `bytes` to UTF-8 from `encoding`, or from the one
+/// [`Encoding::detect`] guesses if it's `None`, dropping any byte-order
+/// mark. UTF-8 input is borrowed rather than copied, and isn't validated.
+pub fn decode(bytes: &[u8], encoding: Option<Encoding>) -> Cow<'_, [u8]> {
+    match encoding.unwrap_or_else(|| Encoding::detect(bytes)) {
+        Encoding::Utf8 => Cow::Borrowed(bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes)),
+        Encoding::Utf16Le => {
+            let bytes = bytes.strip_prefix(b"\xFF\xFE").unwrap_or(bytes);
+            decode_utf16(bytes, u16::from_le_bytes)
+        }
+        Encoding::Utf16Be => {
+            let bytes = bytes.strip_prefix(b"\xFE\xFF").unwrap_or(bytes);
+            decode_utf16(bytes, u16::from_be_bytes)
+        }
+        Encoding::Latin1 => {
+            let text: String = bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9F => WINDOWS_1252[(b - 0x80) as usize],
+                    _ => b as char,
+                })
+                .collect();
+            Cow::Owned(text.into_bytes())
+        }
+    }
+}
+
+/// Unpaired surrogates and a dangling odd byte become U+FFFD.
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Cow<'_, [u8]> {
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    let mut text: String = char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    if bytes.len() % 2 == 1 {
+        text.push(char::REPLACEMENT_CHARACTER);
+    }
+    Cow::Owned(text.into_bytes())
+}
@@ -0,0 +1,150 @@
+//! The TUI's event loop: terminal input is read on its own thread and the
+//! running search's events are forwarded from theirs, all into one channel
+//! the loop blocks on. Keys are handled as soon as they arrive, however
+//! busy the search keeps the loop.
+
+use crossterm::event::{self, Event};
+use rstr::SearchEvent;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+/// How long the input thread waits for a key before checking whether it
+/// should pause or stop.
+const INPUT_POLL: Duration = Duration::from_millis(20);
+
+pub enum UiEvent {
+    Terminal(Event),
+    /// Reading the terminal failed, and the input thread has stopped.
+    InputError(io::Error),
+    /// An event of the search started as this generation by
+    /// [`EventLoop::follow`].
+    Search(u64, SearchEvent),
+    /// That search has stopped sending events, even under `--watch`.
+    SearchEnded(u64),
+    /// How many files that search will look at.
+    FileCount(u64, usize),
+}
+
+pub struct EventLoop {
+    tx: Sender<UiEvent>,
+    rx: Receiver<UiEvent>,
+    /// The search being followed; forwarders of older ones stop.
+    generation: Arc<AtomicU64>,
+    input: Arc<InputState>,
+}
+
+#[derive(Default)]
+struct InputState {
+    paused: AtomicBool,
+    stopped: AtomicBool,
+    /// Held by the input thread while it polls and reads, so pausing can
+    /// wait for a read in progress to finish.
+    reading: Mutex<()>,
+}
+
+/// Keeps the input thread off the terminal, e.g. while an editor runs,
+/// until dropped.
+pub struct InputPause<'a> {
+    input: &'a InputState,
+    _reading: MutexGuard<'a, ()>,
+}
+
+impl Drop for InputPause<'_> {
+    fn drop(&mut self) {
+        self.input.paused.store(false, Ordering::Relaxed);
+    }
+}
+
+impl EventLoop {
+    /// Starts reading terminal input.
+    pub fn new() -> EventLoop {
+        let (tx, rx) = mpsc::channel();
+        let input = Arc::new(InputState::default());
+        let state = input.clone();
+        let input_tx = tx.clone();
+        thread::spawn(move || {
+            while !state.stopped.load(Ordering::Relaxed) {
+                if state.paused.load(Ordering::Relaxed) {
+                    thread::sleep(INPUT_POLL);
+                    continue;
+                }
+                let _reading = state.reading.lock().unwrap();
+                let event = match event::poll(INPUT_POLL) {
+                    Ok(false) => continue,
+                    Ok(true) => event::read().map(UiEvent::Terminal),
+                    Err(err) => Err(err),
+                };
+                let failed = event.is_err();
+                let event = event.unwrap_or_else(UiEvent::InputError);
+                if input_tx.send(event).is_err() || failed {
+                    return;
+                }
+            }
+        });
+        EventLoop {
+            tx,
+            rx,
+            generation: Arc::new(AtomicU64::new(0)),
+            input,
+        }
+    }
+
+    /// Forwards the events and file count of a new search, which replaces
+    /// the one followed so far. Returns the generation its events come
+    /// with; once it's superseded its forwarders stop and drop its
+    /// receivers, which stops the search itself.
+    pub fn follow(&self, events: Receiver<SearchEvent>, file_count: Receiver<usize>) -> u64 {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let current = self.generation.clone();
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            for event in events {
+                if current.load(Ordering::Relaxed) != generation
+                    || tx.send(UiEvent::Search(generation, event)).is_err()
+                {
+                    return;
+                }
+            }
+            let _ = tx.send(UiEvent::SearchEnded(generation));
+        });
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            if let Ok(count) = file_count.recv() {
+                let _ = tx.send(UiEvent::FileCount(generation, count));
+            }
+        });
+        generation
+    }
+
+    /// Waits up to `timeout` for the next event.
+    pub fn next(&self, timeout: Duration) -> Option<UiEvent> {
+        self.rx.recv_timeout(timeout).ok()
+    }
+
+    /// The next event if one is waiting.
+    pub fn try_next(&self) -> Option<UiEvent> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Stops reading terminal input until the returned guard is dropped.
+    pub fn pause_input(&self) -> InputPause<'_> {
+        self.input.paused.store(true, Ordering::Relaxed);
+        InputPause {
+            input: &self.input,
+            _reading: self.input.reading.lock().unwrap(),
+        }
+    }
+}
+
+impl Drop for EventLoop {
+    /// Stops the input thread, waiting for a read in progress so no key
+    /// meant for the shell is taken after the TUI has gone.
+    fn drop(&mut self) {
+        self.input.stopped.store(true, Ordering::Relaxed);
+        let _reading = self.input.reading.lock();
+    }
+}
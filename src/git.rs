@@ -0,0 +1,159 @@
+//! What git knows about the files searched: which it tracks or has seen
+//! change since a commit, for `WalkOptions::only`, who last changed a line,
+//! and the ignore rules it keeps outside the tree. Asks the `git` command.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitFiles {
+    /// The files in git's index.
+    Tracked,
+    /// The files that differ from this revision, staged or not, along with
+    /// the untracked ones that aren't ignored.
+    Modified(String),
+}
+
+/// The files below `root` that `which` selects, as the walk finds them:
+/// `root` joined with their path below it. Files that no longer exist are
+/// left out.
+pub fn files(root: &Path, which: &GitFiles) -> Result<HashSet<PathBuf>, String> {
+    let mut lists = Vec::new();
+    match which {
+        GitFiles::Tracked => lists.push(git(root, &["ls-files", "-z"])?),
+        GitFiles::Modified(revision) => {
+            lists.push(git(
+                root,
+                &[
+                    "diff",
+                    "--name-only",
+                    "--relative",
+                    "--diff-filter=d",
+                    "-z",
+                    // so a revision like `--output=FILE` isn't taken for an option
+                    "--end-of-options",
+                    revision,
+                    "--",
+                ],
+            )?);
+            lists.push(git(
+                root,
+                &["ls-files", "--others", "--exclude-standard", "-z"],
+            )?);
+        }
+    }
+    Ok(lists
+        .iter()
+        .flat_map(|list| list.split('\0'))
+        .filter(|name| !name.is_empty())
+        .map(|name| root.join(name))
+        .collect())
+}
+
+/// Who last changed a line, and when.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blame {
+    pub author: String,
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    /// Whether the change is in a commit yet, rather than only in the
+    /// working tree.
+    pub committed: bool,
+}
+
+/// Who last changed line `line_number` of the file at `path`, going by
+/// `git blame`.
+pub fn blame(path: &Path, line_number: usize) -> Result<Blame, String> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().ok_or("not a file")?.to_string_lossy();
+    let range = format!("{},{}", line_number, line_number);
+    let output = git(dir, &["blame", "--porcelain", "-L", &range, "--", &name])?;
+    let mut lines = output.lines();
+    // the commit is all zeros for changes that aren't committed
+    let commit = lines.next().and_then(|line| line.split(' ').next());
+    let mut blame = Blame {
+        author: String::new(),
+        time: 0,
+        committed: commit.is_some_and(|commit| commit.bytes().any(|b| b != b'0')),
+    };
+    for line in lines {
+        if let Some(author) = line.strip_prefix("author ") {
+            blame.author = author.to_string();
+        } else if let Some(time) = line.strip_prefix("author-time ") {
+            blame.time = time.parse().unwrap_or(0);
+        } else if line.starts_with('\t') {
+            break;
+        }
+    }
+    Ok(blame)
+}
+
+/// The files of exclude rules that apply to the repository whose work tree
+/// is `dir`, lowest precedence first: `core.excludesFile` and then
+/// `.git/info/exclude`. Empty when `dir` isn't a work tree.
+pub fn exclude_files(dir: &Path) -> Vec<PathBuf> {
+    let Some(git_dir) = git_dir(dir) else {
+        return Vec::new();
+    };
+    let global = match git(dir, &["config", "--path", "--get", "core.excludesFile"]) {
+        Ok(file) if !file.trim().is_empty() => Some(PathBuf::from(file.trim())),
+        // unset, or git isn't there to ask: where git looks by default
+        _ => match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(config) => Some(PathBuf::from(config).join("git/ignore")),
+            None => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/git/ignore")),
+        },
+    };
+    // linked work trees share the main one's info directory
+    let common = match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common) => git_dir.join(common.trim()),
+        Err(_) => git_dir,
+    };
+    global
+        .into_iter()
+        .chain([common.join("info/exclude")])
+        .collect()
+}
+
+/// The git directory of the work tree `dir`: its `.git`, or where the
+/// `.git` file of a submodule or linked work tree points.
+fn git_dir(dir: &Path) -> Option<PathBuf> {
+    let dot_git = dir.join(".git");
+    let metadata = fs::metadata(&dot_git).ok()?;
+    if metadata.is_dir() {
+        return Some(dot_git);
+    }
+    let contents = fs::read_to_string(&dot_git).ok()?;
+    let target = contents.trim().strip_prefix("gitdir:")?.trim();
+    Some(dir.join(target))
+}
+
+/// Runs git in `dir` with `args`, returning what it printed.
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => "this needs git installed".to_string(),
+            _ => format!("running git failed: {}", err),
+        })?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        let message = message.lines().next().unwrap_or("").trim();
+        return Err(match message {
+            "" => "git failed".to_string(),
+            message => message.trim_start_matches("fatal: ").to_string(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
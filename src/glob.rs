@@ -0,0 +1,156 @@
+use regex::Regex;
+
+/// Translates a gitignore-style glob into an anchored regex.
+///
+/// `*` and `?` never cross a `/`, `**` spans directories, `[...]` classes
+/// may be negated with `!` or `^` and `{a,b}` matches either alternative.
+pub fn to_regex(glob: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^{}$", translate(glob)))
+}
+
+fn translate(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut out = String::new();
+    let mut braces = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_start = i == 0 || chars[i - 1] == '/';
+                let at_end = i + 2 == chars.len();
+                if at_start && chars.get(i + 2) == Some(&'/') {
+                    // `**/` matches zero or more leading directories
+                    out.push_str("(?:.*/)?");
+                    i += 3;
+                    continue;
+                }
+                if at_start && at_end {
+                    out.push_str(".*");
+                } else {
+                    out.push_str("[^/]*");
+                }
+                i += 2;
+                continue;
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            '[' => match class_end(&chars, i) {
+                Some(end) => {
+                    out.push('[');
+                    let mut j = i + 1;
+                    if matches!(chars[j], '!' | '^') {
+                        out.push('^');
+                        j += 1;
+                    }
+                    while j < end {
+                        let c = chars[j];
+                        if c == '\\' || c == '[' || c == '&' || c == '~' {
+                            out.push('\\');
+                        }
+                        out.push(c);
+                        j += 1;
+                    }
+                    out.push(']');
+                    i = end + 1;
+                    continue;
+                }
+                None => out.push_str(r"\["),
+            },
+            '{' => {
+                braces += 1;
+                out.push_str("(?:");
+            }
+            '}' if braces > 0 => {
+                braces -= 1;
+                out.push(')');
+            }
+            ',' if braces > 0 => out.push('|'),
+            '\\' if i + 1 < chars.len() => {
+                out.push_str(&regex::escape(&chars[i + 1].to_string()));
+                i += 2;
+                continue;
+            }
+            c => out.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    // an unclosed `{` would leave an unbalanced group behind
+    for _ in 0..braces {
+        out.push(')');
+    }
+
+    out
+}
+
+fn class_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut j = start + 1;
+    if matches!(chars.get(j), Some('!' | '^')) {
+        j += 1;
+    }
+    // a `]` right after the opening bracket is a literal member
+    if chars.get(j) == Some(&']') {
+        j += 1;
+    }
+    while j < chars.len() {
+        if chars[j] == ']' {
+            return Some(j);
+        }
+        j += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        to_regex(glob).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn stars_stay_within_a_directory() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(matches("?.rs", "a.rs"));
+        assert!(!matches("?.rs", "ab.rs"));
+        assert!(!matches("a?b", "a/b"));
+    }
+
+    #[test]
+    fn double_stars_span_directories() {
+        assert!(matches("**/*.rs", "main.rs"));
+        assert!(matches("**/*.rs", "src/deep/main.rs"));
+        assert!(matches("src/**", "src/deep/main.rs"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("a/**/b", "a/x/y/b"));
+        assert!(!matches("a/**/b", "a/xb"));
+        // not a whole component, so just a star
+        assert!(!matches("a**.rs", "a/b.rs"));
+    }
+
+    #[test]
+    fn classes_and_alternatives() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(!matches("[!abc].txt", "b.txt"));
+        assert!(matches("[^abc].txt", "d.txt"));
+        assert!(matches("[]x].txt", "].txt"));
+        assert!(matches("[a-c]", "b"));
+        assert!(matches("[", "["));
+        assert!(matches("*.{rs,toml}", "Cargo.toml"));
+        assert!(!matches("*.{rs,toml}", "Cargo.lock"));
+        assert!(matches("{a,b", "b"));
+    }
+
+    #[test]
+    fn everything_else_is_literal() {
+        assert!(matches("a.b", "a.b"));
+        assert!(!matches("a.b", "axb"));
+        assert!(matches(r"\*", "*"));
+        assert!(!matches(r"\*", "a"));
+        assert!(matches("(a)+", "(a)+"));
+        assert!(!matches("a", "ab"));
+    }
+}
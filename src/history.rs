@@ -0,0 +1,49 @@
+//! Recently searched patterns, kept in `~/.local/share/rstr/history`, one
+//! per line with the newest last.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Older patterns are dropped beyond this many.
+const MAX_ENTRIES: usize = 500;
+
+fn path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(dir.join("rstr").join("history"))
+}
+
+/// The stored patterns, oldest first. A missing or unreadable file is an
+/// empty history.
+pub fn load() -> Vec<String> {
+    path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|contents| contents.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Records `pattern` as the newest entry, moving it there if it was already
+/// known. History is a convenience, so failing to save it is ignored.
+pub fn add(pattern: &str) {
+    if pattern.is_empty() || pattern.contains('\n') {
+        return;
+    }
+    let Some(path) = path() else {
+        return;
+    };
+    let mut entries = load();
+    entries.retain(|entry| entry != pattern);
+    entries.push(pattern.to_string());
+    let skip = entries.len().saturating_sub(MAX_ENTRIES);
+
+    let mut contents = entries[skip..].join("\n");
+    contents.push('\n');
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(path, contents);
+}
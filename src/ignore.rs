@@ -0,0 +1,532 @@
+use crate::{git, glob};
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Ignore files consulted in every directory, highest precedence first.
+pub const IGNORE_FILES: &[&str] = &[".rstrignore", ".ignore", ".gitignore"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    None,
+    Ignore,
+    Whitelist,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// The rules of a single gitignore-syntax file, relative to the directory
+/// that contains it.
+#[derive(Debug, Clone, Default)]
+pub struct Gitignore {
+    root: PathBuf,
+    /// Where `root` is below the directory the rules are relative to, for
+    /// those of a repository whose work tree starts above the search root.
+    within: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl Gitignore {
+    pub fn from_file(root: &Path, file: &Path) -> Option<Gitignore> {
+        let contents = fs::read_to_string(file).ok()?;
+        Some(Gitignore::parse(root, &contents))
+    }
+
+    /// Parses gitignore syntax, silently dropping lines whose glob is invalid
+    /// the way git does.
+    pub fn parse(root: &Path, contents: &str) -> Gitignore {
+        let rules = contents
+            .lines()
+            .filter_map(|line| parse_rule(line).ok().flatten())
+            .collect();
+        Gitignore {
+            root: root.to_path_buf(),
+            within: PathBuf::new(),
+            rules,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The same rules, relative to `root` instead.
+    pub fn rooted_at(&self, root: &Path) -> Gitignore {
+        Gitignore {
+            root: root.to_path_buf(),
+            ..self.clone()
+        }
+    }
+
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Verdict {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return Verdict::None;
+        };
+        let relative = self
+            .within
+            .join(relative)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        // the last matching rule wins
+        for rule in self.rules.iter().rev() {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            if rule.regex.is_match(&relative) {
+                return if rule.negated {
+                    Verdict::Whitelist
+                } else {
+                    Verdict::Ignore
+                };
+            }
+        }
+        Verdict::None
+    }
+}
+
+fn parse_rule(line: &str) -> Result<Option<Rule>, regex::Error> {
+    let mut line = line.trim_end_matches(['\r', '\n']);
+    // trailing spaces are dropped unless escaped with a backslash
+    while line.ends_with(' ') && !line.ends_with("\\ ") {
+        line = &line[..line.len() - 1];
+    }
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let mut pattern = line;
+    let negated = pattern.starts_with('!');
+    if negated {
+        pattern = &pattern[1..];
+    }
+    if pattern.starts_with("\\!") || pattern.starts_with("\\#") {
+        pattern = &pattern[1..];
+    }
+
+    let dir_only = pattern.ends_with('/');
+    let pattern = pattern.trim_end_matches('/');
+    if pattern.is_empty() {
+        return Ok(None);
+    }
+
+    // a slash anywhere but the end anchors the pattern to the ignore file's directory
+    let glob = if let Some(anchored) = pattern.strip_prefix('/') {
+        anchored.to_string()
+    } else if pattern.contains('/') {
+        pattern.to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+
+    let regex = glob::to_regex(&glob)?;
+    Ok(Some(Rule {
+        regex,
+        negated,
+        dir_only,
+    }))
+}
+
+/// `--glob` filters. They use gitignore syntax relative to the search root,
+/// but with the meaning flipped: a plain glob selects files and a `!glob`
+/// excludes them. Once any selecting glob is given, files that match none
+/// of them are excluded too.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    rules: Gitignore,
+    has_whitelist: bool,
+}
+
+impl Overrides {
+    pub fn new(root: &Path, globs: &[String]) -> Result<Overrides, String> {
+        let mut rules = Vec::new();
+        let mut has_whitelist = false;
+        for glob in globs {
+            let flipped = match glob.strip_prefix('!') {
+                Some(excluded) => excluded.to_string(),
+                None => {
+                    has_whitelist = true;
+                    format!("!{}", glob)
+                }
+            };
+            match parse_rule(&flipped) {
+                Ok(Some(rule)) => rules.push(rule),
+                Ok(None) => {}
+                Err(err) => return Err(format!("invalid glob '{}': {}", glob, err)),
+            }
+        }
+        Ok(Overrides {
+            rules: Gitignore {
+                root: root.to_path_buf(),
+                within: PathBuf::new(),
+                rules,
+            },
+            has_whitelist,
+        })
+    }
+
+    /// The same globs, relative to `root` instead.
+    pub fn rooted_at(&self, root: &Path) -> Overrides {
+        Overrides {
+            rules: self.rules.rooted_at(root),
+            ..*self
+        }
+    }
+
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Verdict {
+        match self.rules.matched(path, is_dir) {
+            Verdict::None if self.has_whitelist && !is_dir => Verdict::Ignore,
+            verdict => verdict,
+        }
+    }
+}
+
+/// The ignore files that apply to a directory: its own plus every ancestor's
+/// down from the search root.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreStack {
+    levels: Vec<Arc<Gitignore>>,
+}
+
+impl IgnoreStack {
+    /// A stack whose lowest-precedence level is `base`, e.g. rules from the
+    /// config file.
+    pub fn with_base(base: Arc<Gitignore>) -> IgnoreStack {
+        let mut stack = IgnoreStack::default();
+        if !base.is_empty() {
+            stack.levels.push(base);
+        }
+        stack
+    }
+
+    /// Adds the rules git keeps outside the tree (see
+    /// [`git::exclude_files`]) for the repository `root` is in, when its
+    /// work tree starts above `root`, and then the ignore files of each
+    /// directory from the work tree down to `root`'s parent, each relative to
+    /// its own directory; `descend` finds those of the work trees and
+    /// directories it enters.
+    pub fn with_repository_above(mut self, root: &Path) -> IgnoreStack {
+        let Ok(absolute) = root.canonicalize() else {
+            return self;
+        };
+        let Some(work_tree) = absolute
+            .ancestors()
+            .skip(1)
+            .find(|dir| dir.join(".git").exists())
+        else {
+            return self;
+        };
+        let within = absolute.strip_prefix(work_tree).unwrap_or(Path::new(""));
+        for file in git::exclude_files(work_tree) {
+            if let Some(mut excludes) = Gitignore::from_file(root, &file)
+                && !excludes.is_empty()
+            {
+                excludes.within = within.to_path_buf();
+                self.levels.push(Arc::new(excludes));
+            }
+        }
+        let mut dirs: Vec<&Path> = absolute
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(work_tree))
+            .collect();
+        dirs.reverse();
+        for dir in dirs {
+            let within = absolute.strip_prefix(dir).unwrap_or(Path::new(""));
+            for name in IGNORE_FILES.iter().rev() {
+                if let Some(mut gitignore) = Gitignore::from_file(root, &dir.join(name))
+                    && !gitignore.is_empty()
+                {
+                    gitignore.within = within.to_path_buf();
+                    self.levels.push(Arc::new(gitignore));
+                }
+            }
+        }
+        self
+    }
+
+    /// Returns the stack for `dir`, picking up any ignore files it contains,
+    /// and git's exclude rules when it's a repository's work tree.
+    pub fn descend(&self, dir: &Path) -> IgnoreStack {
+        let mut next = self.clone();
+        // below every ignore file in the tree, as in git
+        if dir.join(".git").exists() {
+            for file in git::exclude_files(dir) {
+                if let Some(excludes) = Gitignore::from_file(dir, &file)
+                    && !excludes.is_empty()
+                {
+                    next.levels.push(Arc::new(excludes));
+                }
+            }
+        }
+        // push lower precedence first so `matched` can scan from the back
+        for name in IGNORE_FILES.iter().rev() {
+            if let Some(gitignore) = Gitignore::from_file(dir, &dir.join(name))
+                && !gitignore.is_empty()
+            {
+                next.levels.push(Arc::new(gitignore));
+            }
+        }
+        next
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for gitignore in self.levels.iter().rev() {
+            match gitignore.matched(path, is_dir) {
+                Verdict::Ignore => return true,
+                Verdict::Whitelist => return false,
+                Verdict::None => {}
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// A fresh directory under the system's temporary one holding `files`,
+    /// each a path relative to it and its contents.
+    fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("rstr-ignore-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    fn verdicts(contents: &str, paths: &[(&str, bool)]) -> Vec<Verdict> {
+        let root = Path::new("/repo");
+        let gitignore = Gitignore::parse(root, contents);
+        paths
+            .iter()
+            .map(|&(path, is_dir)| gitignore.matched(&root.join(path), is_dir))
+            .collect()
+    }
+
+    #[test]
+    fn slashes_anchor_patterns_and_a_trailing_one_means_directories() {
+        use Verdict::*;
+        assert_eq!(
+            verdicts(
+                "*.log\n/top\nsrc/gen\nbuild/\n",
+                &[
+                    ("a.log", false),
+                    ("deep/a.log", false),
+                    ("top", false),
+                    ("deep/top", false),
+                    ("src/gen", false),
+                    ("x/src/gen", false),
+                    ("build", true),
+                    ("build", false),
+                    ("deep/build", true),
+                ]
+            ),
+            [
+                Ignore, Ignore, Ignore, None, Ignore, None, Ignore, None, Ignore
+            ]
+        );
+    }
+
+    #[test]
+    fn the_last_matching_rule_wins() {
+        use Verdict::*;
+        assert_eq!(
+            verdicts(
+                "*.txt\n!keep.txt\n!*.md\n*.md\n",
+                &[
+                    ("a.txt", false),
+                    ("keep.txt", false),
+                    ("a.md", false),
+                    ("a.rs", false)
+                ]
+            ),
+            [Ignore, Whitelist, Ignore, None]
+        );
+    }
+
+    #[test]
+    fn comments_blanks_escapes_and_trailing_spaces() {
+        use Verdict::*;
+        assert_eq!(
+            verdicts(
+                "# a comment\n\n\\#hash\n\\!bang\nspaced   \nkept\\ \n[\n",
+                &[
+                    ("# a comment", false),
+                    ("#hash", false),
+                    ("!bang", false),
+                    ("spaced", false),
+                    ("kept ", false),
+                ]
+            ),
+            [None, Ignore, Ignore, Ignore, Ignore]
+        );
+    }
+
+    #[test]
+    fn rules_below_the_work_tree_match_from_it() {
+        let mut gitignore = Gitignore::parse(Path::new("/repo/sub"), "/sub/a.txt\n/b.txt\n");
+        gitignore.within = PathBuf::from("sub");
+        assert_eq!(
+            gitignore.matched(Path::new("/repo/sub/a.txt"), false),
+            Verdict::Ignore
+        );
+        assert_eq!(
+            gitignore.matched(Path::new("/repo/sub/b.txt"), false),
+            Verdict::None
+        );
+        assert_eq!(
+            gitignore.matched(Path::new("/elsewhere/a.txt"), false),
+            Verdict::None
+        );
+    }
+
+    #[test]
+    fn override_globs_select_and_exclude() {
+        let root = Path::new("/repo");
+        let overrides = Overrides::new(root, &["*.rs".into(), "!main.rs".into()]).unwrap();
+        let verdict = |path: &str, is_dir| overrides.matched(&root.join(path), is_dir);
+        assert_eq!(verdict("lib.rs", false), Verdict::Whitelist);
+        assert_eq!(verdict("main.rs", false), Verdict::Ignore);
+        assert_eq!(verdict("README.md", false), Verdict::Ignore);
+        assert_eq!(verdict("src", true), Verdict::None);
+        let excluding = Overrides::new(root, &["!*.md".into()]).unwrap();
+        assert_eq!(excluding.matched(&root.join("a.rs"), false), Verdict::None);
+        assert!(Overrides::new(root, &["[z-a]".into()]).is_err());
+    }
+
+    #[test]
+    fn deeper_and_more_specific_ignore_files_win() {
+        let root = tree(
+            "stack",
+            &[
+                (".gitignore", "*.a\n*.b\n"),
+                (".ignore", "!*.a\n"),
+                ("sub/.gitignore", "!*.b\n*.c\n"),
+                ("sub/.rstrignore", "!*.c\n"),
+            ],
+        );
+        let top = IgnoreStack::default().descend(&root);
+        assert!(!top.is_ignored(&root.join("x.a"), false));
+        assert!(top.is_ignored(&root.join("x.b"), false));
+        let sub = top.descend(&root.join("sub"));
+        let ignored: Vec<bool> = ["x.a", "x.b", "x.c"]
+            .iter()
+            .map(|file| sub.is_ignored(&root.join("sub").join(file), false))
+            .collect();
+        assert_eq!(ignored, [false, false, false]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    /// Whether a walk from `dir` would skip each of `files`, all of them
+    /// directly in `dir`.
+    fn ignored(dir: &Path, files: &[&str]) -> Vec<bool> {
+        let stack = IgnoreStack::default()
+            .with_repository_above(dir)
+            .descend(dir);
+        files
+            .iter()
+            .map(|file| stack.is_ignored(&dir.join(file), false))
+            .collect()
+    }
+
+    #[test]
+    fn ancestor_ignore_files_apply_below_the_work_tree() {
+        let root = tree(
+            "ancestors",
+            &[
+                (".git/HEAD", "ref: refs/heads/main\n"),
+                (".gitignore", "*.log\n/top.txt\n"),
+                ("src/.ignore", "*.tmp\n"),
+                ("src/deep/top.txt", ""),
+            ],
+        );
+        let deep = root.join("src/deep");
+        assert_eq!(
+            ignored(&deep, &["a.log", "a.tmp", "top.txt", "a.rs"]),
+            [true, true, false, false]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    /// A work tree whose every kind of ignore rule is overridden by the next
+    /// one up in precedence: `core.excludesFile`, `.git/info/exclude`, the
+    /// root's `.gitignore`, `sub`'s `.gitignore`, `.ignore` and `.rstrignore`.
+    /// `x.g` is left to `core.excludesFile`.
+    fn layered(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("rstr-ignore-{}-{}", name, std::process::id()));
+        let global = root.join("global-excludes");
+        let config = format!("[core]\n\texcludesFile = {}\n", global.display());
+        tree(
+            name,
+            &[
+                (".git/HEAD", "ref: refs/heads/main\n"),
+                (".git/objects/.keep", ""),
+                (".git/refs/.keep", ""),
+                (".git/config", &config),
+                ("global-excludes", "*.a\n*.g\n"),
+                (".git/info/exclude", "!*.a\n*.b\n"),
+                (".gitignore", "!*.b\n*.c\n"),
+                ("sub/.gitignore", "!*.c\n*.d\n"),
+                ("sub/.ignore", "!*.d\n*.e\n"),
+                ("sub/.rstrignore", "!*.e\n*.f\n"),
+            ],
+        )
+    }
+
+    const LAYERED: &[&str] = &["x.a", "x.b", "x.c", "x.d", "x.e", "x.f", "x.g"];
+
+    #[test]
+    fn ignore_files_override_excludes_from_the_work_tree() {
+        let root = layered("precedence-root");
+        let stack = IgnoreStack::default()
+            .with_repository_above(&root)
+            .descend(&root);
+        let verdicts: Vec<bool> = LAYERED
+            .iter()
+            .map(|file| stack.is_ignored(&root.join(file), false))
+            .collect();
+        assert_eq!(verdicts, [false, false, true, false, false, false, true]);
+        let sub = root.join("sub");
+        let stack = stack.descend(&sub);
+        let verdicts: Vec<bool> = LAYERED
+            .iter()
+            .map(|file| stack.is_ignored(&sub.join(file), false))
+            .collect();
+        assert_eq!(verdicts, [false, false, false, false, false, true, true]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn ignore_files_override_excludes_from_a_subdirectory() {
+        let root = layered("precedence-sub");
+        assert_eq!(
+            ignored(&root.join("sub"), LAYERED),
+            [false, false, false, false, false, true, true]
+        );
+        // with only the excludes and the root's `.gitignore` above it
+        let below = root.join("sub/below");
+        fs::create_dir_all(&below).unwrap();
+        fs::write(root.join("sub/.gitignore"), "").unwrap();
+        fs::write(root.join("sub/.ignore"), "").unwrap();
+        fs::write(root.join("sub/.rstrignore"), "").unwrap();
+        assert_eq!(
+            ignored(&below, LAYERED),
+            [false, false, true, false, false, false, true]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
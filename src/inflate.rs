@@ -0,0 +1,338 @@
+//! A DEFLATE decoder (RFC 1951) for the compressed entries of zip files,
+//! and the gzip framing around DEFLATE data (RFC 1952).
+
+use std::io;
+
+/// Base lengths and extra bits of the length symbols 257..=285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances and extra bits of the distance symbols 0..=29.
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order code length code lengths come in, in dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const MAX_BITS: usize = 15;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads bits least significant first, as DEFLATE packs them.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn take(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid("compressed data ends early"))?;
+            self.pos += 1;
+            self.buffer |= u64::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = (self.buffer & ((1 << n) - 1)) as u32;
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Drops the bits left in the current byte; never more than 7 are
+    /// buffered between reads.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+/// A canonical Huffman code: how many codes there are of each length, and
+/// the symbols ordered by code.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; offsets[MAX_BITS + 1] as usize];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> io::Result<u16> {
+        // codes of each length follow on from the last code of the one
+        // before, so a code is valid once it's below that length's last one
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..=MAX_BITS {
+            code |= bits.take(1)? as i32;
+            let count = i32::from(self.counts[length]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("invalid Huffman code"))
+    }
+}
+
+/// Decompresses raw DEFLATE `data`, returning the output and how many
+/// bytes of `data` it took up.
+pub(crate) fn inflate(data: &[u8]) -> io::Result<(Vec<u8>, usize)> {
+    let mut bits = Bits {
+        data,
+        pos: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out = Vec::with_capacity(data.len() * 3);
+    loop {
+        let last = bits.take(1)? == 1;
+        match bits.take(2)? {
+            0 => {
+                bits.align();
+                let header = data
+                    .get(bits.pos..bits.pos + 4)
+                    .ok_or_else(|| invalid("compressed data ends early"))?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                let nlen = u16::from_le_bytes([header[2], header[3]]);
+                if len != !nlen {
+                    return Err(invalid("corrupt stored block"));
+                }
+                let start = bits.pos + 4;
+                let block = data
+                    .get(start..start + len as usize)
+                    .ok_or_else(|| invalid("compressed data ends early"))?;
+                out.extend_from_slice(block);
+                bits.pos = start + len as usize;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            _ => return Err(invalid("invalid block type")),
+        }
+        if last {
+            return Ok((out, bits.pos));
+        }
+    }
+}
+
+fn read_dynamic_codes(bits: &mut Bits) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = bits.take(5)? as usize + 257;
+    let distance_count = bits.take(5)? as usize + 1;
+    let code_length_count = bits.take(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[i] = bits.take(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = code_lengths.decode(bits)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *i
+                    .checked_sub(1)
+                    .and_then(|p| lengths.get(p))
+                    .ok_or_else(|| invalid("repeated code length without a previous one"))?;
+                (previous, 3 + bits.take(2)? as usize)
+            }
+            17 => (0, 3 + bits.take(3)? as usize),
+            _ => (0, 11 + bits.take(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err(invalid("too many code lengths"));
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(invalid("no end-of-block code"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                if i >= LENGTH_BASE.len() {
+                    return Err(invalid("invalid length code"));
+                }
+                let length = LENGTH_BASE[i] as usize + bits.take(LENGTH_EXTRA[i].into())? as usize;
+                let d = distances.decode(bits)? as usize;
+                if d >= DISTANCE_BASE.len() {
+                    return Err(invalid("invalid distance code"));
+                }
+                let distance =
+                    DISTANCE_BASE[d] as usize + bits.take(DISTANCE_EXTRA[d].into())? as usize;
+                if distance > out.len() {
+                    return Err(invalid("distance reaches before the start of the data"));
+                }
+                // the copy may overlap what it's producing, so go byte by byte
+                let start = out.len() - distance;
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+        }
+    }
+}
+
+/// Decompresses gzip `data`, including files of several gzip members one
+/// after the other as `cat a.gz b.gz` makes.
+pub(crate) fn gunzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    const FEXTRA: u8 = 4;
+    const FNAME: u8 = 8;
+    const FCOMMENT: u8 = 16;
+    const FHCRC: u8 = 2;
+
+    let mut out = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        if rest.len() < 18 || rest[..3] != [0x1f, 0x8b, 8] {
+            return Err(invalid("not gzip data"));
+        }
+        let flags = rest[3];
+        let mut pos = 10;
+        if flags & FEXTRA != 0 {
+            let len = rest
+                .get(pos..pos + 2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+                .ok_or_else(|| invalid("gzip header ends early"))?;
+            pos += 2 + len;
+        }
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                let end = rest
+                    .get(pos..)
+                    .and_then(|tail| memchr::memchr(0, tail))
+                    .ok_or_else(|| invalid("gzip header ends early"))?;
+                pos += end + 1;
+            }
+        }
+        if flags & FHCRC != 0 {
+            pos += 2;
+        }
+        let body = rest
+            .get(pos..)
+            .ok_or_else(|| invalid("gzip header ends early"))?;
+        let (inflated, used) = inflate(body)?;
+        out.extend_from_slice(&inflated);
+        // skip the CRC-32 and size trailer
+        rest = body
+            .get(used + 8..)
+            .ok_or_else(|| invalid("gzip data ends early"))?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // made with Python's zlib, as raw DEFLATE of each block type and gzip
+    const STORED: &[u8] = b"\x01\x03\x00\xfc\xff\x61\x62\x63";
+    const FIXED: &[u8] = b"\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x90\x00";
+    const DYNAMIC: &[u8] = b"\xa5\xd2\x4d\x0a\x84\x30\x0c\x05\xe0\x7d\x4f\x91\x23\x34\xb4\x59\xf4\x38\x32\x53\x15\xa9\x54\xfc\x9b\x9f\xd3\xbb\x10\x8a\x38\x9d\x64\x91\x75\x78\x8f\x8f\x47\xda\xfc\x86\x61\x1b\xa7\x05\xf2\x1e\x67\x58\xfb\x08\xa9\xf9\x7e\xe0\x99\x3b\xb0\xe6\xd1\xac\xf7\xeb\x92\x62\x9c\xce\x3b\x9a\xfc\x4a\x7f\xd3\xde\xb4\xbf\xdd\x97\x74\xa8\xb5\x97\x34\xd5\xba\x2f\x69\x57\x6b\x2f\x69\x27\xc8\x89\x95\x07\x41\xee\x59\x39\x0a\x72\xcb\xca\x51\x90\x7b\x95\x9c\x58\xb9\x53\x6d\x4e\x82\x3c\xa8\xbe\x05\x59\xb9\x15\xe4\xc8\xca\xbd\x4a\x4e\x82\xdc\xa9\x36\x27\x73\x00";
+    const GZIP: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcb\x48\xcd\xc9\xc9\xe7\x02\x00\x20\x30\x3a\x36\x06\x00\x00\x00";
+    const GZIP_NAMED: &[u8] = b"\x1f\x8b\x08\x08\x00\x00\x00\x00\x02\xff\x61\x2e\x74\x78\x74\x00\xcb\x4b\xcc\x4d\x4d\xe1\x02\x00\xdc\xf0\x7a\x41\x06\x00\x00\x00";
+
+    #[test]
+    fn inflates_every_block_type() {
+        assert_eq!(inflate(STORED).unwrap(), (b"abc".to_vec(), STORED.len()));
+        assert_eq!(
+            inflate(FIXED).unwrap(),
+            (b"hello hello hello".to_vec(), FIXED.len())
+        );
+        let text: String = (0..30)
+            .map(|i| {
+                let animal = ["fox", "cat", "owl"][i % 3];
+                let dog = ["lazy", "sleepy"][i % 2];
+                format!("{} jumps over the {} dog {}\n", animal, dog, i * i % 11)
+            })
+            .collect();
+        assert_eq!(
+            inflate(DYNAMIC).unwrap(),
+            (text.into_bytes(), DYNAMIC.len())
+        );
+    }
+
+    #[test]
+    fn inflate_rejects_truncated_data() {
+        assert!(inflate(&STORED[..6]).is_err());
+        assert!(inflate(&DYNAMIC[..DYNAMIC.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn gunzips_members_one_after_the_other() {
+        assert_eq!(gunzip(GZIP).unwrap(), b"hello\n");
+        assert_eq!(gunzip(GZIP_NAMED).unwrap(), b"named\n");
+        assert_eq!(
+            gunzip(&[GZIP, GZIP_NAMED].concat()).unwrap(),
+            b"hello\nnamed\n"
+        );
+        assert!(gunzip(b"hello\n").is_err());
+        assert!(gunzip(&GZIP[..GZIP.len() - 4]).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! A single-line text prompt with a cursor and history recall.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Input {
+    text: String,
+    /// In chars, not bytes.
+    cursor: usize,
+    /// Earlier entries, oldest first.
+    history: Vec<String>,
+    /// The history entry shown, while browsing it with Up/Down.
+    recalled: Option<usize>,
+    /// What was typed before browsing started, restored past the newest entry.
+    draft: String,
+}
+
+impl Input {
+    pub fn with_history(history: Vec<String>) -> Input {
+        Input {
+            history,
+            ..Input::default()
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.chars().count();
+        self.recalled = None;
+    }
+
+    /// Makes `entry` the newest history entry.
+    pub fn remember(&mut self, entry: &str) {
+        self.history.retain(|e| e != entry);
+        self.history.push(entry.to_string());
+        self.recalled = None;
+    }
+
+    /// Width of the text before the cursor, for placing the terminal cursor.
+    pub fn cursor_offset(&self) -> u16 {
+        self.text[..self.byte_index()].width() as u16
+    }
+
+    /// Applies an editing or history key; returns `false` for keys the input
+    /// doesn't handle.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.set_text("");
+            }
+            KeyCode::Char(c) => {
+                let at = self.byte_index();
+                self.text.insert(at, c);
+                self.cursor += 1;
+            }
+            KeyCode::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                let at = self.byte_index();
+                self.text.remove(at);
+            }
+            KeyCode::Delete if self.cursor < self.text.chars().count() => {
+                let at = self.byte_index();
+                self.text.remove(at);
+            }
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.text.chars().count()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.chars().count(),
+            KeyCode::Up => self.recall_older(),
+            KeyCode::Down => self.recall_newer(),
+            KeyCode::Backspace | KeyCode::Delete => {}
+            _ => return false,
+        }
+        true
+    }
+
+    fn recall_older(&mut self) {
+        let index = match self.recalled {
+            Some(0) => return,
+            Some(i) => i - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.text.clone();
+                self.history.len() - 1
+            }
+        };
+        self.show(Some(index));
+    }
+
+    fn recall_newer(&mut self) {
+        match self.recalled {
+            Some(i) if i + 1 < self.history.len() => self.show(Some(i + 1)),
+            Some(_) => self.show(None),
+            None => {}
+        }
+    }
+
+    fn show(&mut self, index: Option<usize>) {
+        let text = match index {
+            Some(i) => self.history[i].clone(),
+            None => std::mem::take(&mut self.draft),
+        };
+        self.set_text(&text);
+        self.recalled = index;
+    }
+
+    fn byte_index(&self) -> usize {
+        self.text
+            .char_indices()
+            .nth(self.cursor)
+            .map_or(self.text.len(), |(i, _)| i)
+    }
+}
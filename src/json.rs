@@ -0,0 +1,434 @@
+//! Writing JSON for `--format json`, and reading it back for sessions.
+//! Documents too nested to write piecemeal, like SARIF reports, are built
+//! as a [`Value`] and written with [`Value::pretty`].
+
+use rstr::definitions::Definition;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Quotes and escapes `s` as a JSON string literal.
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A match's enclosing definition as `{"line":…,"name":…}`.
+pub fn definition(definition: &Definition) -> String {
+    format!(
+        r#"{{"line":{},"name":{}}}"#,
+        definition.line_number,
+        string(&definition.name)
+    )
+}
+
+pub fn string_array(items: &[String]) -> String {
+    let items: Vec<String> = items.iter().map(|s| string(s)).collect();
+    format!("[{}]", items.join(","))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(s)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Value {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(items: Vec<Value>) -> Value {
+        Value::Array(items)
+    }
+}
+
+impl Value {
+    /// An object of `members`, which are written in key order.
+    pub fn object<const N: usize>(members: [(&str, Value); N]) -> Value {
+        Value::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// The value as JSON, indented by two spaces per level.
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, depth: usize) {
+        let indent = |out: &mut String, depth: usize| {
+            out.push('\n');
+            out.push_str(&"  ".repeat(depth));
+        };
+        match self {
+            Value::Null => out.push_str("null"),
+            Value::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+            // JSON has no NaN or infinities
+            Value::Number(n) if !n.is_finite() => out.push_str("null"),
+            Value::Number(n) => {
+                let _ = write!(out, "{}", n);
+            }
+            Value::String(s) => out.push_str(&string(s)),
+            Value::Array(items) if items.is_empty() => out.push_str("[]"),
+            Value::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    indent(out, depth + 1);
+                    item.write_pretty(out, depth + 1);
+                }
+                indent(out, depth);
+                out.push(']');
+            }
+            Value::Object(members) if members.is_empty() => out.push_str("{}"),
+            Value::Object(members) => {
+                out.push('{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    indent(out, depth + 1);
+                    out.push_str(&string(key));
+                    out.push_str(": ");
+                    value.write_pretty(out, depth + 1);
+                }
+                indent(out, depth);
+                out.push('}');
+            }
+        }
+    }
+
+    /// The member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Numbers that are non-negative integers.
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => Some(n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a single JSON value, such as one line of JSON Lines.
+pub fn parse(input: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: input.char_indices().peekable(),
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some((at, _)) => Err(format!("unexpected trailing characters at {}", at)),
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .next_if(|(_, c)| c.is_ascii_whitespace())
+            .is_some()
+        {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((at, c)) => Err(format!("expected '{}' at {}, found '{}'", expected, at, c)),
+            None => Err(format!("expected '{}', found the end", expected)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        let Some(&(at, c)) = self.chars.peek() else {
+            return Err("expected a value, found the end".to_string());
+        };
+        match c {
+            '{' => self.object(),
+            '[' => self.array(),
+            '"' => self.string().map(Value::String),
+            't' => self.keyword("true", Value::Boolean(true)),
+            'f' => self.keyword("false", Value::Boolean(false)),
+            'n' => self.keyword("null", Value::Null),
+            '-' | '0'..='9' => self.number(),
+            _ => Err(format!("unexpected '{}' at {}", c, at)),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for expected in word.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let mut text = String::new();
+        while let Some((_, c)) = self
+            .chars
+            .next_if(|(_, c)| matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+        {
+            text.push(c);
+        }
+        text.parse()
+            .map(Value::Number)
+            .map_err(|_| format!("invalid number '{}'", text))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(out),
+                Some((_, '\\')) => {
+                    let escaped = match self.chars.next() {
+                        Some((_, 'n')) => '\n',
+                        Some((_, 'r')) => '\r',
+                        Some((_, 't')) => '\t',
+                        Some((_, 'b')) => '\u{8}',
+                        Some((_, 'f')) => '\u{c}',
+                        Some((_, 'u')) => self.unicode_escape()?,
+                        Some((_, c @ ('"' | '\\' | '/'))) => c,
+                        Some((at, c)) => return Err(format!("invalid escape '\\{}' at {}", c, at)),
+                        None => return Err("unterminated string".to_string()),
+                    };
+                    out.push(escaped);
+                }
+                Some((_, c)) => out.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    /// The character of a `\uXXXX` escape, combining surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let first = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&first) {
+            return Ok(char::from_u32(first).unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+        self.expect('\\')?;
+        self.expect('u')?;
+        let second = self.hex4()?;
+        let code = 0x10000 + ((first - 0xD800) << 10) + second.wrapping_sub(0xDC00);
+        Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|(_, c)| c.to_digit(16))
+                .ok_or("invalid \\u escape")?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if(|&(_, c)| c == ']').is_some() {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => {}
+                Some((_, ']')) => return Ok(Value::Array(items)),
+                _ => return Err("expected ',' or ']' in an array".to_string()),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut members = BTreeMap::new();
+        self.skip_whitespace();
+        if self.chars.next_if(|&(_, c)| c == '}').is_some() {
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            members.insert(key, self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => {}
+                Some((_, '}')) => return Ok(Value::Object(members)),
+                _ => return Err("expected ',' or '}' in an object".to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_are_escaped() {
+        assert_eq!(string("plain é"), r#""plain é""#);
+        assert_eq!(string("a\"b\\c"), r#""a\"b\\c""#);
+        assert_eq!(string("\n\r\t\u{1}\u{7f}"), "\"\\n\\r\\t\\u0001\u{7f}\"");
+        assert_eq!(string_array(&["a".into(), "b\"".into()]), r#"["a","b\""]"#);
+        assert_eq!(string_array(&[]), "[]");
+    }
+
+    #[test]
+    fn written_strings_parse_back() {
+        for s in [
+            "",
+            "plain",
+            "quote \" and \\ backslash",
+            "\n\r\t\u{0}\u{1f}",
+            "é ✓ 😀",
+        ] {
+            assert_eq!(parse(&string(s)), Ok(Value::String(s.to_string())));
+        }
+    }
+
+    #[test]
+    fn parses_every_kind_of_value() {
+        let value = parse(r#" {"a": [1, -2.5, 3e2], "b": {"c": null}, "d": true, "e": false, "f": "x\/\u00e9\ud83d\ude00\b\f"} "#)
+            .unwrap();
+        assert_eq!(
+            value.get("a").and_then(Value::as_array),
+            Some(
+                &[
+                    Value::Number(1.0),
+                    Value::Number(-2.5),
+                    Value::Number(300.0)
+                ][..]
+            )
+        );
+        assert_eq!(value.get("b").and_then(|b| b.get("c")), Some(&Value::Null));
+        assert_eq!(value.get("d"), Some(&Value::Boolean(true)));
+        assert_eq!(value.get("e"), Some(&Value::Boolean(false)));
+        assert_eq!(
+            value.get("f").and_then(Value::as_str),
+            Some("x/é😀\u{8}\u{c}")
+        );
+        assert_eq!(value.get("missing"), None);
+        assert_eq!(parse("[]"), Ok(Value::Array(Vec::new())));
+        assert_eq!(parse("{}").unwrap().get("a"), None);
+    }
+
+    #[test]
+    fn numbers_are_usize_only_when_whole() {
+        assert_eq!(parse("42").unwrap().as_usize(), Some(42));
+        assert_eq!(parse("-1").unwrap().as_usize(), None);
+        assert_eq!(parse("1.5").unwrap().as_usize(), None);
+        assert_eq!(parse("\"1\"").unwrap().as_usize(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        let cases = [
+            ("", "expected a value, found the end"),
+            ("{} x", "unexpected trailing characters at 3"),
+            ("[1 2]", "expected ',' or ']' in an array"),
+            ("{\"a\" 1}", "expected ':' at 5, found '1'"),
+            ("{\"a\":1,}", "expected '\"' at 7, found '}'"),
+            ("\"open", "unterminated string"),
+            ("\"\\q\"", "invalid escape '\\q' at 2"),
+            ("\"\\u12\"", "invalid \\u escape"),
+            ("tru", "expected 'e', found the end"),
+            ("-", "invalid number '-'"),
+            ("@", "unexpected '@' at 0"),
+        ];
+        for (input, error) in cases {
+            assert_eq!(parse(input), Err(error.to_string()), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn values_print_indented() {
+        let value = Value::object([
+            ("name", "rstr".into()),
+            ("runs", vec![Value::from(1), Value::Null].into()),
+            ("empty", Value::Array(Vec::new())),
+            ("nested", Value::object([("ok", Value::Boolean(true))])),
+            ("nan", Value::Number(f64::NAN)),
+        ]);
+        assert_eq!(
+            value.pretty(),
+            "{\n  \"empty\": [],\n  \"name\": \"rstr\",\n  \"nan\": null,\n  \"nested\": {\n    \"ok\": true\n  },\n  \"runs\": [\n    1,\n    null\n  ]\n}"
+        );
+        assert_eq!(
+            parse(&value.pretty()).unwrap().get("name"),
+            value.get("name")
+        );
+    }
+}
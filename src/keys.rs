@@ -0,0 +1,296 @@
+//! The TUI's key bindings: what each key does in the results list, with
+//! vim-style defaults alongside the arrows, remapped by the config file's
+//! `[keys]` table, e.g. `quit = ["q", "ctrl-q"]`.
+
+use crate::toml::{Table, Value};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// What a key in the results list can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Down,
+    Up,
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    First,
+    Last,
+    Open,
+    /// Marks the selected result, or unmarks it; on a file's row Space
+    /// folds it instead.
+    Mark,
+    /// Adds a note to the selected result, marking it.
+    Note,
+    Fold,
+    /// Lists the files in a tree of their directories, or flat again.
+    Tree,
+    Filter,
+    Jump,
+    Files,
+    Search,
+    /// Searches again in the selected file's directory.
+    Narrow,
+    /// Searches again where the search was before the last `Narrow`.
+    Widen,
+    /// Searches for the text of the selected match.
+    Follow,
+    /// Goes back to the search before the last `Follow`, and `Forward`
+    /// again.
+    Back,
+    Forward,
+    Sort,
+    Preview,
+    Paths,
+    Wrap,
+    /// Scrolls long lines sideways.
+    ScrollLeft,
+    ScrollRight,
+    /// Shows who last changed each result's line, and when.
+    Blame,
+    Copy,
+    CopyLine,
+    /// Writes the results shown to a file.
+    Export,
+    /// Searches within the results, and goes back out again.
+    Refine,
+    Unrefine,
+    Replace,
+    ReplaceFile,
+    Errors,
+    Help,
+    /// Stops the running search; keys bound to it do nothing otherwise,
+    /// or whatever else they're bound to.
+    Cancel,
+    Quit,
+}
+
+/// Every command with its name in `[keys]` and its default keys.
+const DEFAULTS: &[(Command, &str, &[&str])] = &[
+    (Command::Down, "down", &["Down", "j"]),
+    (Command::Up, "up", &["Up", "k"]),
+    (Command::PageDown, "page-down", &["PageDown"]),
+    (Command::PageUp, "page-up", &["PageUp"]),
+    (Command::HalfPageDown, "half-page-down", &["ctrl-d"]),
+    (Command::HalfPageUp, "half-page-up", &["ctrl-u"]),
+    (Command::First, "first", &["Home", "g"]),
+    (Command::Last, "last", &["End", "G"]),
+    (Command::Open, "open", &["Enter"]),
+    (Command::Mark, "mark", &["Space"]),
+    (Command::Note, "note", &["n"]),
+    (Command::Fold, "fold", &["Space"]),
+    (Command::Tree, "tree", &["t"]),
+    (Command::Filter, "filter", &["/"]),
+    (Command::Jump, "jump", &[":"]),
+    (Command::Files, "files", &["f"]),
+    (Command::Search, "search", &["s"]),
+    (Command::Narrow, "narrow", &["d"]),
+    (Command::Widen, "widen", &["u"]),
+    (Command::Follow, "follow", &["*"]),
+    (Command::Back, "back", &["ctrl-o", "["]),
+    (Command::Forward, "forward", &["Tab", "]"]),
+    (Command::Sort, "sort", &["o"]),
+    (Command::Preview, "preview", &["p"]),
+    (Command::Paths, "paths", &["a"]),
+    (Command::Wrap, "wrap", &["w"]),
+    (Command::ScrollLeft, "scroll-left", &["Left", "h"]),
+    (Command::ScrollRight, "scroll-right", &["Right", "l"]),
+    (Command::Blame, "blame", &["b"]),
+    (Command::Copy, "copy", &["y"]),
+    (Command::CopyLine, "copy-line", &["Y"]),
+    (Command::Export, "export", &["E"]),
+    (Command::Refine, "refine", &["i"]),
+    (Command::Unrefine, "unrefine", &["I"]),
+    (Command::Replace, "replace", &["r"]),
+    (Command::ReplaceFile, "replace-file", &["R"]),
+    (Command::Errors, "errors", &["e"]),
+    (Command::Help, "help", &["?"]),
+    (Command::Cancel, "cancel", &["c", "ctrl-c", "Esc"]),
+    (Command::Quit, "quit", &["q", "Esc"]),
+];
+
+/// The names of the commands in `[keys]`.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    DEFAULTS.iter().map(|&(_, name, _)| name)
+}
+
+/// A key with the modifiers held down. Shift is part of the character for
+/// printable keys, so `G` is `shift-g`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Chord {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Chord {
+        let kept = match code {
+            KeyCode::Char(_) => KeyModifiers::CONTROL | KeyModifiers::ALT,
+            _ => KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT,
+        };
+        Chord {
+            code,
+            modifiers: modifiers & kept,
+        }
+    }
+
+    /// Parses chords like `j`, `G`, `ctrl-d`, `alt-Enter` or `PageDown`.
+    /// Key names and modifiers are case-insensitive, characters aren't.
+    pub fn parse(text: &str) -> Result<Chord, String> {
+        let unknown = || format!("unknown key '{}'", text);
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        // a lone character is itself even if it's `-`
+        while rest.chars().nth(1).is_some()
+            && let Some((modifier, key)) = rest.split_once('-')
+            && !key.is_empty()
+        {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(unknown()),
+            };
+            rest = key;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_uppercase().next().unwrap_or(c))
+            }
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "pageup" | "pgup" => KeyCode::PageUp,
+                "pagedown" | "pgdn" => KeyCode::PageDown,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "space" => KeyCode::Char(' '),
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => return Err(unknown()),
+                },
+            },
+        };
+        Ok(Chord::new(code, modifiers))
+    }
+
+    /// How the help overlay shows it, e.g. `Ctrl-d` or `PgDn`.
+    fn label(self) -> String {
+        let key = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::PageUp => "PgUp".to_string(),
+            KeyCode::PageDown => "PgDn".to_string(),
+            code => code.to_string(),
+        };
+        let mut label = String::new();
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl-"),
+            (KeyModifiers::ALT, "Alt-"),
+            (KeyModifiers::SHIFT, "Shift-"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                label.push_str(name);
+            }
+        }
+        label + &key
+    }
+}
+
+impl From<KeyEvent> for Chord {
+    fn from(key: KeyEvent) -> Chord {
+        Chord::new(key.code, key.modifiers)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Command, Vec<Chord>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let bindings = DEFAULTS
+            .iter()
+            .map(|&(command, _, keys)| {
+                let chords = keys.iter().map(|key| Chord::parse(key).unwrap()).collect();
+                (command, chords)
+            })
+            .collect();
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    /// The defaults with the config file's `[keys]` on top. Each entry
+    /// replaces the keys of a command, and takes them from any other
+    /// command they were bound to.
+    pub fn from_config(table: &Table) -> Result<Keymap, String> {
+        let mut keymap = Keymap::default();
+        for (name, value) in table {
+            let error = |message: String| format!("[keys] '{}': {}", name, message);
+            let Some(&(command, _, _)) = DEFAULTS.iter().find(|(_, n, _)| n == name) else {
+                let known: Vec<&str> = DEFAULTS.iter().map(|(_, name, _)| *name).collect();
+                return Err(error(format!(
+                    "unknown command, expected one of {}",
+                    known.join(", ")
+                )));
+            };
+            let keys = match value {
+                Value::String(key) => vec![key.as_str()],
+                Value::Array(items) => items
+                    .iter()
+                    .map(|item| item.as_str())
+                    .collect::<Option<Vec<&str>>>()
+                    .ok_or_else(|| error("expects a key or a list of keys".to_string()))?,
+                _ => return Err(error("expects a key or a list of keys".to_string())),
+            };
+            let chords = keys
+                .into_iter()
+                .map(Chord::parse)
+                .collect::<Result<Vec<Chord>, String>>()
+                .map_err(error)?;
+            for (_, bound) in &mut keymap.bindings {
+                bound.retain(|chord| !chords.contains(chord));
+            }
+            if let Some((_, bound)) = keymap.bindings.iter_mut().find(|(c, _)| *c == command) {
+                *bound = chords;
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// The commands `key` is bound to, in the order they're tried.
+    pub fn commands(&self, key: KeyEvent) -> impl Iterator<Item = Command> + '_ {
+        let chord = Chord::from(key);
+        self.bindings
+            .iter()
+            .filter(move |(_, chords)| chords.contains(&chord))
+            .map(|&(command, _)| command)
+    }
+
+    /// The keys of `command` for the help overlay, e.g. `Down,j`.
+    pub fn label(&self, command: Command) -> String {
+        let chords = self
+            .bindings
+            .iter()
+            .find(|&&(c, _)| c == command)
+            .map_or(&[][..], |(_, chords)| chords);
+        let labels: Vec<String> = chords.iter().map(|chord| chord.label()).collect();
+        if labels.is_empty() {
+            "-".to_string()
+        } else {
+            labels.join(",")
+        }
+    }
+}
@@ -0,0 +1,30 @@
+//! The search engine behind the `rstr` command line tool: directory
+//! walking with ignore-file support, file filters and regex matching, over
+//! a directory tree or standard input.
+//!
+//! ```no_run
+//! use rstr::{SearchOptions, Searcher};
+//!
+//! let regex = regex::Regex::new("TODO").unwrap();
+//! for result in Searcher::new(SearchOptions::new("src", regex)).matches() {
+//!     println!("{}:{}: {}", result.path.display(), result.line_number, result.line);
+//! }
+//! ```
+
+pub mod archive;
+pub mod compressed;
+pub mod definitions;
+pub mod encoding;
+pub mod git;
+pub mod glob;
+pub mod ignore;
+mod inflate;
+pub mod matcher;
+mod mmap;
+mod search;
+pub mod types;
+pub mod walk;
+
+pub use search::{
+    Cancel, Match, STDIN_PATH, SearchEvent, SearchOptions, SearchStats, Searcher, Source,
+};
@@ -1,180 +1,1803 @@
-use clap::Parser;
-use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
-    execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
-};
-use ratatui::{
-    CompletedFrame, Terminal,
-    backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
-    widgets::{Block, Borders, List, ListItem},
-};
-use regex::Regex;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Stdout};
-use std::path::PathBuf;
+mod blame;
+mod clipboard;
+mod completions;
+mod config;
+mod diff;
+mod editor;
+mod events;
+mod history;
+mod input;
+mod json;
+mod keys;
+mod man;
+mod output;
+mod presets;
+mod preview;
+mod replace;
+mod sarif;
+mod session;
+mod sort;
+mod syntax;
+mod theme;
+mod toml;
+mod tui;
+
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueEnum};
+use keys::Keymap;
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+use rstr::encoding::Encoding;
+use rstr::git::{self, GitFiles};
+use rstr::ignore::{Gitignore, Overrides};
+use rstr::matcher::Matcher;
+use rstr::types::TypeRegistry;
+use rstr::walk::{self, Order, WalkOptions};
+use rstr::{STDIN_PATH, SearchEvent, SearchOptions, Searcher, Source};
+use sort::SortBy;
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use theme::Theme;
+
 #[derive(Parser)]
 #[command(name = "rstr")]
 #[command(author = "Alexander Chabowski <alex.gl.cpp@gmail.com>")]
 #[command(version = "2026.1.0")]
 #[command(about = "A simple search tool with regex support and TUI display", long_about = None)]
+#[command(args_override_self = true)]
+#[command(subcommand_negates_reqs = true, disable_help_subcommand = true)]
+#[command(
+    override_usage = "rstr [OPTIONS] [PATH]... PATTERN\n       rstr [OPTIONS] -e PATTERN [PATH]..."
+)]
 struct Cli {
-    #[arg(help = "The path in which to search")]
-    path: PathBuf,
-    #[arg(help = "The search pattern (Regex)")]
-    pattern: String,
-}
-
-use walkdir::WalkDir;
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Cli::parse();
-    let regex = Regex::new(&args.pattern)?;
-
-    let mut terminal = setup_terminal()?;
-    
-    let mut results = Vec::new();
-    let mut last_tick = std::time::Instant::now();
-    let mut animation_frame = 0;
-    
-    let walker = WalkDir::new(&args.path).into_iter().filter_map(|e| e.ok());
-    
-    for entry in walker {
-        let path = entry.path();
-        if path.is_file() {
-            if last_tick.elapsed() >= std::time::Duration::from_millis(50) {
-                animation_frame = (animation_frame + 1) % 4;
-                draw_loading(&mut terminal, &args.pattern, path.to_str().unwrap_or(""), animation_frame)?;
-                last_tick = std::time::Instant::now();
-
-                //exit during loading
-                if event::poll(std::time::Duration::from_millis(0))? {
-                    if let Event::Key(key) = event::read()? {
-                        if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
-                            restore_terminal(&mut terminal)?;
-                            return Ok(());
-                        }
-                    }
-                }
-            }
+    #[arg(
+        value_name = "PATH",
+        help = "The paths in which to search, - for stdin (default: stdin when piped, else the current directory), followed by the search pattern (Regex)",
+        required_unless_present_any = ["type_list", "history", "regexp", "file", "load_session", "baseline"]
+    )]
+    positional: Vec<PathBuf>,
+    #[arg(
+        long = "path",
+        value_name = "PATH",
+        help = "Search this path too (repeatable); a lone positional argument is then the pattern"
+    )]
+    paths: Vec<PathBuf>,
+    #[arg(
+        short = 'e',
+        long,
+        value_name = "PATTERN",
+        help = "Search for this pattern too (repeatable); all positional arguments are then paths"
+    )]
+    regexp: Vec<String>,
+    #[arg(
+        short = 'f',
+        long,
+        value_name = "PATTERNFILE",
+        help = "Search for the patterns in this file, one per line, - for stdin (repeatable); all positional arguments are then paths"
+    )]
+    file: Vec<PathBuf>,
+    #[arg(
+        short = 'i',
+        long,
+        overrides_with_all = ["smart_case", "case_sensitive"],
+        help = "Search case-insensitively"
+    )]
+    ignore_case: bool,
+    #[arg(
+        short = 'S',
+        long,
+        overrides_with_all = ["ignore_case", "case_sensitive"],
+        help = "Search case-insensitively unless the pattern contains an uppercase letter"
+    )]
+    smart_case: bool,
+    #[arg(
+        short = 's',
+        long,
+        overrides_with_all = ["ignore_case", "smart_case"],
+        help = "Search case-sensitively (the default), overriding -i and -S"
+    )]
+    case_sensitive: bool,
+    #[arg(
+        short = 'F',
+        long,
+        help = "Treat the pattern as literal text rather than a regex"
+    )]
+    fixed_strings: bool,
+    #[arg(
+        short = 'w',
+        long,
+        help = "Only match whole words: the match may not border on word characters"
+    )]
+    word_regexp: bool,
+    #[arg(long, help = "Search hidden files and directories")]
+    hidden: bool,
+    #[arg(
+        long,
+        help = "Don't respect .gitignore, .ignore and .rstrignore files, nor git's excludes"
+    )]
+    no_ignore: bool,
+    #[arg(
+        short = 'u',
+        long,
+        action = ArgAction::Count,
+        help = "Search more: -u is --no-ignore, -uu adds --hidden; binary files are always searched, so -uuu is -uu"
+    )]
+    unrestricted: u8,
+    #[arg(
+        long,
+        value_name = "NUM",
+        help = "Descend at most NUM directory levels below the path"
+    )]
+    max_depth: Option<usize>,
+    #[arg(short = 'L', long, help = "Follow symbolic links to directories")]
+    follow: bool,
+    #[arg(
+        long,
+        conflicts_with = "breadth_first",
+        help = "Search files in path order on one thread, so results come out in the same order every time"
+    )]
+    sort_files: bool,
+    #[arg(
+        long,
+        help = "Search the files of shallower directories before those of deeper ones"
+    )]
+    breadth_first: bool,
+    #[arg(
+        long,
+        help = "Search every path to a file, not just the first, where hard links, bind mounts or symlinks lead to it"
+    )]
+    no_dedupe: bool,
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        visible_alias = "max-size",
+        help = "Skip files larger than SIZE bytes; K, M and G suffixes are allowed, e.g. 10M"
+    )]
+    max_filesize: Option<u64>,
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Skip files smaller than SIZE bytes, e.g. 1K"
+    )]
+    min_size: Option<u64>,
+    #[arg(
+        long,
+        value_name = "WHEN",
+        value_parser = parse_time,
+        help = "Only search files modified since WHEN: a time ago like 30m, 12h, 2d or 1w, or a date like 2024-01-01 (UTC)"
+    )]
+    newer_than: Option<SystemTime>,
+    #[arg(
+        long,
+        value_name = "WHEN",
+        value_parser = parse_time,
+        help = "Only search files last modified before WHEN, given like for --newer-than"
+    )]
+    older_than: Option<SystemTime>,
+    #[cfg(unix)]
+    #[arg(
+        long,
+        value_name = "USER",
+        value_parser = parse_owner,
+        help = "Only search files owned by USER, a user name or ID"
+    )]
+    owner: Option<u32>,
+    #[cfg(unix)]
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_parser = walk::Mode::parse,
+        allow_hyphen_values = true,
+        help = "Only search files with these permissions, as with find -perm: exactly MODE (644), all of -MODE (-u+x) or any of /MODE (/o+w)"
+    )]
+    perm: Option<walk::Mode>,
+    #[arg(
+        long,
+        value_name = "REGEX",
+        value_parser = parse_path_regex,
+        help = "Only search files whose path below PATH matches REGEX, e.g. '^src/.*_test'"
+    )]
+    path_regex: Option<Regex>,
+    #[arg(long, help = "Only search the files git tracks")]
+    git_tracked: bool,
+    #[arg(
+        long,
+        value_name = "REV",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "HEAD",
+        conflicts_with = "git_tracked",
+        help = "Only search the files changed since REV (default: HEAD), staged or not, and untracked ones, e.g. --git-modified=main for a branch's"
+    )]
+    git_modified: Option<String>,
+    #[arg(
+        short = 'z',
+        long,
+        help = "Search the files inside zip, tar and tar.gz archives, reported as archive.zip!inner/path"
+    )]
+    search_zip: bool,
+    #[arg(
+        long,
+        help = "Search what .gz, .xz, .zst and .bz2 files decompress to (xz, zstd and bzip2 need those tools)"
+    )]
+    search_compressed: bool,
+    #[arg(
+        short = 'E',
+        long,
+        value_name = "ENCODING",
+        default_value = "auto",
+        help = "Read files as utf-8, utf-16le, utf-16be or latin1 instead of detecting their encoding"
+    )]
+    encoding: String,
+    #[arg(
+        short = 'j',
+        long,
+        help = "Number of search threads (default: number of CPUs)"
+    )]
+    threads: Option<usize>,
+    #[arg(
+        long,
+        help = "Editor used to open results (default: $VISUAL or $EDITOR)"
+    )]
+    editor: Option<String>,
+    #[arg(
+        long,
+        help = "Print results to stdout instead of starting the TUI (implied when stdout isn't a terminal)"
+    )]
+    no_tui: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["save_session", "load_session"],
+        help = "Keep the TUI's results up to date as files are created, modified or deleted"
+    )]
+    watch: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["watch", "save_session", "load_session", "baseline", "quiet", "count", "count_matches", "files_with_matches", "files_without_match", "files", "sort", "write_changes"],
+        help = "Keep following the files searched once the search is done, like tail -f, adding matches of the lines written to them"
+    )]
+    tail: bool,
+    #[arg(
+        long,
+        help = "Don't report files and directories that can't be read in plain output"
+    )]
+    no_messages: bool,
+    #[arg(
+        long,
+        value_enum,
+        help = "Print results to stdout in this format instead of starting the TUI"
+    )]
+    format: Option<Format>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        default_value = "default",
+        help = "Colours of the TUI: default, dark, light, solarized or a theme from the config file"
+    )]
+    theme: String,
+    #[arg(
+        long,
+        value_name = "NUM",
+        default_value_t = 100_000,
+        help = "How many results the TUI keeps the lines of in memory; those of later ones are read again from their files when shown, 0 for no limit"
+    )]
+    results_in_memory: usize,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "BY",
+        help = "List files in this order; plain output then waits for the search to finish"
+    )]
+    sort: Option<SortBy>,
+    #[arg(
+        long,
+        help = "Include the column of the first match in plain output (path:line:column:content)"
+    )]
+    column: bool,
+    #[arg(
+        long,
+        conflicts_with = "quiet",
+        help = "Print the files, bytes and matches searched and how long it took; in the TUI, add the throughput to the status bar"
+    )]
+    stats: bool,
+    #[arg(
+        short = 'o',
+        long,
+        conflicts_with_all = ["replace", "invert_match", "count", "count_matches", "files_with_matches", "files_without_match", "files"],
+        help = "Show just the text of each match with its path:line:column, one match per line"
+    )]
+    only_matching: bool,
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        conflicts_with_all = ["format", "only_matching", "replace", "invert_match", "count", "count_matches", "files_with_matches", "files_without_match", "files", "write_changes"],
+        help = "Print a line per match made from TEMPLATE: {path}, {line}, {column}, {text} and capture groups like $1 or ${name}"
+    )]
+    output_template: Option<String>,
+    #[arg(
+        short = 'p',
+        long = "show-function",
+        help = "Show the function, type or class each match is in, going by the nearest definition above it in source files of known types"
+    )]
+    show_function: bool,
+    #[arg(
+        short = 'M',
+        long,
+        value_name = "NUM",
+        help = "Leave out lines longer than NUM characters in plain output and the TUI, 0 for no limit (default in the TUI: a preview of 1000)"
+    )]
+    max_columns: Option<usize>,
+    #[arg(
+        long,
+        requires = "max_columns",
+        help = "Show the first --max-columns characters of long lines instead of leaving them out"
+    )]
+    max_columns_preview: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "quiet",
+        help = "Write the results to FILE instead of starting the TUI, in --format or the format its extension names (.json, .md, .csv, .tsv, .sarif)"
+    )]
+    output: Option<PathBuf>,
+    #[arg(
+        short = 'N',
+        long,
+        help = "Leave the line numbers out of plain output and the TUI's results"
+    )]
+    no_line_number: bool,
+    #[arg(
+        long,
+        conflicts_with = "relative_path",
+        help = "Show paths as absolute paths, in the TUI and in plain and JSON output"
+    )]
+    absolute_path: bool,
+    #[arg(
+        long,
+        help = "Show paths relative to the current directory (TUI key: a cycles the styles)"
+    )]
+    relative_path: bool,
+    #[arg(
+        short = '0',
+        long,
+        help = "End each path in plain output with a NUL byte instead of a newline or ':', for xargs -0"
+    )]
+    null: bool,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        help = "Make the paths of plain output on a terminal links to FORMAT, a URL with {path}, {line}, {column} and {host} filled in, or one of file, vscode, vscodium, cursor, idea, textmate, macvim and kitty"
+    )]
+    hyperlink_format: Option<String>,
+    #[arg(
+        short = 'A',
+        long,
+        value_name = "NUM",
+        help = "Show NUM lines after each match"
+    )]
+    after_context: Option<usize>,
+    #[arg(
+        short = 'B',
+        long,
+        value_name = "NUM",
+        help = "Show NUM lines before each match"
+    )]
+    before_context: Option<usize>,
+    #[arg(
+        short = 'C',
+        long,
+        value_name = "NUM",
+        help = "Show NUM lines before and after each match"
+    )]
+    context: Option<usize>,
+    #[arg(
+        short = 'g',
+        long = "glob",
+        value_name = "GLOB",
+        help = "Only search files matching GLOB; prefix with ! to exclude (repeatable)"
+    )]
+    globs: Vec<String>,
+    #[arg(
+        short = 't',
+        long = "type",
+        value_name = "TYPE",
+        help = "Only search files of TYPE, e.g. rust or py (repeatable)"
+    )]
+    types: Vec<String>,
+    #[arg(
+        long,
+        value_name = "NAME:GLOB",
+        help = "Define or extend a file type, e.g. 'web:*.{html,css}' (repeatable)"
+    )]
+    type_add: Vec<String>,
+    #[arg(long, help = "List the known file types and exit")]
+    type_list: bool,
+    #[arg(
+        long,
+        help = "List the patterns searched in the TUI, oldest first, and exit"
+    )]
+    history: bool,
+    #[arg(
+        short = 'r',
+        long,
+        value_name = "TEMPLATE",
+        help = "Preview replacing every match with TEMPLATE ($1, ${name} refer to capture groups)"
+    )]
+    replace: Option<String>,
+    #[arg(
+        long,
+        requires = "replace",
+        help = "Allow applying replacements from the TUI (r: match, R: file)"
+    )]
+    write: bool,
+    #[arg(
+        long,
+        requires = "replace",
+        conflicts_with_all = ["write", "count", "count_matches", "files_with_matches", "files_without_match", "quiet"],
+        help = "Apply the replacements to every file without the TUI and print what changed"
+    )]
+    write_changes: bool,
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        requires = "replace",
+        help = "Keep a copy of each rewritten file with SUFFIX appended to its name, e.g. .bak"
+    )]
+    backup_suffix: Option<String>,
+    #[arg(
+        short = 'U',
+        long,
+        help = "Match across line boundaries by searching whole files"
+    )]
+    multiline: bool,
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "How big the compiled pattern may get, e.g. 100M for large alternations such as those of -f files; K, M and G suffixes are allowed"
+    )]
+    regex_size_limit: Option<u64>,
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "How big the cache of the matcher's lazy DFA may grow per search thread, e.g. 10M; more can speed up large patterns"
+    )]
+    dfa_size_limit: Option<u64>,
+    #[cfg(feature = "pcre2")]
+    #[arg(
+        short = 'P',
+        long,
+        conflicts_with_all = ["regex_size_limit", "dfa_size_limit"],
+        help = "Match with PCRE2, which has look-around and backreferences but gives up linear-time matching"
+    )]
+    pcre2: bool,
+    #[arg(
+        short = 'v',
+        long,
+        conflicts_with_all = ["multiline", "replace", "count_matches"],
+        help = "Show the lines that don't match the pattern"
+    )]
+    invert_match: bool,
+    #[arg(
+        short = 'q',
+        long,
+        help = "Print nothing and exit with 0 as soon as something matches, 1 if nothing does"
+    )]
+    quiet: bool,
+    #[arg(
+        short = 'c',
+        long,
+        help = "Print the number of matching lines per file and in total instead of the matches"
+    )]
+    count: bool,
+    #[arg(
+        long,
+        help = "Like --count, but count every match rather than matching lines"
+    )]
+    count_matches: bool,
+    #[arg(
+        short = 'm',
+        long,
+        value_name = "NUM",
+        help = "Stop searching a file after NUM matching lines"
+    )]
+    max_count: Option<usize>,
+    #[arg(
+        long,
+        value_name = "NUM",
+        help = "Stop the whole search after NUM matching lines"
+    )]
+    max_total: Option<usize>,
+    #[arg(
+        short = 'l',
+        long,
+        conflicts_with_all = ["count", "count_matches"],
+        help = "Only list the files that match"
+    )]
+    files_with_matches: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["count", "count_matches", "files_with_matches"],
+        help = "Only list the files that don't match"
+    )]
+    files_without_match: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["count", "count_matches", "files_with_matches", "files_without_match", "replace", "multiline"],
+        help = "Match the pattern against the paths of files below PATH instead of their contents, and list those that match"
+    )]
+    files: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Save the pattern, flags and matches of the search to FILE to reopen later"
+    )]
+    save_session: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["positional", "paths", "regexp", "file", "save_session"],
+        help = "Show the results saved with --save-session instead of searching"
+    )]
+    load_session: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["quiet", "count", "count_matches", "files_with_matches", "files_without_match", "files", "write_changes", "output_template", "write", "watch"],
+        help = "Show the matches added and removed since the session saved in FILE, searching its patterns and paths again when none are given"
+    )]
+    baseline: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Ignore the config file ($RSTR_CONFIG or ~/.config/rstr/config.toml)"
+    )]
+    no_config: bool,
+    #[command(subcommand)]
+    tool: Option<Tool>,
+    /// The named rules the patterns are, for searches of a preset such as
+    /// `rstr secrets`.
+    #[arg(skip)]
+    rules: Option<&'static [presets::Rule]>,
+    /// Print the matching lines that are in several files, for `rstr dupes`.
+    #[arg(skip)]
+    dupes: bool,
+}
 
-            if let Ok(file) = File::open(path) {
-                let reader = BufReader::new(file);
-                for (i, line) in reader.lines().enumerate() {
-                    if let Ok(line) = line {
-                        if regex.is_match(&line) {
-                            results.push(format!("{}:{} : {}", path.display(), i + 1, line));
-                        }
-                    }
-                }
+/// What rstr can do besides searching; a first argument of their name is
+/// taken for them rather than for a path or pattern.
+#[derive(Subcommand)]
+enum Tool {
+    /// Print the completion script for SHELL, with the file types and
+    /// themes known now
+    Completions { shell: completions::Shell },
+    /// Print the man page, in roff, e.g. for `rstr man | man -l -`
+    Man,
+    /// Search for credentials: AWS keys, private keys, JWTs and API tokens,
+    /// each result named by the rule it matches
+    Secrets {
+        /// Paths and flags, as for a search
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            value_name = "ARGS"
+        )]
+        args: Vec<OsString>,
+    },
+    /// Search for TODO, FIXME, HACK and XXX markers, each result named by
+    /// its tag; Markdown output is a checklist grouped by tag and file
+    Todos {
+        /// Paths and flags, as for a search
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            value_name = "ARGS"
+        )]
+        args: Vec<OsString>,
+    },
+    /// Print the lines found in more than one file, grouped, to find
+    /// copied code or config; with a pattern, only the lines it matches
+    Dupes {
+        /// Paths and flags, as for a search
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            value_name = "ARGS"
+        )]
+        args: Vec<OsString>,
+    },
+}
+
+/// What `rstr dupes` searches when no pattern is given: lines of at least
+/// 8 characters once trimmed, which leaves out the likes of a lone `}`.
+const DUPES_PATTERN: &str = r"\S.{6,}\S";
+
+impl Tool {
+    /// The arguments given after a command that searches like the rest.
+    fn search_args(&self) -> Option<&[OsString]> {
+        match self {
+            Tool::Secrets { args } | Tool::Todos { args } | Tool::Dupes { args } => Some(args),
+            Tool::Completions { .. } | Tool::Man => None,
+        }
+    }
+
+    /// The rules of a preset search.
+    fn rules(&self) -> Option<&'static [presets::Rule]> {
+        match self {
+            Tool::Secrets { .. } => Some(presets::SECRETS),
+            Tool::Todos { .. } => Some(presets::TODOS),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// `path:line:content`, like grep
+    Plain,
+    /// One JSON object per match (JSON Lines)
+    Json,
+    /// A section per file with its matches in a code block
+    Markdown,
+    /// Comma-separated path,line,column,text, with a header
+    Csv,
+    /// Tab-separated path, line, column and text, with a header
+    Tsv,
+    /// A SARIF 2.1.0 report, for code scanning
+    Sarif,
+}
+
+impl Format {
+    /// The format a file's extension names, plain for any other.
+    fn from_path(path: &Path) -> Format {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        match extension.to_ascii_lowercase().as_str() {
+            "json" | "jsonl" => Format::Json,
+            "md" | "markdown" => Format::Markdown,
+            "csv" => Format::Csv,
+            "tsv" => Format::Tsv,
+            "sarif" => Format::Sarif,
+            _ => Format::Plain,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("rstr: {}", err);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Where among `flags` the command is, e.g. `secrets`: the first token that
+/// clap reads as one when parsing `flags` up to it. Its arguments after it
+/// are taken as given rather than as clap's matches have them, which drop a
+/// `--`.
+fn tool_position(program: Option<&OsString>, flags: &[OsString]) -> Option<usize> {
+    let mut command = Cli::command();
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|tool| tool.get_name().to_string())
+        .collect();
+    (0..flags.len())
+        .filter(|&at| names.iter().any(|name| flags[at] == name.as_str()))
+        .find(|&at| {
+            command
+                .try_get_matches_from_mut(program.into_iter().chain(&flags[..=at]))
+                .is_ok_and(|matches| matches.subcommand_name().is_some())
+        })
+}
+
+/// Runs rstr; plain output exits with 0 when something matched and 1 when
+/// nothing did, errors exit with 2.
+fn run() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    // the config file's flags go first so the real command line overrides them
+    let config = if env::args_os().any(|arg| arg == "--no-config") {
+        config::Config::default()
+    } else {
+        config::load(&Cli::command())?
+    };
+    let mut argv = env::args_os();
+    let program = argv.next();
+    let flags: Vec<OsString> = config.args.iter().map(OsString::from).chain(argv).collect();
+    let mut args = Cli::parse_from(program.iter().cloned().chain(flags.iter().cloned()));
+    // a preset searches its rules' patterns, and dupes any line long enough
+    // unless given some, with the flags before it and the arguments after it
+    // as for any search
+    if args.tool.as_ref().and_then(Tool::search_args).is_some() {
+        let at = tool_position(program.as_ref(), &flags)
+            .ok_or("couldn't find the command among the arguments")?;
+        let (before, rest) = (&flags[..at], flags[at + 1..].to_vec());
+        let rules = args.tool.as_ref().and_then(Tool::rules);
+        let dupes = matches!(args.tool, Some(Tool::Dupes { .. }));
+        let patterns: Vec<&str> = match rules {
+            Some(rules) => rules.iter().map(|rule| rule.pattern).collect(),
+            None => vec![DUPES_PATTERN],
+        };
+        args = Cli::parse_from(
+            program
+                .into_iter()
+                .chain(before.iter().cloned())
+                .chain(patterns.iter().map(|p| format!("--regexp={}", p).into()))
+                .chain(rest),
+        );
+        args.rules = rules;
+        args.dupes = dupes;
+        if args.dupes
+            && (args.regexp.len() > 1 || !args.file.is_empty())
+            && let Some(i) = args.regexp.iter().position(|p| p == DUPES_PATTERN)
+        {
+            args.regexp.remove(i);
+        }
+    }
+    args.no_ignore |= args.unrestricted >= 1;
+    args.hidden |= args.unrestricted >= 2;
+
+    let mut types = TypeRegistry::new();
+    for definition in &args.type_add {
+        types.add(definition)?;
+    }
+    if let Some(Tool::Man) = args.tool {
+        ignore_broken_pipe(output::print_lines(&[man::page(&Cli::command())
+            .trim_end()
+            .to_string()]))?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(Tool::Completions { shell }) = args.tool {
+        let mut themes: Vec<String> = theme::BUILTIN.iter().map(|t| t.to_string()).collect();
+        themes.extend(config.themes.keys().cloned());
+        let values = [("types", types.names()), ("theme", themes)];
+        let script = completions::script(shell, &Cli::command(), &values);
+        ignore_broken_pipe(output::print_lines(&[script.trim_end().to_string()]))?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.type_list {
+        ignore_broken_pipe(output::print_lines(&types.list()))?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.history {
+        ignore_broken_pipe(output::print_lines(&history::load()))?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let loaded = match &args.load_session {
+        Some(file) => Some(session::load(file)?),
+        None => None,
+    };
+    let baseline = match &args.baseline {
+        Some(file) => Some(session::load(file)?),
+        None => None,
+    };
+
+    let mut given = args.regexp.clone();
+    for file in &args.file {
+        given.extend(read_patterns(&args, file)?);
+    }
+    let patterns_from_stdin = args.file.iter().any(|file| file == Path::new("-"));
+
+    // clap requires at least one argument unless --type-list, --history, -e
+    // or -f was given; the last is the pattern, unless -e or -f gave them
+    let mut positional = args.positional.clone();
+    let (mut paths, patterns) = match &loaded {
+        // clap keeps --load-session apart from paths and patterns
+        Some(session) => (
+            session.paths.iter().map(PathBuf::from).collect(),
+            session.patterns.clone(),
+        ),
+        None if !args.regexp.is_empty() || !args.file.is_empty() => (positional, given),
+        // the baseline's search run again, with the flags given now
+        None if positional.is_empty()
+            && let Some(baseline) = &baseline =>
+        {
+            (
+                baseline.paths.iter().map(PathBuf::from).collect(),
+                baseline.patterns.clone(),
+            )
+        }
+        None => {
+            let pattern = positional
+                .pop()
+                .ok_or("no pattern given")?
+                .into_os_string()
+                .into_string()
+                .map_err(|_| "the pattern isn't valid UTF-8")?;
+            (positional, vec![pattern])
+        }
+    };
+    if loaded.is_none() {
+        paths.splice(0..0, args.paths.iter().cloned());
+    }
+    if paths.is_empty() {
+        let piped = !io::stdin().is_terminal() && !patterns_from_stdin;
+        paths.push(PathBuf::from(if piped { "-" } else { "." }));
+    }
+    if patterns.is_empty() {
+        return Err("the pattern files are empty".into());
+    }
+    let source = match paths.as_slice() {
+        [path] if path == Path::new("-") => {
+            if patterns_from_stdin {
+                return Err(
+                    "standard input can't hold both the patterns and the text to search".into(),
+                );
             }
+            Source::Stdin
+        }
+        _ if paths.iter().any(|path| path == Path::new("-")) => {
+            return Err("standard input can't be searched along with other paths".into());
+        }
+        [path] => Source::Path(path.clone()),
+        _ => Source::Paths(paths.clone()),
+    };
+    if source == Source::Stdin && (args.write || args.write_changes) {
+        return Err("--write can't rewrite standard input".into());
+    }
+    if source == Source::Stdin && args.files {
+        return Err("--files matches the paths of files and can't search standard input".into());
+    }
+    if source == Source::Stdin && args.watch {
+        return Err("--watch can't watch standard input".into());
+    }
+    if source == Source::Stdin && args.tail {
+        return Err("--tail follows files and can't follow standard input".into());
+    }
+    if loaded.is_none() {
+        for path in source.roots() {
+            if let Err(err) = path.metadata() {
+                return Err(format!("{}: {}", path.display(), err).into());
+            }
+        }
+    }
+    let (regex, pattern_set) = build_regex(&args, &patterns).map_err(|err| {
+        // syntax errors point at the problem, which the gist would leave out
+        let message = match &err {
+            PatternError::Regex(regex::Error::CompiledTooBig(_)) => err.gist(),
+            PatternError::Regex(err) => err.to_string(),
+            #[cfg(feature = "pcre2")]
+            PatternError::Pcre2(err, _) => err.to_string(),
+        };
+        match err.hint() {
+            Some(hint) => format!("{}\nhint: {}", message, hint),
+            None => message,
+        }
+    })?;
+    let theme = Theme::resolve(&args.theme, &config.themes)?;
+    let encoding = match args.encoding.as_str() {
+        "auto" => None,
+        name => Some(Encoding::from_name(name).ok_or_else(|| {
+            format!(
+                "unknown encoding '{}', expected auto, utf-8, utf-16le, utf-16be or latin1",
+                name
+            )
+        })?),
+    };
+
+    let walk_options = WalkOptions {
+        hidden: args.hidden,
+        no_ignore: args.no_ignore,
+        // both are relative to the root being walked, see WalkOptions::rooted_at
+        ignore: Arc::new(Gitignore::parse(&paths[0], &config.ignore.join("\n"))),
+        overrides: Arc::new(Overrides::new(&paths[0], &args.globs)?),
+        types: Arc::new(types.select(&args.types)?),
+        max_depth: args.max_depth,
+        follow: args.follow,
+        max_filesize: args.max_filesize,
+        min_filesize: args.min_size,
+        newer_than: args.newer_than,
+        older_than: args.older_than,
+        #[cfg(unix)]
+        owner: args.owner,
+        #[cfg(unix)]
+        mode: args.perm,
+        path_regex: args.path_regex.clone(),
+        only: git_files(&args, &source)?.map(Arc::new),
+        order: if args.sort_files {
+            Order::Sorted
+        } else if args.breadth_first {
+            Order::BreadthFirst
+        } else {
+            Order::Any
+        },
+    };
+    let mut options = SearchOptions::new(source, regex);
+    options.walk = walk_options;
+    if let Some(threads) = args.threads {
+        options.threads = threads;
+    }
+    let counting = args.count || args.count_matches;
+    let listing = args.files_with_matches || args.files_without_match || args.files;
+    // one match is all it takes to list a file
+    options.max_count = if listing { Some(1) } else { args.max_count };
+    options.max_total = args.max_total;
+    if !counting && !listing && !args.only_matching && !args.dupes {
+        options.before_context = args.before_context.or(args.context).unwrap_or(0);
+        options.after_context = args.after_context.or(args.context).unwrap_or(0);
+    }
+    options.replace = args.replace.clone();
+    options.multiline = args.multiline;
+    options.invert = args.invert_match;
+    options.patterns = pattern_set;
+    options.encoding = encoding;
+    options.archives = args.search_zip;
+    options.compressed = args.search_compressed;
+    options.match_paths = args.files;
+    options.dedupe = !args.no_dedupe;
+    options.definitions = args.show_function;
+    let count_regex = args.count_matches.then(|| options.regex.clone());
+    let plain = args.no_tui
+        || args.quiet
+        || args.format.is_some()
+        || args.output.is_some()
+        || counting
+        || args.files_without_match
+        || args.write_changes
+        || args.null
+        || args.output_template.is_some()
+        || args.baseline.is_some()
+        || args.dupes
+        || !io::stdout().is_terminal();
+    if plain && args.write {
+        return Err("--write applies replacements interactively and needs the TUI".into());
+    }
+    if plain && args.watch {
+        return Err("--watch updates the results in the TUI and needs it".into());
+    }
+    let format = match (args.format, &args.output) {
+        (Some(format), _) => format,
+        (None, Some(file)) => Format::from_path(file),
+        (None, None) => Format::Plain,
+    };
+    let hyperlinks = args
+        .hyperlink_format
+        .as_deref()
+        .map(output::Hyperlinks::parse)
+        .transpose()?;
+    if format == Format::Sarif
+        && (counting
+            || listing
+            || args.write_changes
+            || args.output_template.is_some()
+            || args.stats)
+    {
+        return Err(
+            "SARIF reports the matches themselves, without counts, file lists, \
+             --write-changes, --output-template or --stats"
+                .into(),
+        );
+    }
+
+    if args.tail && matches!(format, Format::Markdown | Format::Sarif) {
+        return Err("--tail goes on until stopped, which Markdown and SARIF output can't".into());
+    }
+    if baseline.is_some() && !matches!(format, Format::Plain | Format::Json) {
+        return Err("--baseline shows the changes as plain text or JSON".into());
+    }
+    if args.dupes && !matches!(format, Format::Plain | Format::Json) {
+        return Err("rstr dupes prints plain text or JSON".into());
+    }
+
+    let search = match loaded {
+        Some(session) => session::replay(session),
+        // the file count only feeds the TUI's progress gauge
+        None => spawn_search(options.clone(), !plain, args.watch, args.tail),
+    };
+    let (search, saving) = match args.save_session.clone() {
+        Some(file) => {
+            let session = session::Session {
+                patterns: patterns.clone(),
+                paths: paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect(),
+                args: flags
+                    .iter()
+                    .map(|f| f.to_string_lossy().into_owned())
+                    .collect(),
+                files: 0,
+                bytes: 0,
+                matches: Vec::new(),
+                marks: tui::Marks::new(),
+            };
+            let (search, saving) = session::record(search, session, file.clone());
+            (search, Some((file, saving)))
+        }
+        None => (search, None),
+    };
+
+    let search = if plain {
+        tui::Search {
+            events: output::report_errors(search.events, !args.no_messages, args.tail),
+            ..search
         }
+    } else {
+        search
+    };
+    // marks made in the TUI are kept with the session it saves or shows
+    let session_file = args.save_session.clone().or(args.load_session.clone());
+    let mut marks = None;
+    let code = if args.quiet {
+        // dropping the receiver after the first match stops the search
+        let found = search
+            .events
+            .iter()
+            .any(|event| matches!(event, SearchEvent::Match(_)));
+        exit_code(Ok(found))?
+    } else if plain {
+        let mut out =
+            output::open(args.output.as_deref(), args.tail).map_err(|err| match &args.output {
+                Some(file) => format!("{}: {}", file.display(), err),
+                None => err.to_string(),
+            })?;
+        let out = &mut *out;
+        let layout = output::Layout {
+            paths: path_style(&args),
+            line_numbers: !args.no_line_number,
+            column: args.column,
+            null: args.null,
+            only_matching: args.only_matching,
+            max_columns: max_columns(&args),
+            // links would end up as escapes in files and pipes
+            hyperlinks: hyperlinks.filter(|_| {
+                format == Format::Plain && args.output.is_none() && io::stdout().is_terminal()
+            }),
+            rules: args.rules.is_some(),
+        };
+        let stats = search.stats.clone();
+        let found = if let Some(baseline) = &baseline {
+            diff::print(out, search.events, &baseline.matches, format, layout)
+        } else if args.dupes {
+            output::print_dupes(out, search.events, format, layout)
+        } else if args.write_changes {
+            output::print_changes(out, search.events, format, args.backup_suffix.as_deref())
+        } else if listing {
+            output::print_files(
+                out,
+                search.events,
+                format,
+                layout,
+                args.files_without_match,
+                args.sort,
+            )
+        } else if counting {
+            output::print_counts(
+                out,
+                search.events,
+                format,
+                layout,
+                count_regex.as_ref(),
+                args.sort,
+            )
+        } else if let Some(template) = &args.output_template {
+            output::print_template(
+                out,
+                search.events,
+                &options.regex,
+                template,
+                layout,
+                args.sort,
+            )
+        } else {
+            let labels = match args.rules {
+                Some(rules) => presets::labels(rules, &patterns),
+                None => patterns.clone(),
+            };
+            output::print(out, search.events, format, layout, &labels, args.sort)
+        };
+        let found = found.and_then(|found| {
+            if args.stats {
+                output::print_stats(out, &stats, format)?;
+            }
+            Ok(found)
+        });
+        exit_code(found)?
+    } else {
+        let before = search.marks.clone();
+        let after = run_tui(args, config, search, options, paths, patterns, theme)?;
+        marks = (after != before).then_some(after);
+        ExitCode::SUCCESS
+    };
+
+    if let Some((file, saving)) = saving {
+        let saved = saving.join().expect("the session writer panicked");
+        saved.map_err(|err| format!("{}: {}", file.display(), err))?;
     }
+    if let Some(marks) = marks
+        && let Some(file) = &session_file
+    {
+        session::annotate(file, marks)?;
+    }
+    Ok(code)
+}
+
+/// Browses `search` in the TUI, which can start new searches with the same
+/// flags.
+fn run_tui(
+    args: Cli,
+    config: config::Config,
+    search: tui::Search,
+    options: SearchOptions,
+    paths: Vec<PathBuf>,
+    patterns: Vec<String>,
+    theme: Theme,
+) -> Result<tui::Marks, Box<dyn std::error::Error>> {
+    // several -e patterns are shown, and can be searched again, as one regex
+    let pattern = patterns.join("|");
+    // only the TUI has keys, so only it minds a bad [keys] table
+    let keys = Keymap::from_config(&config.keys)?;
+    // a preset's rules are no pattern anyone would type again
+    if args.rules.is_none() {
+        history::add(&pattern);
+    }
+    // the prompt's patterns are checked with the same flags as they're searched with
+    let args = Rc::new(args);
+    let check_args = Rc::clone(&args);
+    let mut terminal = tui::setup_terminal()?;
+    let ui_options = tui::UiOptions {
+        pattern,
+        patterns: match args.rules {
+            Some(rules) => presets::labels(rules, &patterns),
+            None if patterns.len() > 1 => patterns,
+            None => Vec::new(),
+        },
+        rules: args.rules.is_some(),
+        path: paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(" "),
+        editor: editor::resolve(args.editor.as_deref()),
+        allow_write: args.write,
+        backup_suffix: args.backup_suffix.clone(),
+        encoding: options.encoding,
+        sort: args.sort,
+        files_only: args.files_with_matches || args.files,
+        paths: path_style(&args),
+        line_numbers: !args.no_line_number,
+        only_matching: args.only_matching,
+        // a single match in a minified file shouldn't take over the list
+        max_columns: match args.max_columns {
+            None => Some(output::MaxColumns {
+                limit: TUI_MAX_COLUMNS,
+                preview: true,
+            }),
+            Some(_) => max_columns(&args),
+        },
+        stats: args.stats,
+        flags: active_flags(&args),
+        // --tail's results going on after the first scan are watched for too
+        watch: args.watch || args.tail,
+        literal: args.fixed_strings,
+        results_in_memory: args.results_in_memory,
+        theme,
+        keys,
+        history: history::load(),
+        start: Box::new(move |pattern, source| {
+            if source.roots().iter().any(|path| path == Path::new("-")) {
+                return Err("Standard input has already been read".to_string());
+            }
+            let (regex, _) =
+                build_regex(&args, &[pattern.to_string()]).map_err(|err| prompt_error(&err))?;
+            // globs and ignore rules from the config follow the roots walked,
+            // git is asked again what's tracked or changed there
+            let walk = WalkOptions {
+                only: git_files(&args, &source)?.map(Arc::new),
+                ..options.walk.clone()
+            };
+            let options = SearchOptions {
+                source,
+                regex,
+                walk,
+                patterns: None,
+                ..options.clone()
+            };
+            Ok(spawn_search(options, true, args.watch, args.tail))
+        }),
+        check: Box::new(move |pattern| {
+            let err = build_regex(&check_args, &[pattern.to_string()]).err()?;
+            Some(tui::PatternError {
+                message: prompt_error(&err),
+                span: err.span(pattern),
+            })
+        }),
+    };
+    // the terminal is given back whatever happened in the TUI
+    let ran = tui::run_ui(&mut terminal, &ui_options, search);
+    tui::restore_terminal(&mut terminal)?;
+    if let Some(message) = tui::take_deferred_panic() {
+        eprintln!("rstr: a background thread {}", message);
+    }
+    Ok(ran?)
+}
 
-    run_ui(&mut terminal, &args.pattern, results)?;
-    restore_terminal(&mut terminal)?;
+/// How `--absolute-path` and `--relative-path` say to show paths.
+fn path_style(args: &Cli) -> output::PathStyle {
+    if args.absolute_path {
+        output::PathStyle::Absolute
+    } else if args.relative_path {
+        output::PathStyle::Relative
+    } else {
+        output::PathStyle::Found
+    }
+}
 
-    Ok(())
+/// How `--max-columns` and `--max-columns-preview` say to cut down long
+/// lines, `None` to keep them whole.
+fn max_columns(args: &Cli) -> Option<output::MaxColumns> {
+    match args.max_columns {
+        None | Some(0) => None,
+        Some(limit) => Some(output::MaxColumns {
+            limit,
+            preview: args.max_columns_preview,
+        }),
+    }
 }
 
-fn setup_terminal() -> Result<Terminal<CrosstermBackend<io::Stdout>>, io::Error> {
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    Terminal::new(backend)
+/// The flags that change what a search matches or looks at, in their short
+/// form where there is one, for the TUI's status bar.
+fn active_flags(args: &Cli) -> Vec<String> {
+    let switches = [
+        (args.ignore_case, "-i"),
+        (args.smart_case, "-S"),
+        (args.fixed_strings, "-F"),
+        (args.word_regexp, "-w"),
+        (args.invert_match, "-v"),
+        (args.multiline, "-U"),
+        (args.hidden, "--hidden"),
+        (args.no_ignore, "--no-ignore"),
+        (args.follow, "-L"),
+        (args.no_dedupe, "--no-dedupe"),
+        (args.git_tracked, "--git-tracked"),
+        (args.search_zip, "-z"),
+        (args.search_compressed, "--search-compressed"),
+        (args.write, "--write"),
+        (args.watch, "--watch"),
+        (args.tail, "--tail"),
+    ];
+    let mut flags: Vec<String> = switches
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, flag)| flag.to_string())
+        .collect();
+    #[cfg(feature = "pcre2")]
+    if args.pcre2 {
+        flags.push("-P".to_string());
+    }
+    if let Some(revision) = &args.git_modified {
+        flags.push(format!("--git-modified={}", revision));
+    }
+    flags.extend(args.types.iter().map(|name| format!("-t {}", name)));
+    flags.extend(args.globs.iter().map(|glob| format!("-g {}", glob)));
+    flags
 }
 
-fn restore_terminal(
-    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
-) -> Result<(), io::Error> {
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
-    Ok(())
+/// Applies the flags to a pattern's source: escaped with `-F`, wrapped in word
+/// boundaries with `-w`.
+fn pattern_source(args: &Cli, pattern: &str) -> String {
+    let mut pattern = if args.fixed_strings {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    #[cfg(feature = "pcre2")]
+    if args.word_regexp && args.pcre2 {
+        // PCRE2 has no half boundaries, look-around does the same
+        return format!(r"(?<!\w)(?:{})(?!\w)", pattern);
+    }
+    if args.word_regexp {
+        // unlike `\b`, the half boundaries also work for patterns that start
+        // or end with a non-word character, e.g. `-w '$x'`
+        pattern = format!(r"\b{{start-half}}(?:{})\b{{end-half}}", pattern);
+    }
+    pattern
 }
 
-fn draw_loading(
-    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
-    pattern: &str,
-    current_file: &str,
-    animation_frame: usize,
-) -> io::Result<()> {
-    let dots = match animation_frame {
-        1 => ".  ",
-        2 => ".. ",
-        3 => "...",
-        _ => "   ",
+/// Compiles the patterns into one regex matching any of them and, when
+/// there are several, a set telling which of them matched. With `--pcre2`
+/// they're compiled by PCRE2, which has no sets.
+fn build_regex(
+    args: &Cli,
+    patterns: &[String],
+) -> Result<(Matcher, Option<RegexSet>), PatternError> {
+    let patterns: Vec<String> = patterns.iter().map(|p| pattern_source(args, p)).collect();
+    // -S looks at all of them, so `-S -e foo -e Bar` is case-sensitive
+    let smart_insensitive = args.smart_case && !patterns.iter().any(|p| has_uppercase(p));
+    let case_insensitive = args.ignore_case || smart_insensitive;
+    let combined = match patterns.as_slice() {
+        [pattern] => pattern.clone(),
+        _ => patterns
+            .iter()
+            .map(|p| format!("(?:{})", p))
+            .collect::<Vec<_>>()
+            .join("|"),
+    };
+    #[cfg(feature = "pcre2")]
+    if args.pcre2 {
+        let regex = pcre2::bytes::RegexBuilder::new()
+            .utf(true)
+            .ucp(true)
+            .caseless(case_insensitive)
+            .multi_line(args.multiline)
+            .jit_if_available(true)
+            .build(&combined)
+            .map_err(|err| PatternError::Pcre2(err, combined.clone()))?;
+        return Ok((Matcher::Pcre2(regex), None));
+    }
+    let mut builder = RegexBuilder::new(&combined);
+    builder
+        .case_insensitive(case_insensitive)
+        .multi_line(args.multiline);
+    if let Some(limit) = args.regex_size_limit {
+        builder.size_limit(limit as usize);
+    }
+    if let Some(limit) = args.dfa_size_limit {
+        builder.dfa_size_limit(limit as usize);
+    }
+    let regex = builder.build()?;
+    let set = if patterns.len() > 1 {
+        let mut builder = RegexSetBuilder::new(&patterns);
+        builder
+            .case_insensitive(case_insensitive)
+            .multi_line(args.multiline);
+        if let Some(limit) = args.regex_size_limit {
+            builder.size_limit(limit as usize);
+        }
+        if let Some(limit) = args.dfa_size_limit {
+            builder.dfa_size_limit(limit as usize);
+        }
+        Some(builder.build()?)
+    } else {
+        None
     };
+    Ok((regex.into(), set))
+}
 
-    terminal
-        .draw(|frame| {
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Length(3), Constraint::Min(0)])
-                .split(frame.area());
+/// Why patterns didn't compile, from the engine that was to match them.
+enum PatternError {
+    Regex(regex::Error),
+    /// With the pattern as compiled, which the error's offset is into.
+    #[cfg(feature = "pcre2")]
+    Pcre2(pcre2::Error, String),
+}
 
-            let header = Block::default()
-                .borders(Borders::ALL)
-                .title(format!(" Search term: '{}' (Exit: q) ", pattern));
+impl PatternError {
+    /// The error on one line, see [`error_gist`].
+    fn gist(&self) -> String {
+        match self {
+            PatternError::Regex(err) => error_gist(err),
+            #[cfg(feature = "pcre2")]
+            PatternError::Pcre2(err, _) => err.to_string(),
+        }
+    }
 
-            let loading_text = format!(" Searching{} ", dots);
-            let loading_content = format!("Current file: {}", current_file);
+    /// What to do about the error, see [`hint`].
+    fn hint(&self) -> Option<String> {
+        match self {
+            PatternError::Regex(err) => hint(err),
+            #[cfg(feature = "pcre2")]
+            PatternError::Pcre2(..) => None,
+        }
+    }
 
-            let loading = List::new(vec![ListItem::new(loading_content)])
-                .block(Block::default().borders(Borders::ALL).title(loading_text));
+    /// The characters of `pattern` the error points at, see [`error_span`].
+    /// PCRE2 has the offset into the pattern as compiled where it noticed
+    /// the mistake, usually just after it.
+    fn span(&self, pattern: &str) -> Option<Range<usize>> {
+        match self {
+            PatternError::Regex(err) => error_span(err, pattern),
+            #[cfg(feature = "pcre2")]
+            PatternError::Pcre2(err, source) => {
+                let offset = err.offset()?.checked_sub(source.find(pattern)?)?;
+                let end = pattern.get(..offset)?.chars().count();
+                Some(end.saturating_sub(1)..end)
+            }
+        }
+    }
+}
 
-            frame.render_widget(header, chunks[0]);
-            frame.render_widget(loading, chunks[1]);
-        })
-        .map(|_| ())
+impl From<regex::Error> for PatternError {
+    fn from(err: regex::Error) -> PatternError {
+        PatternError::Regex(err)
+    }
 }
 
-fn draw_results<'a>(
-    terminal: &'a mut Terminal<CrosstermBackend<Stdout>>,
-    pattern: &str,
-    results: &[String],
-) -> std::io::Result<CompletedFrame<'a>> {
-    terminal.draw(|frame| {
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Length(3), Constraint::Min(0)])
-            .split(frame.area());
+/// Reads the patterns of a `-f` file (stdin for `-`), one per line, and
+/// checks that each compiles so a bad one is reported by its line number.
+/// Like grep, an empty line matches everything.
+fn read_patterns(args: &Cli, file: &Path) -> Result<Vec<String>, String> {
+    let name = if file == Path::new("-") {
+        STDIN_PATH.to_string()
+    } else {
+        file.display().to_string()
+    };
+    let mut text = String::new();
+    let read = if file == Path::new("-") {
+        io::stdin().read_to_string(&mut text).map(|_| ())
+    } else {
+        fs::read_to_string(file).map(|contents| text = contents)
+    };
+    read.map_err(|err| format!("{}: {}", name, err))?;
 
-        let header = Block::default()
-            .borders(Borders::ALL)
-            .title(format!(" Search term: '{}' (Exit: q) ", pattern));
+    let patterns: Vec<String> = text
+        .lines()
+        .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+        .collect();
+    for (i, pattern) in patterns.iter().enumerate() {
+        if let Err(err) = build_regex(args, std::slice::from_ref(pattern)) {
+            let mut message = format!("{}:{}: invalid pattern: {}", name, i + 1, err.gist());
+            if let Some(hint) = err.hint() {
+                message = format!("{}\nhint: {}", message, hint);
+            }
+            return Err(message);
+        }
+    }
+    Ok(patterns)
+}
 
-        let items: Vec<ListItem> = results.iter().map(|r| ListItem::new(r.as_str())).collect();
+/// The last line of a regex error, which is all that's needed where there's
+/// no room for the multi-line syntax error with its caret.
+fn error_gist(err: &regex::Error) -> String {
+    if let regex::Error::CompiledTooBig(limit) = err {
+        return format!(
+            "the pattern is too big, compiling it takes more than {} of memory",
+            show_size(*limit as u64)
+        );
+    }
+    let message = err.to_string();
+    let gist = message.lines().last().unwrap_or_default();
+    gist.trim_start_matches("error: ").to_string()
+}
 
-        let list =
-            List::new(items).block(Block::default().borders(Borders::ALL).title(" Found in "));
+/// A pattern's error for the TUI, which shows it on one line, so with just
+/// the gist of syntax errors.
+fn prompt_error(err: &PatternError) -> String {
+    match err.hint() {
+        Some(hint) => format!("Invalid pattern: {} ({})", err.gist(), hint),
+        None => format!("Invalid pattern: {}", err.gist()),
+    }
+}
 
-        frame.render_widget(header, chunks[0]);
-        frame.render_widget(list, chunks[1]);
+/// The characters of `pattern` a syntax error points at with `^`s under
+/// the pattern it quotes, which flags like `-w` may have wrapped it in.
+fn error_span(err: &regex::Error, pattern: &str) -> Option<Range<usize>> {
+    let message = err.to_string();
+    let lines: Vec<&str> = message.lines().collect();
+    let carets = lines.iter().position(|line| {
+        let line = line.trim();
+        !line.is_empty() && line.chars().all(|c| c == '^')
+    })?;
+    let quoted = lines.get(carets.checked_sub(1)?)?;
+    let offset = quoted[..quoted.find(pattern)?].chars().count();
+    let caret_line = lines[carets];
+    let start = caret_line.find('^')?;
+    let start = caret_line[..start].chars().count().checked_sub(offset)?;
+    let len = caret_line.trim().chars().count();
+    let count = pattern.chars().count();
+    Some(start.min(count)..(start + len).min(count))
+}
+
+/// Why the regex engine rejects look-around and backreferences, which
+/// patterns written for PCRE often use, and what to do instead; or how to
+/// let a pattern that's too big compile anyway.
+fn hint(err: &regex::Error) -> Option<String> {
+    if let regex::Error::CompiledTooBig(limit) = err {
+        return Some(format!(
+            "raise the limit with --regex-size-limit, e.g. --regex-size-limit {}",
+            show_size(*limit as u64 * 10)
+        ));
+    }
+    let message = err.to_string();
+    let unsupported = message.contains("look-around") || message.contains("backreferences");
+    unsupported.then(|| {
+        let instead = if cfg!(feature = "pcre2") {
+            "rerun with -P/--pcre2 to match with PCRE2, which has them"
+        } else {
+            "match the surrounding text too and keep the part wanted with a capture group, \
+             e.g. --output-template '$1', or build rstr with the pcre2 feature for -P/--pcre2"
+        };
+        format!(
+            "rstr matches in linear time, which rules out look-around and backreferences; {}",
+            instead
+        )
     })
 }
 
-fn run_ui(
-    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
-    pattern: &str,
-    results: Vec<String>,
-) -> io::Result<()> {
-    loop {
-        draw_results(terminal, pattern, &results)?;
+/// `bytes` the way [`parse_size`] reads sizes, rounded up to whole units.
+fn show_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => bytes.to_string(),
+        1024..0x10_0000 => format!("{}K", bytes.div_ceil(1 << 10)),
+        0x10_0000..0x4000_0000 => format!("{}M", bytes.div_ceil(1 << 20)),
+        _ => format!("{}G", bytes.div_ceil(1 << 30)),
+    }
+}
 
-        if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
-                if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
-                    break;
+/// The files `--git-tracked` or `--git-modified` limit the search to, from
+/// every root.
+fn git_files(args: &Cli, source: &Source) -> Result<Option<HashSet<PathBuf>>, String> {
+    let (which, flag) = match &args.git_modified {
+        Some(revision) => (GitFiles::Modified(revision.clone()), "--git-modified"),
+        None if args.git_tracked => (GitFiles::Tracked, "--git-tracked"),
+        None => return Ok(None),
+    };
+    if *source == Source::Stdin {
+        return Err(format!(
+            "{} picks files and can't search standard input",
+            flag
+        ));
+    }
+    let mut files = HashSet::new();
+    // files given by name are searched whatever git says
+    for root in source.roots().iter().filter(|root| root.is_dir()) {
+        let found = git::files(root, &which)
+            .map_err(|err| format!("{}: {}: {}", flag, root.display(), err))?;
+        files.extend(found);
+    }
+    Ok(Some(files))
+}
+
+/// Compiles `--path-regex`, reporting just the gist of syntax errors.
+fn parse_path_regex(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|err| error_gist(&err))
+}
+
+/// Parses sizes like `512`, `64K`, `10M` or `2G` (powers of 1024).
+fn parse_size(size: &str) -> Result<u64, String> {
+    let (digits, shift) = match size.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&size[..i], 10),
+        Some((i, 'm' | 'M')) => (&size[..i], 20),
+        Some((i, 'g' | 'G')) => (&size[..i], 30),
+        _ => (size, 0),
+    };
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("'{}' isn't a size like 512, 64K, 10M or 2G", size))?;
+    value
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("'{}' is too large", size))
+}
+
+/// Parses `--newer-than` and `--older-than` times: how long ago, in
+/// seconds, minutes, hours, days or weeks (`90s`, `30m`, `12h`, `2d`,
+/// `1w`), or a UTC date with an optional time (`2024-01-01`,
+/// `2024-01-01T12:30`, `2024-01-01 12:30:45`).
+fn parse_time(text: &str) -> Result<SystemTime, String> {
+    let invalid = || format!("'{}' isn't a time like 2d, 12h or 2024-01-01", text);
+    if let Some(unit) = text.chars().last().filter(char::is_ascii_alphabetic) {
+        let seconds = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let count: u64 = text[..text.len() - 1].parse().map_err(|_| invalid())?;
+        let ago = count.checked_mul(seconds).ok_or_else(invalid)?;
+        return SystemTime::now()
+            .checked_sub(Duration::from_secs(ago))
+            .ok_or_else(invalid);
+    }
+    let (date, time) = text.split_once(['T', ' ']).unwrap_or((text, "00:00"));
+    let numbers = |text: &str, separator: char| -> Option<Vec<u64>> {
+        text.split(separator).map(|n| n.parse().ok()).collect()
+    };
+    let (Some(date), Some(time)) = (numbers(date, '-'), numbers(time, ':')) else {
+        return Err(invalid());
+    };
+    let (&[year, month, day], &[hour, minute, ref second @ ..]) = (&date[..], &time[..]) else {
+        return Err(invalid());
+    };
+    let second = match second {
+        [] => 0,
+        [second] => *second,
+        _ => return Err(invalid()),
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1970..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=month_days).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(invalid());
+    }
+    // days since the epoch of the proleptic Gregorian calendar, with
+    // years starting in March so leap days come last
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let days =
+        year * 365 + year / 4 - year / 100 + year / 400 + (153 * month + 2) / 5 + day - 1 - 719_468;
+    let seconds = days * 24 * 60 * 60 + hour * 60 * 60 + minute * 60 + second;
+    Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Parses `--owner`: a user name, looked up in the user database, or a
+/// numeric user ID.
+#[cfg(unix)]
+fn parse_owner(user: &str) -> Result<u32, String> {
+    if let Ok(id) = user.parse() {
+        return Ok(id);
+    }
+    let unknown = || format!("there's no user named '{}'", user);
+    let name = std::ffi::CString::new(user).map_err(|_| unknown())?;
+    // SAFETY: the name is NUL-terminated; the entry returned, if any, stays
+    // valid until the next lookup, and parsing runs on one thread
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    if entry.is_null() {
+        return Err(unknown());
+    }
+    // SAFETY: checked for null above
+    Ok(unsafe { (*entry).pw_uid })
+}
+
+/// Whether a pattern asks for uppercase letters, for `--smart-case`. Escapes
+/// like `\S` or `\p{Lu}` don't count.
+fn has_uppercase(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // \pL and \P{..} name a class rather than spelling letters
+            '\\' => {
+                if let Some('p' | 'P') = chars.next() {
+                    if chars.clone().next() == Some('{') {
+                        chars.find(|&c| c == '}');
+                    } else {
+                        chars.next();
+                    }
                 }
             }
+            c if c.is_uppercase() => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// How often `--watch` looks for changed files, and `--tail` for lines
+/// added to them.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many characters of a line the TUI shows without `--max-columns`.
+const TUI_MAX_COLUMNS: usize = 1000;
+
+/// Starts a search, along with a walk that doesn't read files so the TUI's
+/// progress gauge has a total if `count_files` is set. With `watch` it keeps
+/// sending the matches of files that change, with `tail` those of lines
+/// added to them.
+fn spawn_search(options: SearchOptions, count_files: bool, watch: bool, tail: bool) -> tui::Search {
+    let (count_tx, file_count) = mpsc::channel();
+    if count_files {
+        let counter = Searcher::new(options.clone());
+        thread::spawn(move || {
+            if let Some(count) = counter.count_files() {
+                let _ = count_tx.send(count);
+            }
+        });
+    }
+
+    let searcher = Searcher::new(options);
+    tui::Search {
+        stats: searcher.stats(),
+        cancel: searcher.canceller(),
+        events: if watch {
+            searcher.watch(WATCH_INTERVAL)
+        } else if tail {
+            searcher.tail(WATCH_INTERVAL)
+        } else {
+            searcher.spawn()
+        },
+        file_count,
+        marks: tui::Marks::new(),
+    }
+}
+
+/// Maps whether plain output found anything to the exit code.
+fn exit_code(found: io::Result<bool>) -> io::Result<ExitCode> {
+    match found {
+        Ok(true) => Ok(ExitCode::SUCCESS),
+        Ok(false) => Ok(ExitCode::from(1)),
+        // the reader only goes away early once it has seen matches
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(ExitCode::SUCCESS),
+        Err(err) => Err(err),
+    }
+}
+
+/// The reader going away (`rstr ... | head`) isn't an error worth reporting.
+fn ignore_broken_pipe(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(flags: &[&str]) -> Option<usize> {
+        let flags: Vec<OsString> = flags.iter().map(OsString::from).collect();
+        tool_position(Some(&OsString::from("rstr")), &flags)
+    }
+
+    #[test]
+    fn tool_position_is_the_token_clap_reads_as_the_command() {
+        assert_eq!(position(&["-i", "secrets", "--", "src"]), Some(1));
+        assert_eq!(position(&["-e", "todos", "todos", "src"]), Some(2));
+        assert_eq!(position(&["--glob", "dupes", "-i", "dupes"]), Some(3));
+        assert_eq!(position(&["--", "secrets"]), None);
+        assert_eq!(position(&["src", "secrets"]), None);
+    }
+
+    #[test]
+    fn sizes_take_binary_suffixes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("64K"), Ok(64 << 10));
+        assert_eq!(parse_size("10m"), Ok(10 << 20));
+        assert_eq!(parse_size("2G"), Ok(2 << 30));
+        for invalid in ["", "K", "1.5M", "-1", "10T", "99999999999G"] {
+            assert!(parse_size(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn times_are_utc_dates_or_how_long_ago() {
+        let at = |seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        assert_eq!(parse_time("1970-01-01"), Ok(at(0)));
+        assert_eq!(parse_time("2000-03-01"), Ok(at(951_868_800)));
+        assert_eq!(parse_time("2000-02-29"), Ok(at(951_782_400)));
+        assert_eq!(parse_time("2023-04-30"), Ok(at(1_682_812_800)));
+        assert_eq!(parse_time("2024-02-29T12:30"), Ok(at(1_709_209_800)));
+        assert_eq!(parse_time("2024-01-01 12:30:45"), Ok(at(1_704_112_245)));
+
+        let ago = SystemTime::now()
+            .duration_since(parse_time("2d").unwrap())
+            .unwrap();
+        // the clock may be stepped between the two readings
+        let two_days = Duration::from_secs(2 * 24 * 60 * 60);
+        assert!(
+            ago.abs_diff(two_days) < Duration::from_secs(60),
+            "{:?}",
+            ago
+        );
+        for invalid in [
+            "",
+            "5y",
+            "d",
+            "1969-12-31",
+            "2024-13-01",
+            "2024-01-32",
+            "2024-02-30",
+            "2023-02-29",
+            "2100-02-29",
+            "2023-04-31",
+            "2024-01-01T24:00",
+            "2024-01-01T12:30:45:00",
+            "2024-01",
+        ] {
+            assert!(parse_time(invalid).is_err(), "{:?}", invalid);
         }
     }
-    Ok(())
 }
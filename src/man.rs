@@ -0,0 +1,220 @@
+//! `rstr man`: the man page, in roff, written from the CLI's own definition
+//! so every flag is there with its help, followed by the config file's
+//! keys, the environment variables and the files rstr reads.
+
+use crate::keys;
+use crate::theme;
+use clap::{Arg, Command};
+use std::fmt::Write;
+
+/// The environment variables rstr reads, with what for.
+const ENVIRONMENT: &[(&str, &str)] = &[
+    (
+        "RSTR_CONFIG",
+        "The config file to read instead of the default one; empty for none.",
+    ),
+    (
+        "XDG_CONFIG_HOME",
+        "Where the config file and the global ignore file are, in rstr/ (default: ~/.config), \
+         and git's global excludes file, in git/ignore.",
+    ),
+    (
+        "XDG_DATA_HOME",
+        "Where the TUI keeps its search history, in rstr/history (default: ~/.local/share).",
+    ),
+    (
+        "VISUAL, EDITOR",
+        "The editor the TUI opens results in when --editor isn't given, VISUAL first.",
+    ),
+    (
+        "APPDATA",
+        "On Windows, where the config file and the history are when the above are unset.",
+    ),
+];
+
+/// The man page of `command`.
+pub fn page(command: &Command) -> String {
+    let mut command = command.clone();
+    // --help and --version are only there once built
+    command.build();
+    let name = command.get_name().to_string();
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        ".TH {} 1 \"\" \"{} {}\" \"User Commands\"",
+        name.to_uppercase(),
+        name,
+        command.get_version().unwrap_or("")
+    );
+    out.push_str(".SH NAME\n");
+    let about = command.get_about().map(|about| about.to_string());
+    let _ = writeln!(
+        out,
+        "{} \\- {}",
+        name,
+        escape(about.as_deref().unwrap_or(""))
+    );
+
+    out.push_str(".SH SYNOPSIS\n");
+    let usage = command.render_usage().to_string();
+    for line in usage.lines() {
+        let line = line.trim().trim_start_matches("Usage:").trim();
+        let _ = writeln!(out, ".B {}\n.br", escape(line));
+    }
+    for sub in command.get_subcommands() {
+        let _ = writeln!(out, ".B {} {}\n.br", name, escape(&synopsis(sub)));
+    }
+
+    out.push_str(".SH DESCRIPTION\n");
+    out.push_str(
+        "Searches the files below each PATH for lines matching PATTERN, a regular \
+         expression in the syntax of Rust's regex crate, skipping what .gitignore, \
+         .ignore and .rstrignore files exclude. On a terminal the results are shown in \
+         a TUI to browse, filter, preview and search again in; otherwise they're \
+         printed as path:line:content lines like grep's.\n",
+    );
+
+    out.push_str(".SH OPTIONS\n");
+    for arg in command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+    {
+        option(&mut out, arg);
+    }
+    for arg in command.get_positionals() {
+        out.push_str(".TP\n");
+        let _ = writeln!(out, "\\fI{}\\fR", value_names(arg).join(" "));
+        let _ = writeln!(out, "{}", escape(&help(arg)));
+    }
+
+    if command.get_subcommands().next().is_some() {
+        out.push_str(".SH COMMANDS\n");
+        for sub in command.get_subcommands() {
+            out.push_str(".TP\n");
+            let _ = writeln!(out, "\\fB{}\\fR", escape(&synopsis(sub)));
+            let about = sub.get_about().map(|about| about.to_string());
+            let _ = writeln!(out, "{}", escape(about.as_deref().unwrap_or("")));
+        }
+    }
+
+    out.push_str(".SH CONFIGURATION\n");
+    let _ = writeln!(
+        out,
+        "Defaults are read from ~/.config/rstr/config.toml, a TOML file. Its top-level \
+         keys are long flag names without the dashes, set to true for switches, to a \
+         value for flags that take one and to an array for repeatable ones, e.g. \
+         smart-case = true or glob = [\"!*.min.js\"]. The command line goes on top of \
+         them.\n.TP\n.B ignore\nAn array of gitignore rules applied to every search.\n\
+         .TP\n.B [themes.NAME]\nA colour theme for \\-\\-theme: base names the built-in one \
+         it starts from ({}), and the styles {} override it. A style is modifiers \
+         (bold, dim, italic, underlined, reversed), a colour and on a background colour; \
+         colours are names, #rrggbb or 256-colour indices, e.g. \"bold black on #ffd75f\".\n\
+         .TP\n.B [keys]\nThe keys of the TUI's commands, e.g. quit = [\"q\", \"ctrl-q\"]: \
+         characters, key names such as PageDown or Space, and ctrl-, alt- or shift- \
+         chords. The commands are {}.",
+        theme::BUILTIN.join(", "),
+        escape(&theme::STYLES.join(", ")),
+        escape(&keys::names().collect::<Vec<_>>().join(", "))
+    );
+
+    out.push_str(".SH ENVIRONMENT\n");
+    for (variable, what) in ENVIRONMENT {
+        let _ = writeln!(out, ".TP\n.B {}\n{}", variable, escape(what));
+    }
+
+    out.push_str(".SH FILES\n");
+    for (file, what) in [
+        ("~/.config/rstr/config.toml", "The config file."),
+        (
+            "~/.config/rstr/ignore",
+            "Gitignore rules applied to every search.",
+        ),
+        (
+            "~/.local/share/rstr/history",
+            "The patterns searched in the TUI, for --history and recalling them.",
+        ),
+    ] {
+        let _ = writeln!(out, ".TP\n.I {}\n{}", file, escape(what));
+    }
+
+    out.push_str(".SH EXIT STATUS\n");
+    out.push_str(
+        "Printing results, 0 when something matched and 1 when nothing did; 2 on errors, \
+         such as an invalid pattern or a missing path. The TUI exits with 0.\n",
+    );
+    out
+}
+
+/// An option's entry: its spellings and value, then its help and the
+/// values it takes.
+fn option(out: &mut String, arg: &Arg) {
+    let mut names = Vec::new();
+    if let Some(short) = arg.get_short() {
+        names.push(format!("\\fB\\-{}\\fR", short));
+    }
+    if let Some(long) = arg.get_long() {
+        names.push(format!("\\fB\\-\\-{}\\fR", escape(long)));
+    }
+    let mut heading = names.join(", ");
+    if arg.get_action().takes_values() {
+        for value in value_names(arg) {
+            let _ = write!(heading, " \\fI{}\\fR", escape(&value));
+        }
+    }
+    let _ = writeln!(out, ".TP\n{}\n{}", heading, escape(&help(arg)));
+    let values: Vec<_> = arg
+        .get_possible_values()
+        .into_iter()
+        .filter(|value| !value.is_hide_set())
+        .collect();
+    if values.is_empty() || !arg.get_action().takes_values() {
+        return;
+    }
+    out.push_str(".RS\n");
+    for value in values {
+        let _ = write!(out, ".TP\n.B {}\n", escape(value.get_name()));
+        if let Some(help) = value.get_help() {
+            let _ = writeln!(out, "{}", escape(&help.to_string()));
+        }
+    }
+    out.push_str(".RE\n");
+}
+
+/// Its long help if it has one, else its help.
+fn help(arg: &Arg) -> String {
+    arg.get_long_help()
+        .or(arg.get_help())
+        .map(|help| help.to_string())
+        .unwrap_or_default()
+}
+
+fn value_names(arg: &Arg) -> Vec<String> {
+    match arg.get_value_names() {
+        Some(names) => names.iter().map(|name| name.to_string()).collect(),
+        None => vec![arg.get_id().to_string().to_uppercase()],
+    }
+}
+
+/// A subcommand with its arguments, `completions SHELL`.
+fn synopsis(command: &Command) -> String {
+    let mut synopsis = command.get_name().to_string();
+    for arg in command.get_positionals() {
+        synopsis.push(' ');
+        synopsis.push_str(&value_names(arg).join(" "));
+    }
+    synopsis
+}
+
+/// Escapes what roff would take for requests or escapes: backslashes,
+/// dashes, and dots or quotes that start a line.
+fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    escaped
+        .lines()
+        .map(|line| match line.starts_with(['.', '\'']) {
+            true => format!("\\&{}", line),
+            false => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,207 @@
+//! The pattern a search matches with, see `SearchOptions::regex`: the
+//! `regex` crate's, which matches in linear time, or with the `pcre2`
+//! feature a PCRE2 one, which adds look-around and backreferences.
+
+use regex::Regex;
+use std::ops::Range;
+
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    Regex(Regex),
+    #[cfg(feature = "pcre2")]
+    Pcre2(pcre2::bytes::Regex),
+}
+
+impl Matcher {
+    /// The pattern as it was compiled.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Matcher::Regex(regex) => regex.as_str(),
+            #[cfg(feature = "pcre2")]
+            Matcher::Pcre2(regex) => regex.as_str(),
+        }
+    }
+
+    /// The pattern when it's the `regex` crate's.
+    pub fn as_regex(&self) -> Option<&Regex> {
+        match self {
+            Matcher::Regex(regex) => Some(regex),
+            #[cfg(feature = "pcre2")]
+            Matcher::Pcre2(_) => None,
+        }
+    }
+
+    /// The byte ranges of the successive matches in `text`. PCRE2 gives up
+    /// on text that takes it too long to match, which then has no more.
+    pub fn find_ranges(&self, text: &str) -> Vec<Range<usize>> {
+        match self {
+            Matcher::Regex(regex) => regex.find_iter(text).map(|found| found.range()).collect(),
+            #[cfg(feature = "pcre2")]
+            Matcher::Pcre2(regex) => regex
+                .find_iter(text.as_bytes())
+                .map_while(Result::ok)
+                .map(|found| found.start()..found.end())
+                .collect(),
+        }
+    }
+
+    /// The capture groups of the successive matches in `text`.
+    pub fn captures_iter<'a>(&'a self, text: &'a str) -> Vec<Captures<'a>> {
+        match self {
+            Matcher::Regex(regex) => regex.captures_iter(text).map(Captures::Regex).collect(),
+            #[cfg(feature = "pcre2")]
+            Matcher::Pcre2(regex) => regex
+                .captures_iter(text.as_bytes())
+                .map_while(Result::ok)
+                .map(|captures| Captures::Pcre2 {
+                    text,
+                    groups: (0..captures.len())
+                        .map(|i| captures.get(i).map(|group| group.start()..group.end()))
+                        .collect(),
+                    names: regex.capture_names(),
+                })
+                .collect(),
+        }
+    }
+
+    /// `text` with every match replaced by `template`, expanded as
+    /// [`Captures::expand`] does.
+    pub fn replace_all(&self, text: &str, template: &str) -> String {
+        match self {
+            Matcher::Regex(regex) => regex.replace_all(text, template).into_owned(),
+            #[cfg(feature = "pcre2")]
+            Matcher::Pcre2(_) => {
+                let mut replaced = String::new();
+                let mut done = 0;
+                for captures in self.captures_iter(text) {
+                    let Some(whole) = captures.get(0) else {
+                        continue;
+                    };
+                    replaced.push_str(&text[done..whole.start]);
+                    captures.expand(template, &mut replaced);
+                    done = whole.end;
+                }
+                replaced.push_str(&text[done..]);
+                replaced
+            }
+        }
+    }
+}
+
+impl From<Regex> for Matcher {
+    fn from(regex: Regex) -> Matcher {
+        Matcher::Regex(regex)
+    }
+}
+
+/// The groups one match captured.
+pub enum Captures<'a> {
+    Regex(regex::Captures<'a>),
+    #[cfg(feature = "pcre2")]
+    Pcre2 {
+        text: &'a str,
+        groups: Vec<Option<Range<usize>>>,
+        names: &'a [Option<String>],
+    },
+}
+
+impl Captures<'_> {
+    /// The byte range of group `i`, 0 being the whole match; `None` if it
+    /// took no part in it.
+    pub fn get(&self, i: usize) -> Option<Range<usize>> {
+        match self {
+            Captures::Regex(captures) => captures.get(i).map(|group| group.range()),
+            #[cfg(feature = "pcre2")]
+            Captures::Pcre2 { groups, .. } => groups.get(i).cloned().flatten(),
+        }
+    }
+
+    /// Appends `template` to `dst` with `$1`, `${1}`, `$name` and `${name}`
+    /// replaced by what those groups captured and `$$` by `$`, the way
+    /// `regex::Captures::expand` reads them for either engine.
+    pub fn expand(&self, template: &str, dst: &mut String) {
+        match self {
+            Captures::Regex(captures) => captures.expand(template, dst),
+            #[cfg(feature = "pcre2")]
+            Captures::Pcre2 { text, names, .. } => {
+                let group = |name: &str| {
+                    let i = name
+                        .parse()
+                        .ok()
+                        .or_else(|| names.iter().position(|n| n.as_deref() == Some(name)))?;
+                    self.get(i).map(|range| &text[range])
+                };
+                expand(template, &group, dst);
+            }
+        }
+    }
+}
+
+/// [`Captures::expand`] for groups looked up by `group`.
+#[cfg(feature = "pcre2")]
+fn expand<'t>(template: &str, group: &dyn Fn(&str) -> Option<&'t str>, dst: &mut String) {
+    let mut rest = template;
+    while let Some(dollar) = rest.find('$') {
+        dst.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            dst.push('$');
+            rest = after;
+            continue;
+        }
+        let (name, after) = match rest.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(close) => (&braced[..close], &braced[close + 1..]),
+                None => ("", rest),
+            },
+            None => {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+        if name.is_empty() {
+            // not a reference, so the `$` is kept
+            dst.push('$');
+            continue;
+        }
+        dst.push_str(group(name).unwrap_or_default());
+        rest = after;
+    }
+    dst.push_str(rest);
+}
+
+#[cfg(all(test, feature = "pcre2"))]
+mod tests {
+    use super::*;
+
+    fn pcre2(pattern: &str) -> Matcher {
+        Matcher::Pcre2(
+            pcre2::bytes::RegexBuilder::new()
+                .utf(true)
+                .build(pattern)
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn pcre2_matches_with_look_around_and_backreferences() {
+        let matcher = pcre2(r"(?<=\$)\d+|(\w)\1");
+        assert_eq!(matcher.find_ranges("$12 and 34, boot"), [1..3, 13..15]);
+        assert!(matcher.as_regex().is_none());
+    }
+
+    #[test]
+    fn pcre2_replacements_expand_like_the_regex_crates() {
+        let template = "[$2-${1}$$${last}$ $first${9}]";
+        let text = "ab cd é";
+        let regex = Matcher::from(Regex::new(r"(?<first>\w)(?<last>\w)").unwrap());
+        let expected = regex.replace_all(text, template);
+        assert_eq!(expected, "[b-a$b$ a] [d-c$d$ c] é");
+        assert_eq!(
+            pcre2(r"(?<first>\w)(?<last>\w)").replace_all(text, template),
+            expected
+        );
+    }
+}
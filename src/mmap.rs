@@ -0,0 +1,101 @@
+//! File contents for searching: memory-mapped for large regular files,
+//! read into memory otherwise.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+
+/// Files smaller than this are cheaper to read than to map.
+const MMAP_THRESHOLD: u64 = 64 * 1024;
+
+pub(crate) enum Contents {
+    #[cfg(unix)]
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(unix)]
+            Contents::Mapped(map) => map,
+            Contents::Owned(bytes) => bytes,
+        }
+    }
+}
+
+/// Reads the file at `path`, mapping it when that's worthwhile and falling
+/// back to reading when mapping fails or the file isn't a regular one.
+pub(crate) fn read(path: &Path) -> io::Result<Contents> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+
+    #[cfg(unix)]
+    if metadata.is_file()
+        && metadata.len() >= MMAP_THRESHOLD
+        && let Ok(map) = Mmap::map(&file, metadata.len())
+    {
+        return Ok(Contents::Mapped(map));
+    }
+
+    let mut bytes = Vec::with_capacity(metadata.len().min(MMAP_THRESHOLD) as usize);
+    file.read_to_end(&mut bytes)?;
+    Ok(Contents::Owned(bytes))
+}
+
+/// A read-only private mapping of a whole file. If the file shrinks while
+/// mapped, touching the lost pages raises SIGBUS, a risk every mmap-based
+/// searcher accepts.
+#[cfg(unix)]
+pub(crate) struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl Mmap {
+    fn map(file: &File, len: u64) -> io::Result<Mmap> {
+        use std::os::unix::io::AsRawFd;
+
+        let len = usize::try_from(len).map_err(|_| io::Error::other("file too large to map"))?;
+        // SAFETY: a fresh read-only mapping of an open descriptor; `len` is
+        // non-zero since it's at least MMAP_THRESHOLD
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mmap { ptr, len })
+    }
+}
+
+#[cfg(unix)]
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the mapping covers `len` readable bytes until dropped
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: unmaps exactly the region mapped in `map`
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
@@ -0,0 +1,1297 @@
+use crate::replace::{self, Edit};
+use crate::sort::{FileMeta, SortBy, SortKey};
+use crate::{Format, json, sarif};
+use rstr::definitions::Definition;
+use rstr::matcher::Matcher;
+use rstr::{Match, STDIN_PATH, SearchEvent, SearchStats};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, LineWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+/// How paths are shown: as the search found them, i.e. relative to the
+/// path searched, or made absolute or relative to the current directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathStyle {
+    #[default]
+    Found,
+    Absolute,
+    Relative,
+}
+
+impl PathStyle {
+    pub fn name(self) -> &'static str {
+        match self {
+            PathStyle::Found => "as found",
+            PathStyle::Absolute => "absolute",
+            PathStyle::Relative => "relative",
+        }
+    }
+
+    /// The style after this one, for the TUI key that cycles through them.
+    pub fn next(self) -> PathStyle {
+        match self {
+            PathStyle::Found => PathStyle::Absolute,
+            PathStyle::Absolute => PathStyle::Relative,
+            PathStyle::Relative => PathStyle::Found,
+        }
+    }
+
+    /// `path` in this style. Standard input keeps its placeholder name.
+    pub fn show(self, path: &Path) -> Cow<'_, Path> {
+        if self == PathStyle::Found || path == Path::new(STDIN_PATH) {
+            return Cow::Borrowed(path);
+        }
+        let Ok(absolute) = std::path::absolute(path).map(|path| normalize(&path)) else {
+            return Cow::Borrowed(path);
+        };
+        match (self, current_dir()) {
+            (PathStyle::Relative, Some(cwd)) => Cow::Owned(relative_to(&absolute, cwd)),
+            _ => Cow::Owned(absolute),
+        }
+    }
+}
+
+/// Resolves the `..` in an absolute path without looking at the file
+/// system, as `cd` does.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+fn current_dir() -> Option<&'static Path> {
+    static CWD: OnceLock<Option<PathBuf>> = OnceLock::new();
+    CWD.get_or_init(|| env::current_dir().ok()).as_deref()
+}
+
+/// The path from `base` to `path`, both absolute, going up with `..` where
+/// they part ways.
+fn relative_to(path: &Path, base: &Path) -> PathBuf {
+    let (mut path_parts, mut base_parts) =
+        (path.components().peekable(), base.components().peekable());
+    while let (Some(a), Some(b)) = (path_parts.peek(), base_parts.peek())
+        && a == b
+    {
+        path_parts.next();
+        base_parts.next();
+    }
+    let relative: PathBuf = base_parts
+        .map(|_| Component::ParentDir)
+        .chain(path_parts)
+        .collect();
+    if relative.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        relative
+    }
+}
+
+/// How results are written: the paths in every format, and what follows
+/// them in plain output.
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    pub paths: PathStyle,
+    /// Include each line's number after the path in plain output.
+    pub line_numbers: bool,
+    /// Include the match's column after its line number.
+    pub column: bool,
+    /// End paths with a NUL byte instead of the `:`, `-` or newline that
+    /// usually follows, so they survive `xargs -0` whatever they contain.
+    pub null: bool,
+    /// Write each match's text on its own instead of the whole line, one
+    /// record per match.
+    pub only_matching: bool,
+    /// Cut down long lines in plain and Markdown output.
+    pub max_columns: Option<MaxColumns>,
+    /// Make the paths of plain output links the terminal can open.
+    pub hyperlinks: Option<Hyperlinks>,
+    /// The patterns are named rules, such as `rstr secrets`' ones, which
+    /// plain results name before their text and JSON ones along with their
+    /// indices.
+    pub rules: bool,
+}
+
+/// `--hyperlink-format`: the URL each path of plain output links to, with
+/// `{path}`, `{line}`, `{column}` and `{host}` filled in, written as an OSC 8
+/// hyperlink around the path.
+#[derive(Debug, Clone, Copy)]
+pub struct Hyperlinks {
+    format: &'static str,
+}
+
+/// The formats `--hyperlink-format` knows by name.
+const HYPERLINK_FORMATS: &[(&str, &str)] = &[
+    ("file", "file://{host}{path}"),
+    ("vscode", "vscode://file{path}:{line}:{column}"),
+    ("vscodium", "vscodium://file{path}:{line}:{column}"),
+    ("cursor", "cursor://file{path}:{line}:{column}"),
+    (
+        "idea",
+        "idea://open?file={path}&line={line}&column={column}",
+    ),
+    (
+        "textmate",
+        "txmt://open?url=file://{path}&line={line}&column={column}",
+    ),
+    (
+        "macvim",
+        "mvim://open?url=file://{path}&line={line}&column={column}",
+    ),
+    ("kitty", "file://{host}{path}#{line}"),
+];
+
+impl Hyperlinks {
+    /// The format named `format`, or `format` itself when it isn't a name.
+    pub fn parse(format: &str) -> Result<Self, String> {
+        let format = HYPERLINK_FORMATS
+            .iter()
+            .find(|(name, _)| *name == format)
+            .map_or(format, |(_, format)| format);
+        let mut rest = format;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed {{ in hyperlink format {}", format))?;
+            let name = &rest[start + 1..start + end];
+            if !["path", "line", "column", "host"].contains(&name) {
+                return Err(format!(
+                    "unknown placeholder {{{}}} in hyperlink format {}; \
+                     {{path}}, {{line}}, {{column}} and {{host}} are filled in",
+                    name, format
+                ));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        if !format.contains("{path}") {
+            return Err(format!("hyperlink format {} has no {{path}}", format));
+        }
+        // one format for the whole run, which Layout wants to copy around
+        Ok(Hyperlinks {
+            format: Box::leak(format.into()),
+        })
+    }
+
+    /// The URL for `line` and `column` of the file at `path`, which is made
+    /// absolute first.
+    fn url(self, path: &Path, line: usize, column: usize) -> String {
+        let path = match current_dir() {
+            Some(dir) if path.is_relative() => normalize(&dir.join(path)),
+            _ => path.to_path_buf(),
+        };
+        let path: Vec<String> = path
+            .to_string_lossy()
+            .split('/')
+            .map(sarif::percent_encode)
+            .collect();
+        self.format
+            .replace("{path}", &path.join("/"))
+            .replace("{line}", &line.to_string())
+            .replace("{column}", &column.to_string())
+            .replace("{host}", hostname())
+    }
+}
+
+/// This machine's name, which `file://` URLs name the file's host by, or
+/// nothing when it isn't known.
+fn hostname() -> &'static str {
+    static HOST: OnceLock<String> = OnceLock::new();
+    HOST.get_or_init(|| {
+        #[cfg(unix)]
+        {
+            let mut buf = [0u8; 256];
+            // SAFETY: the buffer is as long as we say; what doesn't fit in
+            // it is cut off rather than written past it
+            if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } == 0 {
+                let len = buf.iter().position(|&b| b == 0).unwrap_or(0);
+                return String::from_utf8_lossy(&buf[..len]).into_owned();
+            }
+        }
+        env::var("COMPUTERNAME").unwrap_or_default()
+    })
+}
+
+/// How plain output and the TUI cut down lines too long to read, such as
+/// those of minified files: left out altogether or, with `preview`, shown
+/// up to `limit` characters, followed by how much is missing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxColumns {
+    pub limit: usize,
+    pub preview: bool,
+}
+
+impl MaxColumns {
+    /// How many bytes of `line` to keep and what to say about the rest, or
+    /// `None` if it's short enough.
+    pub fn cut(self, line: &str) -> Option<(usize, String)> {
+        let (cut, _) = line.char_indices().nth(self.limit)?;
+        let rest = line[cut..].chars().count();
+        Some(if self.preview {
+            (cut, format!(" [... {} more chars]", rest))
+        } else {
+            (
+                0,
+                format!("[Omitted long line of {} chars]", self.limit + rest),
+            )
+        })
+    }
+}
+
+/// `line` cut down to `max`, when it's longer.
+pub fn clip(line: &str, max: Option<MaxColumns>) -> Cow<'_, str> {
+    match max.and_then(|max| max.cut(line)) {
+        Some((cut, note)) => Cow::Owned(format!("{}{}", &line[..cut], note)),
+        None => Cow::Borrowed(line),
+    }
+}
+
+impl Layout {
+    /// Writes `path` followed by `separator`, or by a NUL with `null`.
+    fn write_path(&self, out: &mut dyn Write, path: &Path, separator: &str) -> io::Result<()> {
+        self.write_path_at(out, path, (1, 1), separator)
+    }
+
+    /// Writes `path` as [`Layout::write_path`] does, linking to `line` and
+    /// `column` in it with `hyperlinks`.
+    fn write_path_at(
+        &self,
+        out: &mut dyn Write,
+        path: &Path,
+        (line, column): (usize, usize),
+        separator: &str,
+    ) -> io::Result<()> {
+        let separator = if self.null { "\0" } else { separator };
+        let shown = self.paths.show(path);
+        match self.hyperlinks {
+            Some(links) if path != Path::new(STDIN_PATH) => write!(
+                out,
+                "\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\{}",
+                links.url(path, line, column),
+                shown.display(),
+                separator
+            ),
+            _ => write!(out, "{}{}", shown.display(), separator),
+        }
+    }
+
+    /// `path` as a JSON string.
+    fn json_path(&self, path: &Path) -> String {
+        json::string(&self.paths.show(path).to_string_lossy())
+    }
+}
+
+/// Standard output, or the file given with `--output`, buffered; by the
+/// line with `lines`, for output that goes on until it's stopped.
+pub fn open(file: Option<&Path>, lines: bool) -> io::Result<Box<dyn Write>> {
+    Ok(match (file, lines) {
+        (Some(file), false) => Box::new(BufWriter::new(File::create(file)?)),
+        (Some(file), true) => Box::new(LineWriter::new(File::create(file)?)),
+        (None, false) => Box::new(BufWriter::new(io::stdout().lock())),
+        (None, true) => Box::new(LineWriter::new(io::stdout().lock())),
+    })
+}
+
+/// The matches among `events`, up to the search's end.
+fn matches(events: Receiver<SearchEvent>) -> impl Iterator<Item = Match> {
+    events
+        .into_iter()
+        .take_while(|event| !matches!(event, SearchEvent::Done))
+        .filter_map(|event| match event {
+            SearchEvent::Match(result) => Some(result),
+            _ => None,
+        })
+}
+
+/// Prints results of `patterns` to `out` in `format` as they arrive, laid
+/// out as `layout` says. With `sort` nothing is printed until the search is
+/// done. Returns whether there were any.
+pub fn print(
+    out: &mut dyn Write,
+    events: Receiver<SearchEvent>,
+    format: Format,
+    layout: Layout,
+    patterns: &[String],
+    sort: Option<SortBy>,
+) -> io::Result<bool> {
+    match sort {
+        Some(sort) => write_results(
+            out,
+            sorted(events, sort).into_iter(),
+            format,
+            layout,
+            patterns,
+        ),
+        None => write_results(out, matches(events), format, layout, patterns),
+    }
+}
+
+/// Writes `results` of `patterns` in `format`, laid out as `layout` says,
+/// e.g. for the TUI to export what it shows. Returns whether there were any.
+pub fn write_results(
+    out: &mut dyn Write,
+    results: impl Iterator<Item = Match>,
+    format: Format,
+    layout: Layout,
+    patterns: &[String],
+) -> io::Result<bool> {
+    match format {
+        Format::Markdown if layout.rules => return write_checklist(out, results, layout, patterns),
+        Format::Markdown => return write_markdown(out, results, layout),
+        Format::Sarif => return sarif::write(out, results, patterns, layout),
+        _ => {}
+    }
+    // where the previous plain group ended, to know when a `--` is due
+    let mut last: Option<(PathBuf, usize)> = None;
+    // and the definition last shown, which is only repeated once it changes
+    let mut shown_definition: Option<(PathBuf, usize)> = None;
+    let mut found = false;
+    // CSV and TSV start with a header
+    let mut first = true;
+
+    let mut write = |result: &Match| -> io::Result<()> {
+        found = true;
+        match format {
+            Format::Plain => {
+                let has_context = !result.before.is_empty() || !result.after.is_empty();
+                let adjacent = last.as_ref().is_some_and(|(path, line)| {
+                    *path == result.path && line + 1 >= result.first_line_number()
+                });
+                if last.is_some() && has_context && !adjacent {
+                    writeln!(out, "--")?;
+                }
+                if let Some(definition) = &result.definition {
+                    let key = (result.path.clone(), definition.line_number);
+                    if shown_definition.as_ref() != Some(&key) {
+                        write_definition(out, result, definition, layout)?;
+                        shown_definition = Some(key);
+                    }
+                }
+                if layout.only_matching {
+                    return write_only_matching(out, result, layout);
+                }
+                write_plain(out, result, layout, patterns)?;
+                last = Some((result.path.clone(), result.last_line_number()));
+                Ok(())
+            }
+            Format::Json if layout.only_matching => write_json_pieces(out, result, layout),
+            Format::Json => write_json(out, result, layout, patterns),
+            Format::Csv | Format::Tsv => {
+                let separator = separator(format);
+                if first {
+                    first = false;
+                    writeln!(
+                        out,
+                        "{}",
+                        ["path", "line", "column", "text"].join(&separator.to_string())
+                    )?;
+                }
+                write_delimited(out, result, layout, separator)
+            }
+            Format::Markdown | Format::Sarif => unreachable!("written as a whole above"),
+        }
+    };
+    for result in results {
+        write(&result)?;
+    }
+    out.flush()?;
+    Ok(found)
+}
+
+/// Writes the definition a match is in like `git grep -p` does, as
+/// `path=line=name`.
+fn write_definition(
+    out: &mut dyn Write,
+    result: &Match,
+    definition: &Definition,
+    layout: Layout,
+) -> io::Result<()> {
+    layout.write_path_at(out, &result.path, (definition.line_number, 1), "=")?;
+    if layout.line_numbers {
+        write!(out, "{}=", definition.line_number)?;
+    }
+    writeln!(out, "{}", definition.name)
+}
+
+/// Writes a match as a CSV or TSV record of its path, line, column and
+/// text: one per match on the line with `only_matching`, else one for the
+/// line at the column of its first match. Context lines are left out.
+fn write_delimited(
+    out: &mut dyn Write,
+    result: &Match,
+    layout: Layout,
+    separator: char,
+) -> io::Result<()> {
+    let path = layout.paths.show(&result.path);
+    let path = path.to_string_lossy();
+    let mut record = |line: usize, column: usize, text: &str| {
+        writeln!(
+            out,
+            "{}{sep}{}{sep}{}{sep}{}",
+            quote_field(&path, separator),
+            line,
+            column,
+            quote_field(text, separator),
+            sep = separator
+        )
+    };
+    if layout.only_matching {
+        for (offset, text) in result.matched_pieces() {
+            let (line, _, column) = result.position(offset);
+            record(line, column, text)?;
+        }
+        return Ok(());
+    }
+    let text = result.replacement.as_ref().unwrap_or(&result.line);
+    record(result.line_number, result.char_column(), text)
+}
+
+/// What separates the fields of CSV and TSV records.
+fn separator(format: Format) -> char {
+    if format == Format::Tsv { '\t' } else { ',' }
+}
+
+/// `field` as it goes into a CSV or TSV record: in double quotes, with any
+/// inside doubled, when it holds the separator, a quote or a line break.
+fn quote_field(field: &str, separator: char) -> Cow<'_, str> {
+    if field.contains([separator, '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Writes a section per file, in the order they first matched: its path as
+/// a heading, then its lines in a code block, marked like plain output
+/// (`12:` for matches, `11-` for context) and `--` between groups that
+/// aren't adjacent.
+fn write_markdown(
+    out: &mut dyn Write,
+    results: impl Iterator<Item = Match>,
+    layout: Layout,
+) -> io::Result<bool> {
+    let mut files: Vec<(PathBuf, Vec<Match>)> = Vec::new();
+    let mut index: HashMap<PathBuf, usize> = HashMap::new();
+    for result in results {
+        let i = *index.entry(result.path.clone()).or_insert_with(|| {
+            files.push((result.path.clone(), Vec::new()));
+            files.len() - 1
+        });
+        files[i].1.push(result);
+    }
+    for (n, (path, results)) in files.iter().enumerate() {
+        let mut lines = Vec::new();
+        let mut last: Option<usize> = None;
+        for result in results {
+            let has_context = !result.before.is_empty() || !result.after.is_empty();
+            if let Some(line) = last
+                && has_context
+                && line + 1 < result.first_line_number()
+            {
+                lines.push("--".to_string());
+            }
+            let number = |n: usize, separator: char| {
+                if layout.line_numbers {
+                    format!("{}{}", n, separator)
+                } else {
+                    String::new()
+                }
+            };
+            let clip = |line| clip(line, layout.max_columns);
+            let first = result.first_line_number();
+            for (i, line) in result.before.iter().enumerate() {
+                lines.push(format!("{}{}", number(first + i, '-'), clip(line)));
+            }
+            if layout.only_matching {
+                for (offset, text) in result.matched_pieces() {
+                    let (line, _, column) = result.position(offset);
+                    lines.push(format!("{}{}:{}", number(line, ':'), column, text));
+                }
+            } else {
+                let text = result.replacement.as_ref().unwrap_or(&result.line);
+                for (i, line) in text.split('\n').enumerate() {
+                    let number = number(result.line_number + i, ':');
+                    lines.push(format!("{}{}", number, clip(line)));
+                }
+            }
+            let after_start = result.line_number + result.line_count();
+            for (i, line) in result.after.iter().enumerate() {
+                lines.push(format!("{}{}", number(after_start + i, '-'), clip(line)));
+            }
+            last = Some(result.last_line_number());
+        }
+        // the fence has to be longer than any run of backticks inside it
+        let longest = lines
+            .iter()
+            .flat_map(|line| line.split(|c| c != '`'))
+            .map(str::len)
+            .max()
+            .unwrap_or(0);
+        let fence = "`".repeat(longest.max(2) + 1);
+        if n > 0 {
+            writeln!(out)?;
+        }
+        writeln!(
+            out,
+            "### {}",
+            markdown_code(&layout.paths.show(path).to_string_lossy())
+        )?;
+        writeln!(out)?;
+        writeln!(out, "{}", fence)?;
+        for line in &lines {
+            writeln!(out, "{}", line)?;
+        }
+        writeln!(out, "{}", fence)?;
+    }
+    out.flush()?;
+    Ok(!files.is_empty())
+}
+
+/// Writes `results` of named rules as a Markdown checklist to work
+/// through: a section per rule, in the order of `patterns`, with one per
+/// file inside it and an unticked item per match.
+fn write_checklist(
+    out: &mut dyn Write,
+    results: impl Iterator<Item = Match>,
+    layout: Layout,
+    patterns: &[String],
+) -> io::Result<bool> {
+    let mut rules: BTreeMap<usize, Vec<(PathBuf, Vec<Match>)>> = BTreeMap::new();
+    for result in results {
+        let files = rules
+            .entry(result.patterns.first().copied().unwrap_or(0))
+            .or_default();
+        match files.iter_mut().find(|(path, _)| *path == result.path) {
+            Some((_, matches)) => matches.push(result),
+            None => files.push((result.path.clone(), vec![result])),
+        }
+    }
+    for (n, (rule, files)) in rules.iter().enumerate() {
+        if n > 0 {
+            writeln!(out)?;
+        }
+        let count: usize = files.iter().map(|(_, matches)| matches.len()).sum();
+        let name = patterns.get(*rule).map_or("", String::as_str);
+        writeln!(out, "## {} ({})", name, count)?;
+        for (path, matches) in files {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "### {}",
+                markdown_code(&layout.paths.show(path).to_string_lossy())
+            )?;
+            writeln!(out)?;
+            for result in matches {
+                let text = clip(result.line.lines().next().unwrap_or(""), layout.max_columns);
+                if layout.line_numbers {
+                    write!(out, "- [ ] {}: ", result.line_number)?;
+                } else {
+                    write!(out, "- [ ] ")?;
+                }
+                writeln!(out, "{}", markdown_code(text.trim()))?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(!rules.is_empty())
+}
+
+/// `text` as inline code, with enough backticks around it to hold any
+/// inside it.
+fn markdown_code(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let ticks = "`".repeat(longest + 1);
+    // a backtick at either end needs a space between it and the delimiters
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{} {} {}", ticks, text, ticks)
+    } else {
+        format!("{}{}{}", ticks, text, ticks)
+    }
+}
+
+/// Prints a line per match on each matching line to `out`, made from `template`:
+/// `{path}`, `{line}`, `{column}` and `{text}` (the whole line) are filled
+/// in and `$1`/`${name}` refer to `regex`'s capture groups, as
+/// `Captures::expand` reads them. Returns whether anything matched.
+pub fn print_template(
+    out: &mut dyn Write,
+    events: Receiver<SearchEvent>,
+    regex: &Matcher,
+    template: &str,
+    layout: Layout,
+    sort: Option<SortBy>,
+) -> io::Result<bool> {
+    let mut found = false;
+    let mut write = |result: &Match| -> io::Result<()> {
+        found = true;
+        for captures in regex.captures_iter(&result.line) {
+            let whole = captures.get(0).map_or(0, |m| m.start);
+            let (line, _, column) = result.position(whole);
+            let placeholder = |name: &str| match name {
+                "path" => Some(layout.paths.show(&result.path).display().to_string()),
+                "line" => Some(line.to_string()),
+                "column" => Some(column.to_string()),
+                "text" => Some(result.line.clone()),
+                _ => None,
+            };
+            let mut text = String::new();
+            let mut rest = template;
+            // placeholders are filled in as-is, only the template's own
+            // text goes through the capture expansion, where `${1}` is a group
+            loop {
+                let next = rest.match_indices('{').find_map(|(open, _)| {
+                    if rest[..open].ends_with('$') {
+                        return None;
+                    }
+                    let close = open + rest[open..].find('}')?;
+                    Some((open, close, placeholder(&rest[open + 1..close])?))
+                });
+                let Some((open, close, value)) = next else {
+                    break;
+                };
+                captures.expand(&rest[..open], &mut text);
+                text.push_str(&value);
+                rest = &rest[close + 1..];
+            }
+            captures.expand(rest, &mut text);
+            writeln!(out, "{}", text)?;
+        }
+        Ok(())
+    };
+    let results: Box<dyn Iterator<Item = Match>> = match sort {
+        Some(sort) => Box::new(sorted(events, sort).into_iter()),
+        None => Box::new(matches(events)),
+    };
+    for result in results {
+        write(&result)?;
+    }
+    out.flush()?;
+    Ok(found)
+}
+
+/// Collects every match and orders them file by file.
+fn sorted(events: Receiver<SearchEvent>, sort: SortBy) -> Vec<Match> {
+    let mut files: Vec<(FileMeta, Vec<Match>)> = Vec::new();
+    let mut index: HashMap<PathBuf, usize> = HashMap::new();
+    for event in events {
+        match event {
+            SearchEvent::Match(result) => {
+                let i = *index.entry(result.path.clone()).or_insert_with(|| {
+                    files.push((FileMeta::read(&result.path), Vec::new()));
+                    files.len() - 1
+                });
+                files[i].1.push(result);
+            }
+            SearchEvent::Scanning(_) | SearchEvent::Changed(_) | SearchEvent::Error(..) => {}
+            SearchEvent::Done => break,
+        }
+    }
+    fn key((meta, matches): &(FileMeta, Vec<Match>)) -> SortKey<'_> {
+        SortKey {
+            path: &matches[0].path,
+            meta: *meta,
+            matches: matches.len(),
+        }
+    }
+    files.sort_by(|a, b| sort.compare(&key(a), &key(b)));
+    files.into_iter().flat_map(|(_, matches)| matches).collect()
+}
+
+/// Prints how many matching lines each file has, or with `occurrences` how
+/// many times it matches, followed by the total. Files are listed in the
+/// order their first match arrived unless `sort` says otherwise. Returns
+/// whether anything matched.
+pub fn print_counts(
+    out: &mut dyn Write,
+    events: Receiver<SearchEvent>,
+    format: Format,
+    layout: Layout,
+    occurrences: Option<&Matcher>,
+    sort: Option<SortBy>,
+) -> io::Result<bool> {
+    let mut counts: Vec<(PathBuf, usize)> = Vec::new();
+    let mut index: HashMap<PathBuf, usize> = HashMap::new();
+    for event in events {
+        match event {
+            SearchEvent::Match(result) => {
+                let n = occurrences.map_or(1, |regex| regex.find_ranges(&result.line).len());
+                let i = *index.entry(result.path.clone()).or_insert_with(|| {
+                    counts.push((result.path, 0));
+                    counts.len() - 1
+                });
+                counts[i].1 += n;
+            }
+            SearchEvent::Scanning(_) | SearchEvent::Changed(_) | SearchEvent::Error(..) => {}
+            SearchEvent::Done => break,
+        }
+    }
+
+    if let Some(sort) = sort {
+        let mut keyed: Vec<(FileMeta, (PathBuf, usize))> = counts
+            .into_iter()
+            .map(|count| (FileMeta::read(&count.0), count))
+            .collect();
+        fn key((meta, (path, n)): &(FileMeta, (PathBuf, usize))) -> SortKey<'_> {
+            SortKey {
+                path,
+                meta: *meta,
+                matches: *n,
+            }
+        }
+        keyed.sort_by(|a, b| sort.compare(&key(a), &key(b)));
+        counts = keyed.into_iter().map(|(_, count)| count).collect();
+    }
+
+    let total: usize = counts.iter().map(|(_, n)| n).sum();
+    match format {
+        Format::Markdown => {
+            writeln!(out, "| File | Count |")?;
+            writeln!(out, "| --- | ---: |")?;
+        }
+        Format::Csv => writeln!(out, "path,count")?,
+        Format::Tsv => writeln!(out, "path\tcount")?,
+        Format::Plain | Format::Json | Format::Sarif => {}
+    }
+    for (path, n) in &counts {
+        match format {
+            // main keeps SARIF to matches
+            Format::Plain | Format::Sarif => {
+                layout.write_path(out, path, ":")?;
+                writeln!(out, "{}", n)?
+            }
+            Format::Csv | Format::Tsv => {
+                let separator = separator(format);
+                let path = layout.paths.show(path);
+                let path = path.to_string_lossy();
+                writeln!(out, "{}{}{}", quote_field(&path, separator), separator, n)?
+            }
+            Format::Markdown => writeln!(
+                out,
+                "| {} | {} |",
+                markdown_code(&layout.paths.show(path).to_string_lossy()).replace('|', "\\|"),
+                n
+            )?,
+            Format::Json => writeln!(
+                out,
+                r#"{{"path":{},"count":{}}}"#,
+                layout.json_path(path),
+                n
+            )?,
+        }
+    }
+    match format {
+        Format::Plain | Format::Sarif => writeln!(out, "total:{}", total)?,
+        Format::Json => writeln!(out, r#"{{"total":{}}}"#, total)?,
+        Format::Markdown => writeln!(out, "| **Total** | **{}** |", total)?,
+        // a total row would throw off sums in a spreadsheet
+        Format::Csv | Format::Tsv => {}
+    }
+    out.flush()?;
+    Ok(!counts.is_empty())
+}
+
+/// Prints the matching lines that are in more than one file, leading and
+/// trailing whitespace aside: a group per line, most repeated first, with
+/// where each copy is. Plain output writes the copies as `path:line:text`
+/// with `--` between groups, JSON an object per group. Returns whether
+/// there were any.
+pub fn print_dupes(
+    out: &mut dyn Write,
+    events: Receiver<SearchEvent>,
+    format: Format,
+    layout: Layout,
+) -> io::Result<bool> {
+    let mut groups: HashMap<String, Vec<Match>> = HashMap::new();
+    for result in matches(events) {
+        let text = result.line.lines().next().unwrap_or("").trim().to_string();
+        groups.entry(text).or_default().push(result);
+    }
+    let mut groups: Vec<(String, Vec<Match>)> = groups
+        .into_iter()
+        .filter(|(_, copies)| copies.iter().any(|copy| copy.path != copies[0].path))
+        .collect();
+    // the search's threads find them in no particular order
+    for (_, copies) in &mut groups {
+        copies.sort_by(|a, b| (&a.path, a.line_number).cmp(&(&b.path, b.line_number)));
+    }
+    groups.sort_by(|(a_text, a), (b_text, b)| {
+        b.len()
+            .cmp(&a.len())
+            .then_with(|| (&a[0].path, a[0].line_number).cmp(&(&b[0].path, b[0].line_number)))
+            .then_with(|| a_text.cmp(b_text))
+    });
+
+    for (n, (text, copies)) in groups.iter().enumerate() {
+        match format {
+            Format::Json => {
+                let files: HashSet<&PathBuf> = copies.iter().map(|copy| &copy.path).collect();
+                let locations: Vec<String> = copies
+                    .iter()
+                    .map(|copy| {
+                        format!(
+                            r#"{{"path":{},"line":{}}}"#,
+                            layout.json_path(&copy.path),
+                            copy.line_number
+                        )
+                    })
+                    .collect();
+                writeln!(
+                    out,
+                    r#"{{"text":{},"count":{},"files":{},"locations":[{}]}}"#,
+                    json::string(text),
+                    copies.len(),
+                    files.len(),
+                    locations.join(",")
+                )?;
+            }
+            // main keeps dupes to plain text and JSON
+            _ => {
+                if n > 0 {
+                    writeln!(out, "--")?;
+                }
+                for copy in copies {
+                    let line = copy.line.lines().next().unwrap_or("");
+                    layout.write_path_at(out, &copy.path, (copy.line_number, 1), ":")?;
+                    if layout.line_numbers {
+                        write!(out, "{}:", copy.line_number)?;
+                    }
+                    writeln!(out, "{}", clip(line, layout.max_columns))?;
+                }
+            }
+        }
+    }
+    out.flush()?;
+    Ok(!groups.is_empty())
+}
+
+/// Prints the paths of the files that matched, or with `without` of the
+/// files that were searched but didn't match. Returns whether any were.
+pub fn print_files(
+    out: &mut dyn Write,
+    events: Receiver<SearchEvent>,
+    format: Format,
+    layout: Layout,
+    without: bool,
+    sort: Option<SortBy>,
+) -> io::Result<bool> {
+    let mut first = true;
+    let mut write = |path: &Path| match format {
+        Format::Plain | Format::Sarif => layout.write_path(out, path, "\n"),
+        Format::Json => writeln!(out, r#"{{"path":{}}}"#, layout.json_path(path)),
+        Format::Csv | Format::Tsv => {
+            if first {
+                first = false;
+                writeln!(out, "path")?;
+            }
+            let path = layout.paths.show(path);
+            writeln!(
+                out,
+                "{}",
+                quote_field(&path.to_string_lossy(), separator(format))
+            )
+        }
+        Format::Markdown => writeln!(
+            out,
+            "- {}",
+            markdown_code(&layout.paths.show(path).to_string_lossy())
+        ),
+    };
+
+    let mut found = false;
+    if without {
+        let mut searched: Vec<PathBuf> = Vec::new();
+        let mut matched: HashSet<PathBuf> = HashSet::new();
+        for event in events {
+            match event {
+                SearchEvent::Scanning(path) => searched.push(path),
+                SearchEvent::Match(result) => {
+                    matched.insert(result.path);
+                }
+                SearchEvent::Done => break,
+                SearchEvent::Changed(_) | SearchEvent::Error(..) => {}
+            }
+        }
+        searched.retain(|path| !matched.contains(path));
+        if let Some(sort) = sort {
+            let mut keyed: Vec<(FileMeta, PathBuf)> = searched
+                .into_iter()
+                .map(|path| (FileMeta::read(&path), path))
+                .collect();
+            keyed.sort_by(|(a_meta, a), (b_meta, b)| {
+                let key = |path, meta| SortKey {
+                    path,
+                    meta,
+                    matches: 0,
+                };
+                sort.compare(&key(a, *a_meta), &key(b, *b_meta))
+            });
+            searched = keyed.into_iter().map(|(_, path)| path).collect();
+        }
+        for path in &searched {
+            found = true;
+            write(path)?;
+        }
+    } else if let Some(sort) = sort {
+        for result in sorted(events, sort) {
+            found = true;
+            write(&result.path)?;
+        }
+    } else {
+        for event in events {
+            match event {
+                SearchEvent::Match(result) => {
+                    found = true;
+                    write(&result.path)?;
+                }
+                SearchEvent::Scanning(_) | SearchEvent::Changed(_) | SearchEvent::Error(..) => {}
+                SearchEvent::Done => break,
+            }
+        }
+    }
+    out.flush()?;
+    Ok(found)
+}
+
+/// `1536` -> `1.5 KiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// `bytes` read in `elapsed`, per second: `12.5 MiB/s`.
+pub fn throughput(bytes: u64, elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64();
+    let per_second = if seconds > 0.0 {
+        (bytes as f64 / seconds) as u64
+    } else {
+        0
+    };
+    format!("{}/s", format_bytes(per_second))
+}
+
+/// Prints the totals of a finished search for `--stats`: files and bytes
+/// searched, matches, and how long it took if that's known.
+pub fn print_stats(out: &mut dyn Write, stats: &SearchStats, format: Format) -> io::Result<()> {
+    let elapsed = stats.elapsed();
+    match format {
+        Format::Plain | Format::Markdown | Format::Csv | Format::Tsv | Format::Sarif => {
+            writeln!(out)?;
+            writeln!(out, "{} matches", stats.matches())?;
+            writeln!(out, "{} files contained matches", stats.matched_files())?;
+            writeln!(out, "{} files searched", stats.files())?;
+            writeln!(out, "{} searched", format_bytes(stats.bytes()))?;
+            if let Some(elapsed) = elapsed {
+                writeln!(out, "{:.3} seconds", elapsed.as_secs_f64())?;
+                writeln!(out, "{}", throughput(stats.bytes(), elapsed))?;
+            }
+        }
+        Format::Json => {
+            write!(
+                out,
+                r#"{{"stats":{{"matches":{},"matched_files":{},"files":{},"bytes":{}"#,
+                stats.matches(),
+                stats.matched_files(),
+                stats.files(),
+                stats.bytes()
+            )?;
+            if let Some(elapsed) = elapsed {
+                write!(out, r#","seconds":{:.6}"#, elapsed.as_secs_f64())?;
+            }
+            writeln!(out, "}}}}")?;
+        }
+    }
+    out.flush()
+}
+
+/// Prints `lines` to stdout, one per line.
+pub fn print_lines(lines: &[String]) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()
+}
+
+/// Writes a match grep-style: `path:line:content` (`path:line:column:content`
+/// with a column) for the match itself and `path-line-content` for its
+/// context lines, leaving out the line numbers without `line_numbers`. Later
+/// lines of a multiline match are at column 1.
+fn write_plain(
+    out: &mut dyn Write,
+    result: &Match,
+    layout: Layout,
+    patterns: &[String],
+) -> io::Result<()> {
+    let path = &result.path;
+    let number = |n: usize, separator: char| {
+        if layout.line_numbers {
+            format!("{}{}", n, separator)
+        } else {
+            String::new()
+        }
+    };
+    for (i, line) in result.before.iter().enumerate() {
+        layout.write_path_at(out, path, (result.first_line_number() + i, 1), "-")?;
+        writeln!(
+            out,
+            "{}{}",
+            number(result.first_line_number() + i, '-'),
+            clip(line, layout.max_columns)
+        )?;
+    }
+    let text = result.replacement.as_ref().unwrap_or(&result.line);
+    for (i, line) in text.split('\n').enumerate() {
+        let col = if i == 0 { result.char_column() } else { 1 };
+        layout.write_path_at(out, path, (result.line_number + i, col), ":")?;
+        write!(out, "{}", number(result.line_number + i, ':'))?;
+        if layout.column {
+            write!(out, "{}:", col)?;
+        }
+        if i == 0 && layout.rules {
+            write!(out, "[{}] ", rule_names(result, patterns).join(", "))?;
+        }
+        writeln!(out, "{}", clip(line, layout.max_columns))?;
+    }
+    let after_start = result.line_number + result.line_count();
+    for (i, line) in result.after.iter().enumerate() {
+        layout.write_path_at(out, path, (after_start + i, 1), "-")?;
+        writeln!(
+            out,
+            "{}{}",
+            number(after_start + i, '-'),
+            clip(line, layout.max_columns)
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the text of each match as `path:line:column:text`, every line of
+/// it on its own for matches spanning lines. Context isn't shown.
+fn write_only_matching(out: &mut dyn Write, result: &Match, layout: Layout) -> io::Result<()> {
+    for (offset, text) in result.matched_pieces() {
+        let (line, _, column) = result.position(offset);
+        layout.write_path_at(out, &result.path, (line, column), ":")?;
+        if layout.line_numbers {
+            write!(out, "{}:", line)?;
+        }
+        writeln!(out, "{}:{}", column, text)?;
+    }
+    Ok(())
+}
+
+/// Writes one object per match's text, as [`write_only_matching`] splits
+/// them up.
+fn write_json_pieces(out: &mut dyn Write, result: &Match, layout: Layout) -> io::Result<()> {
+    for (offset, text) in result.matched_pieces() {
+        let (line, column, char_column) = result.position(offset);
+        writeln!(
+            out,
+            r#"{{"path":{},"line":{},"column":{},"char_column":{},"text":{}}}"#,
+            layout.json_path(&result.path),
+            line,
+            column,
+            char_column,
+            json::string(text)
+        )?;
+    }
+    Ok(())
+}
+
+/// The names of the rules `result` matches, `patterns` being theirs.
+fn rule_names(result: &Match, patterns: &[String]) -> Vec<String> {
+    result
+        .patterns
+        .iter()
+        .filter_map(|&i| patterns.get(i).cloned())
+        .collect()
+}
+
+fn write_json(
+    out: &mut dyn Write,
+    result: &Match,
+    layout: Layout,
+    patterns: &[String],
+) -> io::Result<()> {
+    write!(
+        out,
+        r#"{{"path":{},"line":{},"column":{},"char_column":{},"text":{}"#,
+        layout.json_path(&result.path),
+        result.line_number,
+        result.column(),
+        result.char_column(),
+        json::string(&result.line)
+    )?;
+    if !result.spans.is_empty() {
+        let submatches: Vec<String> = result
+            .spans
+            .iter()
+            .map(|span| {
+                format!(
+                    r#"{{"start":{},"end":{},"text":{}}}"#,
+                    span.start,
+                    span.end,
+                    json::string(&result.line[span.clone()])
+                )
+            })
+            .collect();
+        write!(out, r#","submatches":[{}]"#, submatches.join(","))?;
+    }
+    if let Some(replacement) = &result.replacement {
+        write!(out, r#","replacement":{}"#, json::string(replacement))?;
+    }
+    if !result.patterns.is_empty() {
+        let indices: Vec<String> = result.patterns.iter().map(|i| i.to_string()).collect();
+        write!(out, r#","patterns":[{}]"#, indices.join(","))?;
+        if layout.rules {
+            let names = rule_names(result, patterns);
+            write!(out, r#","rules":{}"#, json::string_array(&names))?;
+        }
+    }
+    if let Some(definition) = &result.definition {
+        write!(out, r#","definition":{}"#, json::definition(definition))?;
+    }
+    if !result.before.is_empty() {
+        write!(out, r#","before":{}"#, json::string_array(&result.before))?;
+    }
+    if !result.after.is_empty() {
+        write!(out, r#","after":{}"#, json::string_array(&result.after))?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Applies the replacements of every match once the search is done, one
+/// file at a time, and prints how many were made in each file and overall.
+/// Files that can't be rewritten are reported and skipped; the error at the
+/// end says how many there were. Returns whether anything was replaced.
+pub fn print_changes(
+    out: &mut dyn Write,
+    events: Receiver<SearchEvent>,
+    format: Format,
+    backup_suffix: Option<&str>,
+) -> io::Result<bool> {
+    let mut files: BTreeMap<PathBuf, Vec<Match>> = BTreeMap::new();
+    for event in events {
+        match event {
+            SearchEvent::Match(result) => {
+                files.entry(result.path.clone()).or_default().push(result)
+            }
+            SearchEvent::Done => break,
+            SearchEvent::Scanning(_) | SearchEvent::Changed(_) | SearchEvent::Error(..) => {}
+        }
+    }
+
+    let (mut total, mut changed, mut failed) = (0, 0, 0);
+    for (path, results) in &files {
+        let edits: Vec<Edit> = results
+            .iter()
+            .filter_map(|result| {
+                Some(Edit {
+                    line_number: result.line_number,
+                    original: &result.line,
+                    replacement: result.replacement.as_deref()?,
+                })
+            })
+            // lines the template leaves as they were aren't changes
+            .filter(|edit| edit.original != edit.replacement)
+            .collect();
+        if edits.is_empty() {
+            continue;
+        }
+        let applied = match replace::apply(path, &edits, backup_suffix) {
+            Ok(applied) => applied.len(),
+            Err(err) => {
+                out.flush()?;
+                eprintln!("rstr: {}: {}", path.display(), err);
+                failed += 1;
+                continue;
+            }
+        };
+        let skipped = edits.len() - applied;
+        total += applied;
+        if applied > 0 {
+            changed += 1;
+        }
+        match format {
+            Format::Plain | Format::Markdown | Format::Csv | Format::Tsv | Format::Sarif => {
+                write!(out, "{}: {} replacement(s)", path.display(), applied)?;
+                if skipped > 0 {
+                    write!(out, ", {} skipped as the line changed", skipped)?;
+                }
+                writeln!(out)?;
+            }
+            Format::Json => writeln!(
+                out,
+                r#"{{"path":{},"replacements":{},"skipped":{}}}"#,
+                json::string(&path.to_string_lossy()),
+                applied,
+                skipped
+            )?,
+        }
+    }
+    if format != Format::Json {
+        writeln!(out, "{} replacement(s) in {} file(s)", total, changed)?;
+    }
+    out.flush()?;
+
+    if failed > 0 {
+        return Err(io::Error::other(format!(
+            "{} file(s) couldn't be rewritten",
+            failed
+        )));
+    }
+    Ok(total > 0)
+}
+
+/// Passes `events` through, writing the errors among them to stderr as
+/// `rstr: path: reason` unless `messages` is off. With `endless`, the
+/// [`SearchEvent::Done`] of `--tail`'s first scan is left out, so the
+/// output goes on with the lines added after it.
+pub fn report_errors(
+    events: Receiver<SearchEvent>,
+    messages: bool,
+    endless: bool,
+) -> Receiver<SearchEvent> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for event in events {
+            match event {
+                SearchEvent::Error(path, message) => {
+                    if messages {
+                        eprintln!("rstr: {}: {}", path.display(), message);
+                    }
+                }
+                SearchEvent::Done if endless => {}
+                // the reader going away stops the search behind `events`
+                event => {
+                    if tx.send(event).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    rx
+}
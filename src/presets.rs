@@ -0,0 +1,76 @@
+//! The curated sets of patterns that subcommands such as `rstr secrets`
+//! and `rstr todos` search with. Each is a rule with a name, which results,
+//! JSON and SARIF show instead of the pattern itself.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub name: &'static str,
+    pub pattern: &'static str,
+}
+
+const fn rule(name: &'static str, pattern: &'static str) -> Rule {
+    Rule { name, pattern }
+}
+
+/// Credentials that shouldn't be in a tree: cloud and service keys by their
+/// known shapes, private keys by their PEM header, JWTs, and long values
+/// assigned to names like `api_key` or `password`.
+pub const SECRETS: &[Rule] = &[
+    rule(
+        "aws-access-key-id",
+        r"\b(?:AKIA|ASIA|ABIA|ACCA)[0-9A-Z]{16}\b",
+    ),
+    rule(
+        "aws-secret-access-key",
+        r#"(?i)\baws_?(?:secret_?(?:access_?)?key|secret)\b\s*[:=]\s*["']?[0-9a-z/+]{40}\b"#,
+    ),
+    rule(
+        "private-key",
+        r"-----BEGIN (?:[A-Z0-9]+ )*PRIVATE KEY(?: BLOCK)?-----",
+    ),
+    rule(
+        "jwt",
+        r"\beyJ[0-9A-Za-z_-]{10,}\.eyJ[0-9A-Za-z_-]{10,}\.[0-9A-Za-z_-]{10,}",
+    ),
+    rule(
+        "github-token",
+        r"\b(?:gh[pousr]_[0-9A-Za-z]{36}|github_pat_[0-9A-Za-z_]{82})\b",
+    ),
+    rule("gitlab-token", r"\bglpat-[0-9A-Za-z_-]{20}\b"),
+    rule("slack-token", r"\bxox[abposr]-[0-9A-Za-z-]{10,}"),
+    rule(
+        "slack-webhook",
+        r"https://hooks\.slack\.com/services/T[0-9A-Za-z_]+/B[0-9A-Za-z_]+/[0-9A-Za-z_]+",
+    ),
+    rule("google-api-key", r"\bAIza[0-9A-Za-z_-]{35}"),
+    rule("stripe-key", r"\b[rs]k_(?:live|test)_[0-9A-Za-z]{24,}\b"),
+    rule("npm-token", r"\bnpm_[0-9A-Za-z]{36}\b"),
+    rule(
+        "generic-api-token",
+        r#"(?i)\b(?:api_?key|api_?token|access_?token|auth_?token|secret_?key|client_?secret|password|passwd)\b["']?\s*[:=]\s*["']?[0-9a-z_\-+/=.]{16,}"#,
+    ),
+];
+
+/// The markers of work left in comments, each its own rule so results can
+/// be grouped by them.
+pub const TODOS: &[Rule] = &[
+    rule("TODO", r"\bTODO\b"),
+    rule("FIXME", r"\bFIXME\b"),
+    rule("HACK", r"\bHACK\b"),
+    rule("XXX", r"\bXXX\b"),
+];
+
+/// What results show for each of `patterns`: the name of the rule it's the
+/// pattern of, or itself when it's none of `rules`, e.g. one given with `-e`
+/// on top of them.
+pub fn labels(rules: &[Rule], patterns: &[String]) -> Vec<String> {
+    patterns
+        .iter()
+        .map(
+            |pattern| match rules.iter().find(|rule| rule.pattern == pattern) {
+                Some(rule) => rule.name.to_string(),
+                None => pattern.clone(),
+            },
+        )
+        .collect()
+}
@@ -0,0 +1,42 @@
+use crate::syntax::{self, Token};
+use rstr::encoding::{self, Encoding};
+use rstr::{archive, compressed};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// The lines of the file shown in the preview pane, kept around so moving
+/// between results in the same file doesn't re-read it.
+pub struct Preview {
+    pub path: PathBuf,
+    pub lines: Vec<String>,
+    /// The syntax tokens of each line, none if the language isn't known.
+    pub tokens: Vec<Vec<(Range<usize>, Token)>>,
+}
+
+impl Preview {
+    pub fn load(path: &Path, encoding: Option<Encoding>) -> Preview {
+        let lines = match read_lines(path, encoding) {
+            Ok(lines) => lines,
+            Err(err) => vec![format!("<cannot read file: {}>", err)],
+        };
+        Preview {
+            tokens: syntax::highlight(path, &lines),
+            path: path.to_path_buf(),
+            lines,
+        }
+    }
+}
+
+/// The lines of the file at `path`, which may be an archive entry or
+/// compressed, read as the search read them.
+pub fn read_lines(path: &Path, encoding: Option<Encoding>) -> io::Result<Vec<String>> {
+    let bytes = archive::read_entry(path)
+        .or_else(|| compressed::read(path))
+        .unwrap_or_else(|| fs::read(path))?;
+    Ok(String::from_utf8_lossy(&encoding::decode(&bytes, encoding))
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
@@ -0,0 +1,233 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+/// A rewrite of the lines starting at `line_number`: they must still read
+/// `original` (several `\n`-joined lines for multiline matches) for
+/// `replacement` to be applied.
+pub struct Edit<'a> {
+    pub line_number: usize,
+    pub original: &'a str,
+    pub replacement: &'a str,
+}
+
+/// Applies `edits` to the file at `path`, keeping line endings intact.
+/// Lines that changed since the search are left alone; returns the line
+/// numbers of the edits that were applied, in order. With `backup_suffix`, the original is first copied
+/// to its path with the suffix appended.
+pub fn apply(path: &Path, edits: &[Edit], backup_suffix: Option<&str>) -> io::Result<Vec<usize>> {
+    let contents = fs::read_to_string(path)?;
+    let mut lines: Vec<String> = contents.split_inclusive('\n').map(str::to_string).collect();
+
+    // bottom-up, so an edit that changes the line count can't shift the
+    // lines of the edits still to come
+    let mut order: Vec<&Edit> = edits.iter().collect();
+    order.sort_by_key(|edit| std::cmp::Reverse(edit.line_number));
+
+    let mut applied = Vec::new();
+    for edit in order {
+        let Some(first) = edit.line_number.checked_sub(1) else {
+            continue;
+        };
+        let count = edit.original.split('\n').count();
+        if first + count > lines.len() {
+            continue;
+        }
+        let mut span = lines[first..first + count].concat();
+        let body_len = span.trim_end_matches(['\n', '\r']).len();
+        if span[..body_len] != *edit.original {
+            continue;
+        }
+        span.replace_range(..body_len, edit.replacement);
+        lines.splice(first..first + count, [span]);
+        applied.push(edit.line_number);
+    }
+    applied.reverse();
+
+    if !applied.is_empty() {
+        // write through symlinks instead of replacing them
+        let path = fs::canonicalize(path)?;
+        if let Some(suffix) = backup_suffix {
+            let mut backup = path.clone().into_os_string();
+            backup.push(suffix);
+            fs::copy(&path, backup)?;
+        }
+        write_atomically(&path, lines.concat().as_bytes())?;
+    }
+    Ok(applied)
+}
+
+/// Replaces the file at `path` with `contents` by writing a temporary file
+/// next to it and renaming it over the original, so the file either has
+/// its old or its new contents even if rstr is interrupted. The original's
+/// permissions carry over.
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let permissions = fs::metadata(path)?.permissions();
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.rstr-{}.tmp", name, process::id()));
+
+    let written = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::set_permissions(&temp, permissions)?;
+        fs::rename(&temp, path)
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    /// A fresh directory under the system's temporary one, for a test's
+    /// files.
+    fn dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rstr-replace-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn edit<'a>(line_number: usize, original: &'a str, replacement: &'a str) -> Edit<'a> {
+        Edit {
+            line_number,
+            original,
+            replacement,
+        }
+    }
+
+    #[test]
+    fn rewrites_lines_that_still_read_the_same() {
+        let dir = dir("unchanged");
+        let file = dir.join("a.txt");
+        fs::write(&file, "one\ntwo\nthree\nfour").unwrap();
+        let edits = [
+            edit(1, "one", "1"),
+            edit(2, "changed since", "2"),
+            edit(4, "four", "4"),
+            edit(0, "one", "0"),
+            edit(9, "nine", "9"),
+        ];
+        assert_eq!(apply(&file, &edits, None).unwrap(), [1, 4]);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "1\ntwo\nthree\n4");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_line_endings() {
+        let dir = dir("crlf");
+        let file = dir.join("a.txt");
+        fs::write(&file, "one\r\ntwo\r\nthree\n").unwrap();
+        let edits = [edit(1, "one", "1"), edit(3, "three", "3")];
+        assert_eq!(apply(&file, &edits, None).unwrap(), [1, 3]);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "1\r\ntwo\r\n3\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn multiline_edits_can_change_the_line_count() {
+        let dir = dir("multiline");
+        let file = dir.join("a.txt");
+        fs::write(&file, "a\nb\r\nc\nd\ne\n").unwrap();
+        let edits = [
+            edit(1, "a\nb", "ab"),
+            edit(3, "c", "c1\nc2"),
+            edit(4, "d\ne", "de"),
+        ];
+        assert_eq!(apply(&file, &edits, None).unwrap(), [1, 3, 4]);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "ab\r\nc1\nc2\nde\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_nothing_when_no_edit_applies() {
+        let dir = dir("nothing");
+        let file = dir.join("a.txt");
+        fs::write(&file, "one\n").unwrap();
+        let edits = [edit(1, "other", "1")];
+        assert!(apply(&file, &edits, Some(".bak")).unwrap().is_empty());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "one\n");
+        assert!(!dir.join("a.txt.bak").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn replaces_the_file_by_renaming_a_temporary_one() {
+        let dir = dir("rename");
+        let file = dir.join("a.txt");
+        fs::write(&file, "one\n").unwrap();
+        // a reader holding the old file keeps seeing the old contents
+        let mut old = fs::File::open(&file).unwrap();
+        apply(&file, &[edit(1, "one", "1")], None).unwrap();
+        let mut seen = String::new();
+        io::Read::read_to_string(&mut old, &mut seen).unwrap();
+        assert_eq!(seen, "one\n");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "1\n");
+        let names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["a.txt"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn backs_up_the_original() {
+        let dir = dir("backup");
+        let file = dir.join("a.txt");
+        fs::write(&file, "one\n").unwrap();
+        apply(&file, &[edit(1, "one", "1")], Some(".orig")).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "1\n");
+        assert_eq!(fs::read_to_string(dir.join("a.txt.orig")).unwrap(), "one\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = dir("permissions");
+        let file = dir.join("run.sh");
+        fs::write(&file, "echo one\n").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o750)).unwrap();
+        apply(&file, &[edit(1, "echo one", "echo 1")], None).unwrap();
+        let mode = fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_through_symlinks() {
+        let dir = dir("symlink");
+        let target = dir.join("target.txt");
+        let link = dir.join("link.txt");
+        fs::write(&target, "one\n").unwrap();
+        std::os::unix::fs::symlink("target.txt", &link).unwrap();
+        apply(&link, &[edit(1, "one", "1")], Some(".bak")).unwrap();
+        assert!(
+            fs::symlink_metadata(&link)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_to_string(&target).unwrap(), "1\n");
+        // the backup is of the file the link points to, next to it
+        assert_eq!(
+            fs::read_to_string(dir.join("target.txt.bak")).unwrap(),
+            "one\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+//! SARIF 2.1.0 reports for `--format sarif`, which code-scanning services
+//! take as uploads: each pattern is a rule of the `rstr` tool, and each
+//! match a result located by its file and region.
+
+use crate::json::Value;
+use crate::output::Layout;
+use rstr::Match;
+use std::env;
+use std::io::{self, Write};
+use std::path::{Component, Path};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Where relative artifact URIs are resolved from: the directory rstr ran
+/// in, since that's what the paths it found are relative to.
+const SRCROOT: &str = "%SRCROOT%";
+
+/// Writes a report of `results`, which match `patterns`, once they're all
+/// in. Returns whether there were any.
+pub fn write(
+    out: &mut dyn Write,
+    results: impl Iterator<Item = Match>,
+    patterns: &[String],
+    layout: Layout,
+) -> io::Result<bool> {
+    let rules: Vec<Value> = patterns
+        .iter()
+        .map(|pattern| {
+            Value::object([
+                ("id", pattern.as_str().into()),
+                (
+                    "shortDescription",
+                    Value::object([("text", format!("Matches of {}", pattern).into())]),
+                ),
+            ])
+        })
+        .collect();
+    let mut found = false;
+    let mut entries = Vec::new();
+    for result in results {
+        found = true;
+        // results of several patterns name those they match; the rest match the only one
+        let rule = result.patterns.first().copied().unwrap_or(0);
+        let artifact = artifact(&result, layout);
+        let regions: Vec<Value> = if result.spans.is_empty() {
+            vec![Value::object([
+                ("startLine", result.line_number.into()),
+                ("snippet", snippet(&result)),
+            ])]
+        } else {
+            result
+                .spans
+                .iter()
+                .map(|span| {
+                    let (start_line, _, start_column) = result.position(span.start);
+                    let (end_line, _, end_column) = result.position(span.end);
+                    Value::object([
+                        ("startLine", start_line.into()),
+                        ("startColumn", start_column.into()),
+                        ("endLine", end_line.into()),
+                        ("endColumn", end_column.into()),
+                        ("snippet", snippet(&result)),
+                    ])
+                })
+                .collect()
+        };
+        for region in regions {
+            let physical =
+                Value::object([("artifactLocation", artifact.clone()), ("region", region)]);
+            let mut location = Value::object([("physicalLocation", physical)]);
+            if let Some(definition) = &result.definition
+                && let Value::Object(members) = &mut location
+            {
+                let logical = Value::object([("name", definition.name.as_str().into())]);
+                members.insert("logicalLocations".to_string(), vec![logical].into());
+            }
+            entries.push(Value::object([
+                (
+                    "ruleId",
+                    patterns.get(rule).map_or("", String::as_str).into(),
+                ),
+                ("ruleIndex", rule.into()),
+                ("level", "note".into()),
+                (
+                    "message",
+                    Value::object([("text", result.line.trim().into())]),
+                ),
+                ("locations", vec![location].into()),
+            ]));
+        }
+    }
+
+    let driver = Value::object([
+        ("name", "rstr".into()),
+        ("version", env!("CARGO_PKG_VERSION").into()),
+        ("rules", rules.into()),
+    ]);
+    let mut run = Value::object([
+        ("tool", Value::object([("driver", driver)])),
+        ("columnKind", "unicodeCodePoints".into()),
+        ("results", entries.into()),
+    ]);
+    if let Ok(dir) = env::current_dir()
+        && let Value::Object(members) = &mut run
+    {
+        let root = format!("{}/", file_uri(&dir).trim_end_matches('/'));
+        members.insert(
+            "originalUriBaseIds".to_string(),
+            Value::object([(SRCROOT, Value::object([("uri", root.into())]))]),
+        );
+    }
+    let report = Value::object([
+        ("$schema", SCHEMA.into()),
+        ("version", "2.1.0".into()),
+        ("runs", vec![run].into()),
+    ]);
+    writeln!(out, "{}", report.pretty())?;
+    out.flush()?;
+    Ok(found)
+}
+
+/// The location of the file `result` is in.
+fn artifact(result: &Match, layout: Layout) -> Value {
+    let path = layout.paths.show(&result.path);
+    if path.is_absolute() {
+        Value::object([("uri", file_uri(&path).into())])
+    } else {
+        // `./src/main.rs` is `src/main.rs` to SARIF consumers
+        let relative: Vec<String> = path
+            .components()
+            .filter(|component| *component != Component::CurDir)
+            .map(|component| percent_encode(&component.as_os_str().to_string_lossy()))
+            .collect();
+        Value::object([
+            ("uri", relative.join("/").into()),
+            ("uriBaseId", SRCROOT.into()),
+        ])
+    }
+}
+
+fn snippet(result: &Match) -> Value {
+    Value::object([("text", result.line.as_str().into())])
+}
+
+/// An absolute path as a `file://` URI.
+fn file_uri(path: &Path) -> String {
+    let encoded: String = path
+        .to_string_lossy()
+        .split('/')
+        .map(percent_encode)
+        .collect::<Vec<String>>()
+        .join("/");
+    format!("file://{}", encoded)
+}
+
+/// Escapes what isn't allowed as is in a URI path segment.
+pub fn percent_encode(segment: &str) -> String {
+    let mut out = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(byte as char)
+            }
+            byte => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
@@ -0,0 +1,1345 @@
+use crate::compressed::Compression;
+use crate::definitions::{self, Definition};
+use crate::encoding::{self, Encoding};
+use crate::matcher::Matcher;
+use crate::walk::{self, WalkOptions};
+use crate::{archive, mmap};
+use regex::{Regex, RegexBuilder, RegexSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The path reported for matches read from standard input.
+pub const STDIN_PATH: &str = "<stdin>";
+
+/// A matching line (or lines, in multiline mode) with its surroundings.
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub path: PathBuf,
+    pub line_number: usize,
+    pub line: String,
+    /// Byte ranges of every match within `line`, in order. Empty for the
+    /// lines reported by [`SearchOptions::invert`].
+    pub spans: Vec<Range<usize>>,
+    /// Lines directly preceding the match, oldest first.
+    pub before: Vec<String>,
+    /// Lines directly following the match.
+    pub after: Vec<String>,
+    /// `line` with every match substituted when a replacement is set.
+    pub replacement: Option<String>,
+    /// Indices of the patterns in [`SearchOptions::patterns`] that match
+    /// `line`, empty when searching for a single pattern.
+    pub patterns: Vec<usize>,
+    /// The function, type or class the match is in, with
+    /// [`SearchOptions::definitions`].
+    pub definition: Option<Definition>,
+}
+
+impl Match {
+    /// Whether every span still falls on `line`, and on the boundaries of
+    /// its characters, which they needn't once it's read again from a file
+    /// that changed.
+    pub fn spans_fit(&self) -> bool {
+        self.spans
+            .iter()
+            .all(|span| self.line.get(span.clone()).is_some())
+    }
+
+    /// Byte range of the first match within `line`, the one columns refer
+    /// to; empty at the start of the line if there is none.
+    pub fn matched(&self) -> Range<usize> {
+        self.spans.first().cloned().unwrap_or(0..0)
+    }
+
+    /// 1-based byte column of the first match.
+    pub fn column(&self) -> usize {
+        self.matched().start + 1
+    }
+
+    /// 1-based column of the first match in characters, which is what
+    /// editors expect.
+    pub fn char_column(&self) -> usize {
+        self.line[..self.matched().start].chars().count() + 1
+    }
+
+    /// Where the byte `offset` of `line` falls: its line number, and its
+    /// 1-based byte and character columns on that line.
+    pub fn position(&self, offset: usize) -> (usize, usize, usize) {
+        let before = &self.line[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (
+            self.line_number + before.matches('\n').count(),
+            offset - line_start + 1,
+            before[line_start..].chars().count() + 1,
+        )
+    }
+
+    /// The text of every match on the line one line at a time, with the
+    /// byte offset each piece starts at; a match spanning lines (`-U`) comes
+    /// in several pieces. Empty matches are left out.
+    pub fn matched_pieces(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.spans
+            .iter()
+            .filter(|span| !span.is_empty())
+            .flat_map(|span| {
+                let mut offset = span.start;
+                self.line[span.clone()].split('\n').map(move |piece| {
+                    let start = offset;
+                    offset += piece.len() + 1;
+                    (start, piece)
+                })
+            })
+    }
+
+    pub fn first_line_number(&self) -> usize {
+        self.line_number - self.before.len()
+    }
+
+    /// Number of lines the match itself spans (more than one in multiline mode).
+    pub fn line_count(&self) -> usize {
+        self.line.split('\n').count()
+    }
+
+    pub fn last_line_number(&self) -> usize {
+        self.line_number + self.line_count() - 1 + self.after.len()
+    }
+
+    /// Whether the match came from standard input rather than a file.
+    pub fn is_stdin(&self) -> bool {
+        self.path == Path::new(STDIN_PATH)
+    }
+}
+
+/// What to search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A file, or a directory to walk.
+    Path(PathBuf),
+    /// Several files or directories, walked one after the other. A file
+    /// reached from more than one of them is searched once, under the
+    /// first.
+    Paths(Vec<PathBuf>),
+    /// Exactly these files, such as those of earlier results, searched as
+    /// they are without walking or filtering.
+    Files(Vec<PathBuf>),
+    /// Everything read from standard input.
+    Stdin,
+}
+
+impl Source {
+    /// The files and directories to walk, none for standard input.
+    pub fn roots(&self) -> &[PathBuf] {
+        match self {
+            Source::Path(root) => std::slice::from_ref(root),
+            Source::Paths(roots) | Source::Files(roots) => roots,
+            Source::Stdin => &[],
+        }
+    }
+}
+
+impl From<PathBuf> for Source {
+    fn from(path: PathBuf) -> Source {
+        Source::Path(path)
+    }
+}
+
+impl From<&Path> for Source {
+    fn from(path: &Path) -> Source {
+        Source::Path(path.to_path_buf())
+    }
+}
+
+impl From<&str> for Source {
+    fn from(path: &str) -> Source {
+        Source::Path(PathBuf::from(path))
+    }
+}
+
+#[derive(Debug)]
+pub enum SearchEvent {
+    /// A file is about to be searched.
+    Scanning(PathBuf),
+    Match(Match),
+    /// The search is over; only sent by [`Searcher::spawn`] and
+    /// [`Searcher::watch`].
+    Done,
+    /// Only sent by [`Searcher::watch`]: the file was modified, created or
+    /// deleted since it was searched. Its earlier matches are stale, and
+    /// the new ones follow.
+    Changed(PathBuf),
+    /// A file or directory couldn't be read, with the reason; the search
+    /// goes on without it.
+    Error(PathBuf, String),
+}
+
+#[derive(Clone)]
+pub struct SearchOptions {
+    pub source: Source,
+    pub regex: Matcher,
+    pub walk: WalkOptions,
+    pub threads: usize,
+    pub before_context: usize,
+    pub after_context: usize,
+    /// Template (`$1`, `${name}`) every match is substituted with to fill
+    /// in [`Match::replacement`].
+    pub replace: Option<String>,
+    /// Match against whole files so matches can span lines.
+    pub multiline: bool,
+    /// Report the lines that don't match instead, with no
+    /// [`Match::spans`]. Ignored in multiline mode.
+    pub invert: bool,
+    /// The individual patterns when `regex` is an alternation of several,
+    /// to tell which of them each match contains in [`Match::patterns`].
+    pub patterns: Option<RegexSet>,
+    /// What the files are encoded in; `None` detects it for each file.
+    /// Matches and context are always UTF-8.
+    pub encoding: Option<Encoding>,
+    /// Stop searching a file after this many matches; 1 is enough to list
+    /// the files that match.
+    pub max_count: Option<usize>,
+    /// Stop the whole search after this many matches.
+    pub max_total: Option<usize>,
+    /// Search the files inside zip and tar archives instead of the archives
+    /// themselves, see [`crate::archive`].
+    pub archives: bool,
+    /// Search what compressed files decompress to, see [`crate::compressed`].
+    pub compressed: bool,
+    /// Match `regex` against the paths of the files instead of reading
+    /// them, with one [`Match`] per file whose `line` is the path matched.
+    pub match_paths: bool,
+    /// Search each file once however many paths lead to it, through hard
+    /// links, bind mounts or symlinks, see [`FileId`]. Overlapping roots
+    /// are searched once either way.
+    pub dedupe: bool,
+    /// Fill in [`Match::definition`] for the file types
+    /// [`crate::definitions`] knows.
+    pub definitions: bool,
+}
+
+impl SearchOptions {
+    /// Options for searching `source` with `regex` using the default walk
+    /// filters, one thread per CPU and no context.
+    pub fn new(source: impl Into<Source>, regex: impl Into<Matcher>) -> SearchOptions {
+        SearchOptions {
+            source: source.into(),
+            regex: regex.into(),
+            walk: WalkOptions::default(),
+            threads: thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            before_context: 0,
+            after_context: 0,
+            replace: None,
+            multiline: false,
+            invert: false,
+            patterns: None,
+            encoding: None,
+            max_count: None,
+            max_total: None,
+            archives: false,
+            compressed: false,
+            match_paths: false,
+            dedupe: true,
+            definitions: false,
+        }
+    }
+}
+
+/// What a file is known by when telling whether it was already searched:
+/// its device and inode on Unix with [`SearchOptions::dedupe`], else its
+/// canonical path.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum FileId {
+    Inode(u64, u64),
+    Path(PathBuf),
+}
+
+impl FileId {
+    fn of(path: &Path, dedupe: bool) -> FileId {
+        #[cfg(unix)]
+        if dedupe && let Ok(meta) = fs::metadata(path) {
+            use std::os::unix::fs::MetadataExt;
+            return FileId::Inode(meta.dev(), meta.ino());
+        }
+        #[cfg(not(unix))]
+        let _ = dedupe;
+        FileId::Path(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()))
+    }
+}
+
+/// Running totals the engine keeps while it searches, readable from any
+/// thread through [`Searcher::stats`].
+#[derive(Debug, Default)]
+pub struct SearchStats {
+    files: AtomicUsize,
+    bytes: AtomicU64,
+    matches: AtomicUsize,
+    matched_files: AtomicUsize,
+    /// How long the last run took in nanoseconds, 0 until it's over.
+    elapsed: AtomicU64,
+}
+
+impl SearchStats {
+    /// Totals carried over from an earlier search, e.g. one saved to disk.
+    pub fn with_totals(files: usize, bytes: u64, matches: usize) -> SearchStats {
+        SearchStats {
+            files: AtomicUsize::new(files),
+            bytes: AtomicU64::new(bytes),
+            matches: AtomicUsize::new(matches),
+            ..SearchStats::default()
+        }
+    }
+
+    /// Files (or stdin) searched so far.
+    pub fn files(&self) -> usize {
+        self.files.load(Ordering::Relaxed)
+    }
+
+    /// Bytes read from the searched files so far.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Matches reported so far.
+    pub fn matches(&self) -> usize {
+        self.matches.load(Ordering::Relaxed)
+    }
+
+    /// Files with at least one match so far.
+    pub fn matched_files(&self) -> usize {
+        self.matched_files.load(Ordering::Relaxed)
+    }
+
+    /// How long [`Searcher::run`] took, walk included, once it returned.
+    /// `None` while it runs, and for totals carried over.
+    pub fn elapsed(&self) -> Option<Duration> {
+        match self.elapsed.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+}
+
+/// Stops a running search from another thread, see [`Searcher::canceller`].
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    /// Makes the search stop at the next file or match. Whatever it
+    /// reported so far stays valid.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Searcher {
+    options: SearchOptions,
+    stats: Arc<SearchStats>,
+    cancel: Cancel,
+    /// Matches claimed against [`SearchOptions::max_total`], by any thread.
+    claimed: AtomicUsize,
+    /// Finds candidate lines across a whole file at once. It matches at
+    /// least wherever `regex` matches a single line: `^`/`$` match at line
+    /// boundaries and case is ignored, since the builder's flags can't be
+    /// recovered from `regex`. `None` if the wrapped pattern doesn't compile
+    /// or can't be made to match at every line (see [`prefilter_source`]),
+    /// and for PCRE2 patterns, whose syntax it doesn't know.
+    prefilter: Option<Regex>,
+}
+
+impl Searcher {
+    pub fn new(options: SearchOptions) -> Searcher {
+        let prefilter = options
+            .regex
+            .as_regex()
+            .and_then(|regex| prefilter_source(regex.as_str()))
+            .and_then(|source| {
+                RegexBuilder::new(&format!("(?mi:{})", source))
+                    .crlf(true)
+                    .build()
+                    .ok()
+            });
+        Searcher {
+            options,
+            stats: Arc::default(),
+            cancel: Cancel::default(),
+            claimed: AtomicUsize::new(0),
+            prefilter,
+        }
+    }
+
+    pub fn options(&self) -> &SearchOptions {
+        &self.options
+    }
+
+    /// The counters this searcher updates; keep a handle before
+    /// [`Searcher::spawn`] to watch a background search.
+    pub fn stats(&self) -> Arc<SearchStats> {
+        self.stats.clone()
+    }
+
+    /// A handle that stops this searcher's [`Searcher::run`], also once it
+    /// has moved to a background thread with [`Searcher::spawn`].
+    pub fn canceller(&self) -> Cancel {
+        self.cancel.clone()
+    }
+
+    /// Walks the search roots with the same filters as a search but
+    /// without reading anything, returning how many files a search would
+    /// look at. `None` when searching stdin.
+    pub fn count_files(&self) -> Option<usize> {
+        if self.options.source == Source::Stdin {
+            return None;
+        }
+        let count = AtomicUsize::new(0);
+        self.walk_roots(
+            &|_| {
+                count.fetch_add(1, Ordering::Relaxed);
+                true
+            },
+            &|_, _| {},
+        );
+        Some(count.into_inner())
+    }
+
+    /// Walks every root of the source in turn, calling `visit` once per
+    /// file even where roots overlap or, with `dedupe`, where links lead to
+    /// the same file, until it returns `false`.
+    fn walk_roots(
+        &self,
+        visit: &(dyn Fn(&Path) -> bool + Sync),
+        on_error: &(dyn Fn(&Path, io::Error) + Sync),
+    ) {
+        if let Source::Files(files) = &self.options.source {
+            // spread over the workers like a walk's files are, and kept in
+            // order on one for a sorted walk
+            let threads = match self.options.walk.order {
+                walk::Order::Sorted => 1,
+                _ => self.options.threads.max(1),
+            };
+            let next = AtomicUsize::new(0);
+            let stopped = AtomicBool::new(false);
+            thread::scope(|scope| {
+                for _ in 0..threads {
+                    scope.spawn(|| {
+                        while !stopped.load(Ordering::Relaxed)
+                            && let Some(path) = files.get(next.fetch_add(1, Ordering::Relaxed))
+                        {
+                            if !visit(path) {
+                                stopped.store(true, Ordering::Relaxed);
+                            }
+                        }
+                    });
+                }
+            });
+            return;
+        }
+        let roots = self.options.source.roots();
+        let seen = Mutex::new(HashSet::new());
+        let dedupe = self.options.dedupe;
+        let first_visit = |path: &Path| {
+            (roots.len() == 1 && !dedupe) || seen.lock().unwrap().insert(FileId::of(path, dedupe))
+        };
+        let stopped = AtomicBool::new(false);
+        for root in roots {
+            walk::walk_with_errors(
+                root,
+                &self.options.walk.rooted_at(root),
+                self.options.threads,
+                &|path| {
+                    let going = !first_visit(path) || visit(path);
+                    if !going {
+                        stopped.store(true, Ordering::Relaxed);
+                    }
+                    going
+                },
+                on_error,
+            );
+            if stopped.load(Ordering::Relaxed) {
+                return;
+            }
+        }
+    }
+
+    /// Walks the search root (or reads stdin) and reports every file and
+    /// match to `on_event`, returning once the walk is done. `on_event` is
+    /// called from all the walker threads; returning `false` from it stops
+    /// the search.
+    pub fn run(&self, on_event: &(dyn Fn(SearchEvent) -> bool + Sync)) {
+        let started = Instant::now();
+        self.search_source(on_event);
+        let nanos = started.elapsed().as_nanos().max(1);
+        self.stats
+            .elapsed
+            .store(u64::try_from(nanos).unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    fn search_source(&self, on_event: &(dyn Fn(SearchEvent) -> bool + Sync)) {
+        let on_event = &|event| !self.cancel.is_cancelled() && on_event(event);
+        if self.options.source == Source::Stdin {
+            let path = Path::new(STDIN_PATH);
+            let mut bytes = Vec::new();
+            if !on_event(SearchEvent::Scanning(path.to_path_buf())) {
+                return;
+            }
+            match io::stdin().lock().read_to_end(&mut bytes) {
+                Ok(_) => {
+                    self.search_bytes(&bytes, path, &mut |result| {
+                        on_event(SearchEvent::Match(result))
+                    });
+                }
+                Err(err) => {
+                    on_event(SearchEvent::Error(path.to_path_buf(), err.to_string()));
+                }
+            }
+            return;
+        }
+        self.walk_roots(
+            &|path| {
+                on_event(SearchEvent::Scanning(path.to_path_buf()))
+                    && self.search_path(path, on_event)
+            },
+            &|path, err| {
+                on_event(SearchEvent::Error(path.to_path_buf(), err.to_string()));
+            },
+        );
+    }
+
+    /// Searches one file for [`Searcher::run`] and friends, reporting its
+    /// matches, or why it couldn't be read, to `on_event`.
+    fn search_path(&self, path: &Path, on_event: &dyn Fn(SearchEvent) -> bool) -> bool {
+        if self.options.match_paths {
+            return self.match_path(path, &mut |result| on_event(SearchEvent::Match(result)));
+        }
+        // a bug in one file's search shouldn't take the whole search down
+        let searched = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.try_search_file(path, &mut |result| on_event(SearchEvent::Match(result)))
+        }));
+        match searched {
+            Ok(Ok(keep_going)) => keep_going,
+            Ok(Err(err)) => on_event(SearchEvent::Error(path.to_path_buf(), err.to_string())),
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown cause".to_string());
+                let message = format!("searching it panicked: {}", message);
+                on_event(SearchEvent::Error(path.to_path_buf(), message))
+            }
+        }
+    }
+
+    /// Runs the search on a background thread, streaming its events and a
+    /// final [`SearchEvent::Done`]. Dropping the receiver stops the search.
+    pub fn spawn(self) -> Receiver<SearchEvent> {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            self.run(&|event| tx.send(event).is_ok());
+            let _ = tx.send(SearchEvent::Done);
+        });
+
+        rx
+    }
+
+    /// Like [`Searcher::spawn`], but keeps going after [`SearchEvent::Done`]:
+    /// every `interval` the tree is walked again, and each file whose size
+    /// or modification time changed is searched again after a
+    /// [`SearchEvent::Changed`]. Standard input is only read once.
+    pub fn watch(self, interval: Duration) -> Receiver<SearchEvent> {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let stdin = self.options.source == Source::Stdin;
+            // taken first, so files that change mid-search are searched again
+            let mut known = (!stdin).then(|| self.snapshot());
+            self.run(&|event| tx.send(event).is_ok());
+            if tx.send(SearchEvent::Done).is_err() {
+                return;
+            }
+            let Some(known) = known.as_mut() else {
+                return;
+            };
+            loop {
+                thread::sleep(interval);
+                if self.cancel.is_cancelled() {
+                    return;
+                }
+                let current = self.snapshot();
+                let mut changed: Vec<&PathBuf> = current
+                    .iter()
+                    .filter(|(path, state)| known.get(*path) != Some(state))
+                    .map(|(path, _)| path)
+                    .chain(known.keys().filter(|path| !current.contains_key(*path)))
+                    .collect();
+                changed.sort();
+                for path in changed {
+                    if self.cancel.is_cancelled()
+                        || tx.send(SearchEvent::Changed(path.clone())).is_err()
+                    {
+                        return;
+                    }
+                    if current.contains_key(path)
+                        && !self.search_path(path, &|event| tx.send(event).is_ok())
+                    {
+                        return;
+                    }
+                }
+                *known = current;
+            }
+        });
+
+        rx
+    }
+
+    /// Like [`Searcher::watch`], but for files that only grow, such as logs:
+    /// after [`SearchEvent::Done`], every `interval` the lines added to the
+    /// end of each file are searched, numbered on from those before them. A
+    /// file that shrank was truncated or replaced, and is searched from its
+    /// start again after a [`SearchEvent::Changed`]. Archives, compressed
+    /// files and standard input aren't followed.
+    pub fn tail(self, interval: Duration) -> Receiver<SearchEvent> {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let stdin = self.options.source == Source::Stdin;
+            // taken first, so no line added mid-search is missed
+            let sizes = (!stdin).then(|| self.snapshot());
+            // and lines added before the search read them aren't found twice
+            let reported = Mutex::new(HashMap::new());
+            self.run(&|event| {
+                if let SearchEvent::Match(result) = &event {
+                    let mut reported = reported.lock().unwrap();
+                    let last = reported.entry(result.path.clone()).or_insert(0);
+                    *last = result.line_number.max(*last);
+                }
+                tx.send(event).is_ok()
+            });
+            if tx.send(SearchEvent::Done).is_err() {
+                return;
+            }
+            let Some(sizes) = sizes else {
+                return;
+            };
+            let mut reported = reported.into_inner().unwrap();
+            let mut tails: HashMap<PathBuf, Tail> = sizes
+                .into_iter()
+                .map(|(path, (size, _))| {
+                    let reported = reported.remove(&path).unwrap_or(0);
+                    (path, Tail::scanned(size, reported))
+                })
+                .collect();
+            loop {
+                thread::sleep(interval);
+                if self.cancel.is_cancelled() {
+                    return;
+                }
+                let current = self.snapshot();
+                tails.retain(|path, _| current.contains_key(path));
+                let mut paths: Vec<(&PathBuf, u64)> = current
+                    .iter()
+                    .map(|(path, &(size, _))| (path, size))
+                    .collect();
+                paths.sort();
+                for (path, size) in paths {
+                    // files created since are all new lines
+                    let tail = tails.entry(path.clone()).or_insert_with(Tail::empty);
+                    if size == tail.size {
+                        continue;
+                    }
+                    if self.cancel.is_cancelled()
+                        || !self.search_tail(path, size, tail, &|event| tx.send(event).is_ok())
+                    {
+                        return;
+                    }
+                }
+            }
+        });
+
+        rx
+    }
+
+    /// Searches the complete lines `path` has past `tail` now that it's
+    /// `size` bytes long, moving `tail` on past them. Returns `false` as
+    /// soon as `on_event` does.
+    fn search_tail(
+        &self,
+        path: &Path,
+        size: u64,
+        tail: &mut Tail,
+        on_event: &dyn Fn(SearchEvent) -> bool,
+    ) -> bool {
+        if (self.options.archives && archive::Kind::from_path(path).is_some())
+            || (self.options.compressed && Compression::from_path(path).is_some())
+        {
+            return true;
+        }
+        if size < tail.size {
+            *tail = Tail::empty();
+            if !on_event(SearchEvent::Changed(path.to_path_buf())) {
+                return false;
+            }
+        }
+        // it may be in the middle of being rotated
+        let Ok(bytes) = mmap::read(path) else {
+            return true;
+        };
+        tail.size = size;
+        let mut start = (tail.offset as usize).min(bytes.len());
+        let lines = match tail.lines {
+            Some(lines) => lines,
+            None => {
+                // the scan may have stopped partway into a line still being written
+                start = memchr::memrchr(b'\n', &bytes[..start]).map_or(0, |i| i + 1);
+                memchr::memchr_iter(b'\n', &bytes[..start]).count()
+            }
+        };
+        let Some(end) = memchr::memrchr(b'\n', &bytes[start..]).map(|i| start + i + 1) else {
+            tail.offset = start as u64;
+            tail.lines = Some(lines);
+            return true;
+        };
+        tail.offset = end as u64;
+        tail.lines = Some(lines + memchr::memchr_iter(b'\n', &bytes[start..end]).count());
+        let reported = tail.reported;
+        self.search_contents(&bytes[start..end], path, false, &mut |mut result| {
+            result.line_number += lines;
+            if result.line_number <= reported {
+                return true;
+            }
+            tail.reported = result.line_number;
+            if let Some(definition) = &mut result.definition {
+                definition.line_number += lines;
+            }
+            on_event(SearchEvent::Match(result))
+        })
+    }
+
+    /// The size and modification time of every file a search would look at.
+    fn snapshot(&self) -> HashMap<PathBuf, (u64, Option<SystemTime>)> {
+        let files = Mutex::new(HashMap::new());
+        self.walk_roots(
+            &|path| {
+                if let Ok(metadata) = fs::metadata(path) {
+                    let state = (metadata.len(), metadata.modified().ok());
+                    files.lock().unwrap().insert(path.to_path_buf(), state);
+                }
+                true
+            },
+            &|_, _| {},
+        );
+        files.into_inner().unwrap()
+    }
+
+    /// Runs the search in the background and iterates over its matches.
+    pub fn matches(self) -> impl Iterator<Item = Match> {
+        self.spawn().into_iter().filter_map(|event| match event {
+            SearchEvent::Match(result) => Some(result),
+            _ => None,
+        })
+    }
+
+    /// Searches a single file, handing each match to `emit` once its
+    /// trailing context is complete. Returns `false` as soon as `emit` does.
+    /// Files that can't be read are skipped.
+    pub fn search_file(&self, path: &Path, emit: &mut dyn FnMut(Match) -> bool) -> bool {
+        self.try_search_file(path, emit).unwrap_or(true)
+    }
+
+    fn try_search_file(
+        &self,
+        path: &Path,
+        emit: &mut dyn FnMut(Match) -> bool,
+    ) -> io::Result<bool> {
+        let bytes = mmap::read(path)?;
+        if self.options.archives
+            && let Some(kind) = archive::Kind::from_path(path)
+        {
+            return archive::for_each_entry(kind, &bytes, &mut |name, contents| {
+                self.search_bytes(contents, &archive::entry_path(path, name), emit)
+            });
+        }
+        if self.options.compressed
+            && let Some(compression) = Compression::from_path(path)
+        {
+            let contents = compression.decompress(path, &bytes)?;
+            return Ok(self.search_bytes(&contents, path, emit));
+        }
+        Ok(self.search_bytes(&bytes, path, emit))
+    }
+
+    /// Counts a match against [`SearchOptions::max_total`] before it's
+    /// reported, so concurrent files can't overshoot: `None` once the total
+    /// is reached, else whether this is the last match allowed.
+    fn claim(&self) -> Option<bool> {
+        let Some(max) = self.options.max_total else {
+            return Some(false);
+        };
+        let claimed = self.claimed.fetch_add(1, Ordering::Relaxed);
+        (claimed < max).then_some(claimed + 1 == max)
+    }
+
+    /// Matches the regex against `path` for [`SearchOptions::match_paths`]:
+    /// against the part below the root searched, or the file's name when
+    /// the root is the file itself. Returns `false` as soon as `emit` does.
+    fn match_path(&self, path: &Path, emit: &mut dyn FnMut(Match) -> bool) -> bool {
+        self.stats.files.fetch_add(1, Ordering::Relaxed);
+        // the first root the path is below is the one it was found under
+        let name = match self
+            .options
+            .source
+            .roots()
+            .iter()
+            .find(|r| path.starts_with(r))
+        {
+            Some(root) => walk::below_root(root, path),
+            None => path,
+        };
+        let line = name.to_string_lossy().into_owned();
+        let spans = self.options.regex.find_ranges(&line);
+        if spans.is_empty() != self.options.invert {
+            return true;
+        }
+        let Some(last) = self.claim() else {
+            return false;
+        };
+        self.stats.matches.fetch_add(1, Ordering::Relaxed);
+        self.stats.matched_files.fetch_add(1, Ordering::Relaxed);
+        let result = Match {
+            path: path.to_path_buf(),
+            line_number: 1,
+            spans,
+            replacement: None,
+            patterns: matching_patterns(&self.options, &line),
+            line,
+            before: Vec::new(),
+            after: Vec::new(),
+            definition: None,
+        };
+        emit(result) && !last
+    }
+
+    /// Like [`Searcher::search_file`] for contents already in memory,
+    /// reporting matches under `path`.
+    pub fn search_bytes(
+        &self,
+        bytes: &[u8],
+        path: &Path,
+        emit: &mut dyn FnMut(Match) -> bool,
+    ) -> bool {
+        self.stats.files.fetch_add(1, Ordering::Relaxed);
+        self.search_contents(bytes, path, true, emit)
+    }
+
+    /// Searches `bytes` of `path`, which are the whole file when `whole`
+    /// and else lines added to one already searched, which the stats
+    /// don't count again.
+    fn search_contents(
+        &self,
+        bytes: &[u8],
+        path: &Path,
+        whole: bool,
+        emit: &mut dyn FnMut(Match) -> bool,
+    ) -> bool {
+        self.stats
+            .bytes
+            .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        let mut matched = !whole;
+        let emit = &mut |result| {
+            let Some(last) = self.claim() else {
+                return false;
+            };
+            self.stats.matches.fetch_add(1, Ordering::Relaxed);
+            if !matched {
+                matched = true;
+                self.stats.matched_files.fetch_add(1, Ordering::Relaxed);
+            }
+            // and stop the walk once the total is reached
+            emit(result) && !last
+        };
+        let bytes = &encoding::decode(bytes, self.options.encoding);
+        // a second pass over the file, only as far as its last match
+        let mut definitions = self
+            .options
+            .definitions
+            .then(|| definitions::Scanner::new(path, bytes))
+            .flatten();
+        let emit = &mut |mut result: Match| {
+            if let Some(scanner) = &mut definitions {
+                result.definition = scanner.before(result.line_number);
+            }
+            emit(result)
+        };
+        let search = |emit: &mut dyn FnMut(Match) -> bool| {
+            if self.options.multiline {
+                search_multiline(bytes, path, &self.options, emit)
+            } else {
+                search_lines(bytes, path, &self.options, self.prefilter.as_ref(), emit)
+            }
+        };
+        let Some(max) = self.options.max_count else {
+            return search(emit);
+        };
+        if max == 0 {
+            return true;
+        }
+        // stop this file after `max` matches, but only stop the search if asked to
+        let (mut count, mut keep_going) = (0, true);
+        search(&mut |result| {
+            count += 1;
+            keep_going = emit(result);
+            keep_going && count < max
+        });
+        keep_going
+    }
+}
+
+/// How far [`Searcher::tail`] has searched a file.
+struct Tail {
+    /// The file's size when last looked at.
+    size: u64,
+    /// Where the lines not yet searched start.
+    offset: u64,
+    /// How many lines come before `offset`; `None` until counted, when
+    /// `offset` is only the size the file had when the search started.
+    lines: Option<usize>,
+    /// The number of the last line reported as a match, which the file may
+    /// have grown past before the first search read it.
+    reported: usize,
+}
+
+impl Tail {
+    /// A file seen for the first time, all of whose lines are new.
+    fn empty() -> Tail {
+        Tail {
+            size: 0,
+            offset: 0,
+            lines: Some(0),
+            reported: 0,
+        }
+    }
+
+    /// A file that was `size` bytes long when the first search started,
+    /// which reported matches up to line `reported`.
+    fn scanned(size: u64, reported: usize) -> Tail {
+        Tail {
+            size,
+            offset: size,
+            lines: None,
+            reported,
+        }
+    }
+}
+
+/// Which of the individual patterns match `line`, for [`Match::patterns`].
+fn matching_patterns(options: &SearchOptions, line: &str) -> Vec<usize> {
+    match &options.patterns {
+        Some(set) if !options.invert => set.matches(line).into_iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// `pattern` made to match across a whole file wherever it matches one of
+/// its lines: `\A` and `\z` outside classes, which would only match at
+/// the file's ends, become `^` and `$`. `None` when a flag group turns
+/// multi-line mode off, which would do the same to `^` and `$`.
+fn prefilter_source(pattern: &str) -> Option<String> {
+    let mut source = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+    let mut class = 0usize;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('A') if class == 0 => source.push('^'),
+                Some('z') if class == 0 => source.push('$'),
+                Some(escaped) => {
+                    source.push(c);
+                    source.push(escaped);
+                }
+                None => source.push(c),
+            },
+            '[' => {
+                class += 1;
+                source.push(c);
+            }
+            ']' if class > 0 => {
+                class -= 1;
+                source.push(c);
+            }
+            '(' if class == 0 && chars.peek() == Some(&'?') => {
+                source.push(c);
+                let flags: String = chars
+                    .clone()
+                    .skip(1)
+                    .take_while(|&c| c != ':' && c != ')')
+                    .collect();
+                if flags
+                    .split_once('-')
+                    .is_some_and(|(_, off)| off.contains('m'))
+                {
+                    return None;
+                }
+            }
+            c => source.push(c),
+        }
+    }
+    Some(source)
+}
+
+/// Searches a file line by line. When it's valid UTF-8, `prefilter` skips
+/// straight to the next line that may match, so line numbers and context are
+/// only worked out around matches. Lines that aren't valid UTF-8 are skipped.
+fn search_lines(
+    bytes: &[u8],
+    path: &Path,
+    options: &SearchOptions,
+    prefilter: Option<&Regex>,
+    emit: &mut dyn FnMut(Match) -> bool,
+) -> bool {
+    // every line that doesn't match is a result when inverting
+    let prefilter = prefilter.filter(|_| !options.invert);
+    let text = prefilter.and_then(|prefilter| Some((prefilter, std::str::from_utf8(bytes).ok()?)));
+
+    let mut before = VecDeque::with_capacity(options.before_context);
+    let mut pending: Option<Match> = None;
+    // start and 0-based number of the next line to look at
+    let mut pos = 0;
+    let mut i = 0;
+
+    while pos < bytes.len() {
+        // while a match still wants trailing context every line counts
+        if pending.is_none()
+            && let Some((prefilter, text)) = text
+        {
+            let Some(found) = prefilter.find_at(text, pos) else {
+                break;
+            };
+            let start =
+                memchr::memrchr(b'\n', &bytes[pos..found.start()]).map_or(pos, |n| pos + n + 1);
+            if start > pos {
+                let skipped = &text[pos..start - 1];
+                i += memchr::memchr_iter(b'\n', skipped.as_bytes()).count() + 1;
+                let tail: Vec<&str> = skipped.rsplit('\n').take(options.before_context).collect();
+                for line in tail.into_iter().rev() {
+                    if before.len() == options.before_context {
+                        before.pop_front();
+                    }
+                    before.push_back(line_text(line, true).to_string());
+                }
+                pos = start;
+            }
+            // an empty match after the last newline isn't on a line
+            if pos == bytes.len() {
+                break;
+            }
+        }
+
+        let end = memchr::memchr(b'\n', &bytes[pos..]).map_or(bytes.len(), |n| pos + n);
+        let raw = match text {
+            Some((_, text)) => Ok(&text[pos..end]),
+            None => std::str::from_utf8(&bytes[pos..end]),
+        };
+        let terminated = end < bytes.len();
+        pos = end + 1;
+        i += 1;
+        let Ok(raw) = raw else {
+            continue;
+        };
+        let line = line_text(raw, terminated).to_string();
+
+        let spans = options.regex.find_ranges(&line);
+        let found = match spans.is_empty() {
+            false if !options.invert => Some(spans),
+            true if options.invert => Some(Vec::new()),
+            _ => None,
+        };
+        if let Some(spans) = found {
+            // a match cuts the previous match's trailing context short
+            if let Some(result) = pending.take()
+                && !emit(result)
+            {
+                return false;
+            }
+            let replacement = options
+                .replace
+                .as_ref()
+                .filter(|_| !options.invert)
+                .map(|template| options.regex.replace_all(&line, template));
+            pending = Some(Match {
+                path: path.to_path_buf(),
+                line_number: i,
+                spans,
+                replacement,
+                patterns: matching_patterns(options, &line),
+                line,
+                before: before.drain(..).collect(),
+                after: Vec::new(),
+                definition: None,
+            });
+        } else if let Some(result) = &mut pending
+            && result.after.len() < options.after_context
+        {
+            result.after.push(line);
+        } else if options.before_context > 0 {
+            // only lines that aren't already some match's context land here
+            if before.len() == options.before_context {
+                before.pop_front();
+            }
+            before.push_back(line);
+        }
+
+        if let Some(result) = &pending
+            && result.after.len() == options.after_context
+            && !emit(pending.take().unwrap())
+        {
+            return false;
+        }
+    }
+
+    match pending {
+        Some(result) => emit(result),
+        None => true,
+    }
+}
+
+/// A line without its terminator, the way `BufRead::lines` yields it: a
+/// `\r` only goes along with a following `\n`.
+fn line_text(raw: &str, terminated: bool) -> &str {
+    match raw.strip_suffix('\r') {
+        Some(rest) if terminated => rest,
+        _ => raw,
+    }
+}
+
+/// Searches a whole file at once so matches may span lines. A match's
+/// `line` holds every line it touches; later matches starting on one of
+/// those lines are folded into it.
+fn search_multiline(
+    bytes: &[u8],
+    path: &Path,
+    options: &SearchOptions,
+    emit: &mut dyn FnMut(Match) -> bool,
+) -> bool {
+    let contents = String::from_utf8_lossy(bytes);
+
+    // byte offset where each line starts
+    let mut starts = vec![0];
+    starts.extend(contents.match_indices('\n').map(|(i, _)| i + 1));
+    if starts.last() == Some(&contents.len()) && !contents.is_empty() {
+        starts.pop();
+    }
+    let line_of = |offset: usize| starts.partition_point(|&start| start <= offset) - 1;
+    let line_end = |line: usize| {
+        let end = starts
+            .get(line + 1)
+            .map_or(contents.len(), |&next| next - 1);
+        let text = &contents[starts[line]..end];
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        starts[line] + text.trim_end_matches('\r').len()
+    };
+
+    // (first line, last line, match ranges) with 0-based line indices
+    let mut spans: Vec<(usize, usize, Vec<Range<usize>>)> = Vec::new();
+    for found in options.regex.find_ranges(&contents) {
+        let first = line_of(found.start);
+        if let Some((_, last, ranges)) = spans.last_mut()
+            && first <= *last
+        {
+            ranges.push(found);
+            continue;
+        }
+        // a match ending right after a newline doesn't touch the next line
+        let last = line_of(found.end.saturating_sub(1).max(found.start));
+        spans.push((first, last, vec![found]));
+    }
+
+    let line_text = |line: usize| contents[starts[line]..line_end(line)].to_string();
+    for (k, (first, last, ranges)) in spans.iter().enumerate() {
+        let (first, last) = (*first, *last);
+        let previous_last = if k == 0 { None } else { Some(spans[k - 1].1) };
+        let next_first = spans.get(k + 1).map_or(starts.len(), |span| span.0);
+
+        let before_start = first
+            .saturating_sub(options.before_context)
+            .max(previous_last.map_or(0, |l| l + 1));
+        let after_end = (last + options.after_context).min(next_first - 1);
+
+        let line_start = starts[first];
+        let line = contents[line_start..line_end(last)].to_string();
+        let relative = |range: &Range<usize>| {
+            let end = (range.end - line_start).min(line.len());
+            (range.start - line_start).min(end)..end
+        };
+        let replacement = options
+            .replace
+            .as_ref()
+            .map(|template| options.regex.replace_all(&line, template));
+
+        let result = Match {
+            path: path.to_path_buf(),
+            line_number: first + 1,
+            spans: ranges.iter().map(relative).collect(),
+            replacement,
+            patterns: matching_patterns(options, &line),
+            line,
+            before: (before_start..first).map(line_text).collect(),
+            after: (last + 1..=after_end).map(line_text).collect(),
+            definition: None,
+        };
+        if !emit(result) {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// The numbers of the lines of `text` that a search for `pattern` finds,
+    /// with the prefilter it gets.
+    fn matching_lines(pattern: &str, text: &str) -> Vec<usize> {
+        search_with(pattern, text, true)
+    }
+
+    fn search_with(pattern: &str, text: &str, prefiltered: bool) -> Vec<usize> {
+        let searcher = Searcher::new(SearchOptions::new("test", Regex::new(pattern).unwrap()));
+        let prefilter = searcher.prefilter.as_ref().filter(|_| prefiltered);
+        let mut found = Vec::new();
+        search_lines(
+            text.as_bytes(),
+            Path::new("test"),
+            &searcher.options,
+            prefilter,
+            &mut |result| {
+                found.push(result.line_number);
+                true
+            },
+        );
+        found
+    }
+
+    #[test]
+    fn prefilter_anchors_match_at_every_line() {
+        assert_eq!(prefilter_source(r"\Afoo\z").as_deref(), Some("^foo$"));
+        assert_eq!(prefilter_source(r"\\A[\z]").as_deref(), Some(r"\\A[\z]"));
+        assert_eq!(prefilter_source(r"(?-m:^foo)"), None);
+        assert_eq!(
+            prefilter_source(r"(?i-s:foo)").as_deref(),
+            Some("(?i-s:foo)")
+        );
+    }
+
+    #[test]
+    fn start_and_end_of_text_anchors_match_each_line() {
+        let text = "foo\nfoo\nbar foo";
+        assert_eq!(matching_lines(r"\Afoo", text), [1, 2]);
+        assert_eq!(matching_lines(r"foo\z", text), [1, 2, 3]);
+        assert_eq!(matching_lines(r"(?-m:^foo)", text), [1, 2]);
+    }
+
+    #[test]
+    fn empty_matches_after_the_last_newline_are_no_line() {
+        assert_eq!(matching_lines("^$", "a\n\nb\n"), [2]);
+        assert_eq!(matching_lines("^$", "a\r\n"), [] as [usize; 0]);
+    }
+
+    #[test]
+    fn the_prefilter_skips_no_line_that_matches() {
+        let patterns = [
+            "foo",
+            r"\bfoo\b",
+            r"^\s*fn ",
+            "o$",
+            r"(?i)FOO",
+            r"\Afoo",
+            r"foo\z",
+            r"(?-m:^foo|bar$)",
+            r"(?s:f.o)",
+            "[^a]oo",
+            r"\x41",
+            r"^$",
+            r"fo+\b|\d{2,}",
+        ];
+        let texts = [
+            "foo\nfoo\nbar foo",
+            "  fn main() {\r\n    food();\r\n}\r\n",
+            "\nFOO\n\nxoo bar\n",
+            "A 12 foo\tbar\nzoo",
+            "",
+        ];
+        for pattern in patterns {
+            for text in texts {
+                assert_eq!(
+                    matching_lines(pattern, text),
+                    search_with(pattern, text, false),
+                    "{:?} in {:?}",
+                    pattern,
+                    text
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tails_skip_lines_the_first_search_already_reported() {
+        let file = env::temp_dir().join(format!("rstr-tail-{}.log", std::process::id()));
+        let searcher = Searcher::new(SearchOptions::new(
+            file.as_path(),
+            Regex::new("ERROR").unwrap(),
+        ));
+        let tailed = |tail: &mut Tail| {
+            let size = fs::metadata(&file).unwrap().len();
+            let found = Mutex::new(Vec::new());
+            searcher.search_tail(&file, size, tail, &|event| {
+                if let SearchEvent::Match(result) = event {
+                    found.lock().unwrap().push(result.line_number);
+                }
+                true
+            });
+            found.into_inner().unwrap()
+        };
+        // the first search started at 6 bytes, but read on to line 2
+        fs::write(
+            &file,
+            "ERROR
+ERROR again
+",
+        )
+        .unwrap();
+        let mut tail = Tail::scanned(6, 2);
+        assert!(tailed(&mut tail).is_empty());
+        fs::write(
+            &file,
+            "ERROR
+ERROR again
+ok
+ERROR last
+",
+        )
+        .unwrap();
+        assert_eq!(tailed(&mut tail), [4]);
+        fs::write(
+            &file, "ERROR
+",
+        )
+        .unwrap();
+        assert_eq!(tailed(&mut tail), [1]);
+        fs::remove_file(&file).unwrap();
+    }
+}
@@ -0,0 +1,340 @@
+//! Saved searches (`--save-session` / `--load-session`): the pattern, the
+//! flags and every match, so a search can be reopened without rescanning.
+//!
+//! A session is JSON Lines: a header object followed by one object per
+//! match, which says whether it was marked in the TUI and with what note.
+
+use crate::json::{self, Value};
+use crate::tui;
+use rstr::definitions::Definition;
+use rstr::{Match, SearchEvent};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+const VERSION: usize = 1;
+
+pub struct Session {
+    /// The patterns searched for, more than one with `-e` or `-f`.
+    pub patterns: Vec<String>,
+    /// Where it searched; saved as `path`, with `paths` listing them all
+    /// when there were several.
+    pub paths: Vec<String>,
+    /// The flags the search ran with, config file included.
+    pub args: Vec<String>,
+    pub files: usize,
+    pub bytes: u64,
+    pub matches: Vec<Match>,
+    pub marks: tui::Marks,
+}
+
+/// Passes the events of `search` through while keeping its matches, and
+/// writes them to `file` as a session once the search is done or its
+/// receiver goes away. The handle reports whether writing worked.
+pub fn record(
+    search: tui::Search,
+    mut session: Session,
+    file: PathBuf,
+) -> (tui::Search, JoinHandle<io::Result<()>>) {
+    let (tx, events) = mpsc::channel();
+    let stats = search.stats.clone();
+    let source = search.events;
+    let handle = thread::spawn(move || {
+        for event in source {
+            let done = matches!(event, SearchEvent::Done);
+            if let SearchEvent::Match(result) = &event {
+                session.matches.push(result.clone());
+            }
+            if tx.send(event).is_err() || done {
+                break;
+            }
+        }
+        session.files = stats.files();
+        session.bytes = stats.bytes();
+        save(&file, &session)
+    });
+    let search = tui::Search { events, ..search };
+    (search, handle)
+}
+
+/// Replays a loaded session as if it were a search that just finished.
+pub fn replay(session: Session) -> tui::Search {
+    tui::Search {
+        marks: session.marks,
+        ..tui::Search::finished(session.matches, session.files, session.bytes)
+    }
+}
+
+/// Keeps `marks` with the session saved in `file`, for the matches it has.
+pub fn annotate(file: &Path, marks: tui::Marks) -> Result<(), String> {
+    let session = Session {
+        marks,
+        ..load(file)?
+    };
+    save(file, &session).map_err(|err| format!("{}: {}", file.display(), err))
+}
+
+fn save(file: &Path, session: &Session) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(file)?);
+    writeln!(
+        out,
+        r#"{{"rstr_session":{},"patterns":{},"path":{}{},"args":{},"files":{},"bytes":{}}}"#,
+        VERSION,
+        json::string_array(&session.patterns),
+        json::string(session.paths.first().map_or("", String::as_str)),
+        if session.paths.len() > 1 {
+            format!(r#","paths":{}"#, json::string_array(&session.paths))
+        } else {
+            String::new()
+        },
+        json::string_array(&session.args),
+        session.files,
+        session.bytes
+    )?;
+    for result in &session.matches {
+        write!(
+            out,
+            r#"{{"path":{},"line":{},"start":{},"end":{},"text":{},"before":{},"after":{}"#,
+            json::string(&result.path.to_string_lossy()),
+            result.line_number,
+            result.matched().start,
+            result.matched().end,
+            json::string(&result.line),
+            json::string_array(&result.before),
+            json::string_array(&result.after)
+        )?;
+        // start and end cover the usual single match
+        if result.spans.len() != 1 {
+            let spans: Vec<String> = result
+                .spans
+                .iter()
+                .map(|span| format!("[{},{}]", span.start, span.end))
+                .collect();
+            write!(out, r#","spans":[{}]"#, spans.join(","))?;
+        }
+        if let Some(replacement) = &result.replacement {
+            write!(out, r#","replacement":{}"#, json::string(replacement))?;
+        }
+        if !result.patterns.is_empty() {
+            let indices: Vec<String> = result.patterns.iter().map(|i| i.to_string()).collect();
+            write!(out, r#","patterns":[{}]"#, indices.join(","))?;
+        }
+        if let Some(definition) = &result.definition {
+            write!(out, r#","definition":{}"#, json::definition(definition))?;
+        }
+        match session
+            .marks
+            .get(&(result.path.clone(), result.line_number))
+        {
+            Some(note) if note.is_empty() => write!(out, r#","marked":true"#)?,
+            Some(note) => write!(out, r#","marked":true,"note":{}"#, json::string(note))?,
+            None => {}
+        }
+        writeln!(out, "}}")?;
+    }
+    out.flush()
+}
+
+/// Reads a session written by [`record`], reporting problems by line.
+pub fn load(file: &Path) -> Result<Session, String> {
+    let text = fs::read_to_string(file).map_err(|err| format!("{}: {}", file.display(), err))?;
+    let error = |line: usize, message: &str| format!("{}:{}: {}", file.display(), line, message);
+
+    let mut lines = text.lines().enumerate();
+    let header = match lines.next() {
+        Some((_, line)) => json::parse(line).map_err(|err| error(1, &err))?,
+        None => return Err(error(1, "empty session file")),
+    };
+    if header.get("rstr_session").and_then(Value::as_usize) != Some(VERSION) {
+        return Err(error(1, "not an rstr session, or one from another version"));
+    }
+    let strings = |value: Option<&Value>| -> Option<Vec<String>> {
+        value?
+            .as_array()?
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect()
+    };
+    let mut session = Session {
+        patterns: strings(header.get("patterns")).ok_or_else(|| error(1, "missing patterns"))?,
+        paths: match strings(header.get("paths")) {
+            Some(paths) => paths,
+            None => vec![
+                header
+                    .get("path")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| error(1, "missing path"))?
+                    .to_string(),
+            ],
+        },
+        args: strings(header.get("args")).unwrap_or_default(),
+        files: header.get("files").and_then(Value::as_usize).unwrap_or(0),
+        bytes: header.get("bytes").and_then(Value::as_usize).unwrap_or(0) as u64,
+        matches: Vec::new(),
+        marks: tui::Marks::new(),
+    };
+
+    for (i, line) in lines {
+        let value = json::parse(line).map_err(|err| error(i + 1, &err))?;
+        let number = |key: &str| value.get(key).and_then(Value::as_usize);
+        let parsed = (|| {
+            let line = value.get("text")?.as_str()?.to_string();
+            let span = |start: usize, end: usize| {
+                (start <= end && line.is_char_boundary(start) && line.is_char_boundary(end))
+                    .then_some(start..end)
+            };
+            // every match when there are several, else just the first
+            let spans = match value.get("spans").and_then(Value::as_array) {
+                Some(items) => items
+                    .iter()
+                    .map(|item| match item.as_array()? {
+                        [start, end] => span(start.as_usize()?, end.as_usize()?),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?,
+                None => vec![span(number("start")?, number("end")?)?],
+            };
+            let before = strings(value.get("before"))?;
+            let line_number = number("line").filter(|&n| n > before.len())?;
+            Some(Match {
+                path: PathBuf::from(value.get("path")?.as_str()?),
+                line_number,
+                spans,
+                before,
+                after: strings(value.get("after"))?,
+                replacement: value
+                    .get("replacement")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                patterns: value
+                    .get("patterns")
+                    .and_then(Value::as_array)
+                    .map(|items| items.iter().filter_map(Value::as_usize).collect())
+                    .unwrap_or_default(),
+                definition: value.get("definition").and_then(|definition| {
+                    Some(Definition {
+                        line_number: definition.get("line")?.as_usize()?,
+                        name: definition.get("name")?.as_str()?.to_string(),
+                    })
+                }),
+                line,
+            })
+        })();
+        let parsed = parsed.ok_or_else(|| error(i + 1, "not a valid match"))?;
+        if value.get("marked") == Some(&Value::Boolean(true)) {
+            let note = value.get("note").and_then(Value::as_str).unwrap_or("");
+            session
+                .marks
+                .insert((parsed.path.clone(), parsed.line_number), note.to_string());
+        }
+        session.matches.push(parsed);
+    }
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// A file under the system's temporary directory holding `contents`.
+    fn file(name: &str, contents: &str) -> PathBuf {
+        let name = format!("rstr-session-{}-{}.jsonl", name, std::process::id());
+        let file = env::temp_dir().join(name);
+        fs::write(&file, contents).unwrap();
+        file
+    }
+
+    #[test]
+    fn sessions_read_back_as_they_were_written() {
+        let saved = [
+            r#"{"rstr_session":1,"patterns":["fo+","bar"],"path":"src","paths":["src","tests"],"args":["-i","--hidden"],"files":2,"bytes":300}"#,
+            r#"{"path":"src/a.rs","line":3,"start":4,"end":7,"text":"let foo = \"é\";","before":["a","b"],"after":["c"]}"#,
+            r#"{"path":"src/a.rs","line":9,"start":0,"end":3,"text":"foo bar foo","before":[],"after":[],"spans":[[0,3],[4,7],[8,11]],"replacement":"X X X","patterns":[0,1],"definition":{"line":7,"name":"fn main"},"marked":true}"#,
+            r#"{"path":"tests/b.rs","line":1,"start":0,"end":3,"text":"bar","before":[],"after":[],"marked":true,"note":"check this"}"#,
+        ]
+        .map(|line| format!("{}\n", line))
+        .concat();
+        let file = file("round-trip", &saved);
+        let session = load(&file).unwrap();
+        assert_eq!(session.patterns, ["fo+", "bar"]);
+        assert_eq!(session.paths, ["src", "tests"]);
+        assert_eq!(session.args, ["-i", "--hidden"]);
+        assert_eq!((session.files, session.bytes), (2, 300));
+        assert_eq!(session.matches.len(), 3);
+        let first = &session.matches[0];
+        assert_eq!(first.path, Path::new("src/a.rs"));
+        assert_eq!(first.line_number, 3);
+        assert_eq!(first.matched(), 4..7);
+        assert_eq!(first.spans.len(), 1);
+        assert_eq!(first.line, "let foo = \"é\";");
+        assert_eq!(first.before, ["a", "b"]);
+        assert_eq!(first.after, ["c"]);
+        assert_eq!(session.matches[1].spans, [0..3, 4..7, 8..11]);
+        assert_eq!(session.marks.len(), 2);
+        assert_eq!(
+            session.marks[&(PathBuf::from("tests/b.rs"), 1)],
+            "check this"
+        );
+
+        save(&file, &session).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), saved);
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn loading_reports_problems_by_line() {
+        let header = r#"{"rstr_session":1,"patterns":["a"],"path":"."}"#;
+        let valid = r#"{"path":"a","line":1,"start":0,"end":1,"text":"a","before":[],"after":[]}"#;
+        let cases = [
+            ("", ":1: empty session file"),
+            (
+                "{\"rstr_session\":2}\n",
+                ":1: not an rstr session, or one from another version",
+            ),
+            (
+                "{\"patterns\":[\"a\"]}\n",
+                ":1: not an rstr session, or one from another version",
+            ),
+            (
+                "{\"rstr_session\":1,\"path\":\".\"}\n",
+                ":1: missing patterns",
+            ),
+            (
+                &format!("{}\n{}\n{{\"path\":\n", header, valid),
+                ":3: expected a value, found the end",
+            ),
+            // the match starts after it ends
+            (
+                &format!(
+                    "{}\n{}\n",
+                    header,
+                    valid
+                        .replace("\"end\":1", "\"end\":0")
+                        .replace("\"start\":0", "\"start\":1")
+                ),
+                ":2: not a valid match",
+            ),
+            // it's on the first line, with a line of context before it
+            (
+                &format!(
+                    "{}\n{}\n",
+                    header,
+                    valid.replace("\"before\":[]", "\"before\":[\"z\"]")
+                ),
+                ":2: not a valid match",
+            ),
+        ];
+        for (i, (contents, error)) in cases.iter().enumerate() {
+            let file = file(&format!("error-{}", i), contents);
+            let message = match load(&file) {
+                Ok(_) => String::from("loaded"),
+                Err(message) => message,
+            };
+            assert_eq!(message, format!("{}{}", file.display(), error));
+            fs::remove_file(file).unwrap();
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use clap::ValueEnum;
+use std::cmp::Ordering;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The order `--sort` lists files in. Matches keep their line order
+/// within a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortBy {
+    /// By path
+    Path,
+    /// Most recently modified first
+    Modified,
+    /// Largest first
+    Size,
+    /// Most matches first
+    Matches,
+}
+
+/// What files are sorted by besides their path and match count, read once
+/// when their first match arrives.
+#[derive(Clone, Copy, Default)]
+pub struct FileMeta {
+    pub modified: Option<SystemTime>,
+    pub size: u64,
+}
+
+impl FileMeta {
+    /// Zeroed for standard input or files that vanished since the search.
+    pub fn read(path: &Path) -> FileMeta {
+        match fs::metadata(path) {
+            Ok(metadata) => FileMeta {
+                modified: metadata.modified().ok(),
+                size: metadata.len(),
+            },
+            Err(_) => FileMeta::default(),
+        }
+    }
+}
+
+/// A file as the sort sees it.
+pub struct SortKey<'a> {
+    pub path: &'a Path,
+    pub meta: FileMeta,
+    pub matches: usize,
+}
+
+impl SortBy {
+    pub fn compare(self, a: &SortKey, b: &SortKey) -> Ordering {
+        let order = match self {
+            SortBy::Path => Ordering::Equal,
+            SortBy::Modified => b.meta.modified.cmp(&a.meta.modified),
+            SortBy::Size => b.meta.size.cmp(&a.meta.size),
+            SortBy::Matches => b.matches.cmp(&a.matches),
+        };
+        order.then_with(|| a.path.cmp(b.path))
+    }
+
+    /// The order after `sort` when cycling through them in the TUI, with
+    /// `None` being the order results arrived in.
+    pub fn cycle(sort: Option<SortBy>) -> Option<SortBy> {
+        match sort {
+            None => Some(SortBy::Path),
+            Some(SortBy::Path) => Some(SortBy::Modified),
+            Some(SortBy::Modified) => Some(SortBy::Size),
+            Some(SortBy::Size) => Some(SortBy::Matches),
+            Some(SortBy::Matches) => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortBy::Path => "path",
+            SortBy::Modified => "modified",
+            SortBy::Size => "size",
+            SortBy::Matches => "matches",
+        }
+    }
+}
@@ -0,0 +1,412 @@
+//! Syntax highlighting for the preview pane: keywords, strings, comments
+//! and numbers, told apart per language by a small table rather than a
+//! full grammar. Good enough to scan context by; the styles come from the
+//! theme.
+
+use rstr::types::{TypeFilter, TypeRegistry};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+/// Files larger than this aren't highlighted, so opening a huge log in the
+/// preview stays quick.
+const MAX_BYTES: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Keyword,
+    String,
+    Comment,
+    Number,
+}
+
+/// What a language's source looks like, as far as highlighting goes.
+struct Language {
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// `'` starts a char literal only when one char and a `'` follow, as
+    /// Rust's lifetimes use it too.
+    char_literals: bool,
+    /// Separated by spaces.
+    keywords: &'static str,
+}
+
+const C_KEYWORDS: &str = "\
+    auto break case char const continue default do double else enum extern float for goto if \
+    inline int long register return short signed sizeof static struct switch typedef union \
+    unsigned void volatile while bool true false NULL";
+
+const CPP_KEYWORDS: &str = "\
+    auto break case char const constexpr continue default delete do double else enum \
+    explicit extern float for friend goto if inline int long namespace new noexcept nullptr \
+    operator override private protected public return short signed sizeof static struct \
+    switch template this throw try catch typedef typename union unsigned using virtual void \
+    volatile while bool true false class";
+
+const JS_KEYWORDS: &str = "\
+    async await break case catch class const continue debugger default delete do else export \
+    extends false finally for from function if import in instanceof let new null of return \
+    static super switch this throw true try typeof undefined var void while yield";
+
+const TS_KEYWORDS: &str = "\
+    abstract any as async await boolean break case catch class const continue declare \
+    default delete do else enum export extends false finally for from function if implements \
+    import in instanceof interface keyof let namespace never new null number of private \
+    protected public readonly return static string super switch this throw true try type \
+    typeof undefined unknown var void while yield";
+
+const JAVA_KEYWORDS: &str = "\
+    abstract boolean break byte case catch char class continue default do double else enum \
+    extends false final finally float for if implements import instanceof int interface long \
+    new null package private protected public record return short static super switch \
+    synchronized this throw throws true try var void volatile while";
+
+const CS_KEYWORDS: &str = "\
+    abstract as async await base bool break case catch class const continue default do \
+    double else enum false finally float for foreach if in int interface internal is long \
+    namespace new null out override private protected public readonly record ref return \
+    sealed static string struct switch this throw true try using var virtual void while";
+
+/// Languages by the file type names of `rstr::types`.
+const LANGUAGES: &[(&str, Language)] = &[
+    (
+        "rust",
+        Language {
+            line_comments: &["//"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"', '\''],
+            char_literals: true,
+            keywords: "as async await break const continue crate dyn else enum extern false fn for \
+                if impl in let loop match mod move mut pub ref return self Self static \
+                struct super trait true type unsafe use where while",
+        },
+    ),
+    (
+        "py",
+        Language {
+            line_comments: &["#"],
+            block_comment: None,
+            quotes: &['"', '\''],
+            char_literals: false,
+            keywords: "and as assert async await break class continue def del elif else except \
+                False finally for from global if import in is lambda None nonlocal not or \
+                pass raise return self True try while with yield",
+        },
+    ),
+    (
+        "go",
+        Language {
+            line_comments: &["//"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"', '\'', '`'],
+            char_literals: false,
+            keywords: "\
+                break case chan const continue default defer else fallthrough false for func \
+                go goto if import interface map nil package range return select struct \
+                switch true type var",
+        },
+    ),
+    (
+        "js",
+        Language {
+            line_comments: &["//"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"', '\'', '`'],
+            char_literals: false,
+            keywords: JS_KEYWORDS,
+        },
+    ),
+    (
+        "ts",
+        Language {
+            line_comments: &["//"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"', '\'', '`'],
+            char_literals: false,
+            keywords: TS_KEYWORDS,
+        },
+    ),
+    (
+        "c",
+        Language {
+            line_comments: &["//"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"', '\''],
+            char_literals: false,
+            keywords: C_KEYWORDS,
+        },
+    ),
+    (
+        "cpp",
+        Language {
+            line_comments: &["//"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"', '\''],
+            char_literals: false,
+            keywords: CPP_KEYWORDS,
+        },
+    ),
+    (
+        "java",
+        Language {
+            line_comments: &["//"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"', '\''],
+            char_literals: false,
+            keywords: JAVA_KEYWORDS,
+        },
+    ),
+    (
+        "cs",
+        Language {
+            line_comments: &["//"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"', '\''],
+            char_literals: false,
+            keywords: CS_KEYWORDS,
+        },
+    ),
+    (
+        "kotlin",
+        Language {
+            line_comments: &["//"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"', '\''],
+            char_literals: false,
+            keywords: "as break class continue data do else enum false for fun if import in \
+                interface is null object override package private return sealed super this \
+                throw true try val var when while",
+        },
+    ),
+    (
+        "swift",
+        Language {
+            line_comments: &["//"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"'],
+            char_literals: false,
+            keywords: "actor as break case class continue default defer do else enum extension \
+                false for func guard if import in init let nil private protocol public \
+                return self static struct switch throw true try var where while",
+        },
+    ),
+    (
+        "php",
+        Language {
+            line_comments: &["//", "#"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"', '\''],
+            char_literals: false,
+            keywords: "abstract as break case catch class const continue default echo else elseif \
+                enum extends false final finally fn for foreach function if implements \
+                interface namespace new null private protected public return static switch \
+                throw trait true try use while",
+        },
+    ),
+    (
+        "ruby",
+        Language {
+            line_comments: &["#"],
+            block_comment: None,
+            quotes: &['"', '\''],
+            char_literals: false,
+            keywords: "begin break case class def do else elsif end ensure false for if in module \
+                next nil raise rescue return self super then true unless until when while \
+                yield",
+        },
+    ),
+    (
+        "lua",
+        Language {
+            line_comments: &["--"],
+            block_comment: None,
+            quotes: &['"', '\''],
+            char_literals: false,
+            keywords: "and break do else elseif end false for function goto if in local nil not or \
+                repeat return then true until while",
+        },
+    ),
+    (
+        "sh",
+        Language {
+            line_comments: &["#"],
+            block_comment: None,
+            quotes: &['"', '\''],
+            char_literals: false,
+            keywords: "case do done elif else esac export fi for function if in local return then \
+                until while",
+        },
+    ),
+    (
+        "toml",
+        Language {
+            line_comments: &["#"],
+            block_comment: None,
+            quotes: &['"', '\''],
+            char_literals: false,
+            keywords: "true false",
+        },
+    ),
+    (
+        "yaml",
+        Language {
+            line_comments: &["#"],
+            block_comment: None,
+            quotes: &['"', '\''],
+            char_literals: false,
+            keywords: "true false null yes no",
+        },
+    ),
+    (
+        "json",
+        Language {
+            line_comments: &[],
+            block_comment: None,
+            quotes: &['"'],
+            char_literals: false,
+            keywords: "true false null",
+        },
+    ),
+];
+
+/// The language of `path`, looked up once per extension.
+fn language_for(path: &Path) -> Option<&'static Language> {
+    static FILTERS: OnceLock<Vec<TypeFilter>> = OnceLock::new();
+    static BY_EXTENSION: OnceLock<Mutex<HashMap<String, Option<usize>>>> = OnceLock::new();
+    let filters = FILTERS.get_or_init(|| {
+        let types = TypeRegistry::new();
+        LANGUAGES
+            .iter()
+            .map(|(name, _)| types.select(&[name.to_string()]).unwrap())
+            .collect()
+    });
+    // names like `Makefile` have no extension, so they're their own key
+    let key = path
+        .extension()
+        .or_else(|| path.file_name())?
+        .to_string_lossy()
+        .into_owned();
+    let mut cache = BY_EXTENSION.get_or_init(Mutex::default).lock().unwrap();
+    let index = *cache
+        .entry(key)
+        .or_insert_with(|| filters.iter().position(|filter| filter.matches(path)));
+    index.map(|i| &LANGUAGES[i].1)
+}
+
+/// The tokens of each of `lines`, the contents of `path`, as byte ranges
+/// into the line; empty when the file's language isn't known or it's too
+/// big.
+pub fn highlight(path: &Path, lines: &[String]) -> Vec<Vec<(Range<usize>, Token)>> {
+    let size: usize = lines.iter().map(|line| line.len() + 1).sum();
+    let Some(language) = language_for(path).filter(|_| size <= MAX_BYTES) else {
+        return Vec::new();
+    };
+    let mut in_comment = false;
+    lines
+        .iter()
+        .map(|line| tokens(language, line, &mut in_comment))
+        .collect()
+}
+
+/// The tokens of one line; `in_comment` says whether a block comment is
+/// still open, coming in and going out.
+fn tokens(language: &Language, line: &str, in_comment: &mut bool) -> Vec<(Range<usize>, Token)> {
+    let mut tokens = Vec::new();
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < line.len() {
+        let rest = &line[i..];
+        if *in_comment {
+            let (_, end) = language
+                .block_comment
+                .expect("only block comments stay open");
+            let close = match rest.find(end) {
+                Some(at) => {
+                    *in_comment = false;
+                    i + at + end.len()
+                }
+                None => line.len(),
+            };
+            tokens.push((i..close, Token::Comment));
+            i = close;
+            continue;
+        }
+        if language
+            .line_comments
+            .iter()
+            .any(|comment| rest.starts_with(comment))
+        {
+            tokens.push((i..line.len(), Token::Comment));
+            break;
+        }
+        if let Some((start, _)) = language.block_comment
+            && rest.starts_with(start)
+        {
+            *in_comment = true;
+            tokens.push((i..i + start.len(), Token::Comment));
+            i += start.len();
+            continue;
+        }
+        let c = rest.chars().next().unwrap_or_default();
+        let after_word = i > 0 && (bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_');
+        if language.quotes.contains(&c) {
+            if let Some(end) = string_end(rest, c, language.char_literals) {
+                tokens.push((i..i + end, Token::String));
+                i += end;
+                continue;
+            }
+        } else if c.is_ascii_digit() && !after_word {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            tokens.push((i..i + end, Token::Number));
+            i += end;
+            continue;
+        } else if (c.is_alphabetic() || c == '_') && !after_word {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            if language
+                .keywords
+                .split(' ')
+                .any(|keyword| keyword == &rest[..end])
+            {
+                tokens.push((i..i + end, Token::Keyword));
+            }
+            i += end;
+            continue;
+        }
+        i += c.len_utf8();
+    }
+    tokens
+}
+
+/// Where the string starting with `quote` at the start of `text` ends, past
+/// its closing quote; the end of the line if it isn't closed on it. With
+/// `char_literals`, a `'` that isn't a char literal is no string at all.
+fn string_end(text: &str, quote: char, char_literals: bool) -> Option<usize> {
+    let mut chars = text.char_indices().skip(1);
+    if char_literals && quote == '\'' {
+        let (_, first) = chars.next()?;
+        if first == '\\' {
+            let (at, escaped) = chars.next()?;
+            let from = at + escaped.len_utf8();
+            return text[from..].find('\'').map(|end| from + end + 1);
+        }
+        let (at, second) = chars.next()?;
+        return (second == '\'').then_some(at + 1);
+    }
+    let mut escaped = false;
+    for (at, c) in chars {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return Some(at + c.len_utf8());
+        }
+    }
+    Some(text.len())
+}
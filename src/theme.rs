@@ -0,0 +1,247 @@
+//! The TUI's colours: built-in themes picked with `--theme`, and custom
+//! ones defined in the config file's `[themes.NAME]` tables.
+
+use crate::toml::{Table, Value};
+use ratatui::style::{Color, Modifier, Style};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+pub const BUILTIN: &[&str] = &["default", "dark", "light", "solarized"];
+
+/// The styles a `[themes.NAME]` table can set.
+pub const STYLES: &[&str] = &[
+    "border",
+    "title",
+    "status",
+    "gauge",
+    "bar",
+    "path",
+    "matched",
+    "selection",
+    "context",
+    "replacement",
+    "tag",
+    "error",
+    "keyword",
+    "string",
+    "comment",
+    "number",
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub border: Style,
+    pub title: Style,
+    /// The status line at the top, with the gauge underneath while searching.
+    pub status: Style,
+    pub gauge: Style,
+    /// The status bar at the bottom, with the totals and active flags.
+    pub bar: Style,
+    /// File headers, and the keys in the help overlay.
+    pub path: Style,
+    /// The matched text, and the line numbers of matches in the preview.
+    pub matched: Style,
+    pub selection: Style,
+    /// Context lines, and line numbers in the preview.
+    pub context: Style,
+    pub replacement: Style,
+    /// The names of the `-e` patterns a result contains.
+    pub tag: Style,
+    /// What's wrong with the pattern in the search prompt.
+    pub error: Style,
+    /// Syntax highlighting in the preview.
+    pub keyword: Style,
+    pub string: Style,
+    pub comment: Style,
+    pub number: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            border: Style::default(),
+            title: Style::default(),
+            status: Style::default(),
+            gauge: Style::default().fg(Color::Green),
+            bar: Style::default().add_modifier(Modifier::REVERSED),
+            path: Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+            matched: Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+            selection: Style::default().add_modifier(Modifier::REVERSED),
+            context: Style::default().fg(Color::DarkGray),
+            replacement: Style::default().fg(Color::Green),
+            tag: Style::default().fg(Color::Magenta),
+            error: Style::default().fg(Color::Red),
+            keyword: Style::default().fg(Color::Magenta),
+            string: Style::default().fg(Color::Green),
+            comment: Style::default().fg(Color::DarkGray),
+            number: Style::default().fg(Color::Cyan),
+        }
+    }
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Theme> {
+        let bold = |color: Color| Style::default().fg(color).add_modifier(Modifier::BOLD);
+        let fg = |color: Color| Style::default().fg(color);
+        let theme = match name {
+            "default" => Theme::default(),
+            "dark" => Theme {
+                border: fg(Color::DarkGray),
+                title: bold(Color::LightCyan),
+                status: fg(Color::White),
+                gauge: fg(Color::LightGreen),
+                bar: fg(Color::White).bg(Color::Rgb(0x30, 0x30, 0x38)),
+                path: bold(Color::LightBlue),
+                matched: bold(Color::Black).bg(Color::Yellow),
+                selection: Style::default().bg(Color::Rgb(0x3a, 0x3a, 0x4a)),
+                context: fg(Color::Gray),
+                replacement: fg(Color::LightGreen),
+                tag: fg(Color::LightMagenta),
+                error: fg(Color::LightRed),
+                keyword: fg(Color::LightMagenta),
+                string: fg(Color::LightGreen),
+                comment: fg(Color::DarkGray),
+                number: fg(Color::LightCyan),
+            },
+            // no light greys or yellows, they vanish on a white background
+            "light" => Theme {
+                border: fg(Color::Gray),
+                title: bold(Color::Blue),
+                status: fg(Color::Black),
+                gauge: fg(Color::Blue),
+                bar: fg(Color::Black).bg(Color::Rgb(0xe0, 0xe0, 0xe0)),
+                path: bold(Color::Blue),
+                matched: bold(Color::Red),
+                selection: Style::default().bg(Color::Rgb(0xd0, 0xe0, 0xf8)),
+                context: fg(Color::Rgb(0x80, 0x80, 0x80)),
+                replacement: fg(Color::Rgb(0x00, 0x80, 0x00)),
+                tag: fg(Color::Magenta),
+                error: fg(Color::Red),
+                keyword: fg(Color::Rgb(0x80, 0x00, 0x80)),
+                string: fg(Color::Rgb(0x00, 0x80, 0x00)),
+                comment: fg(Color::Rgb(0x80, 0x80, 0x80)),
+                number: fg(Color::Blue),
+            },
+            "solarized" => {
+                let base01 = Color::Rgb(0x58, 0x6e, 0x75);
+                let base1 = Color::Rgb(0x93, 0xa1, 0xa1);
+                Theme {
+                    border: fg(base01),
+                    title: bold(Color::Rgb(0x26, 0x8b, 0xd2)),
+                    status: fg(base1),
+                    gauge: fg(Color::Rgb(0x85, 0x99, 0x00)),
+                    bar: fg(Color::Rgb(0x00, 0x2b, 0x36)).bg(base1),
+                    path: bold(Color::Rgb(0x2a, 0xa1, 0x98)),
+                    matched: bold(Color::Rgb(0xb5, 0x89, 0x00)),
+                    selection: Style::default().bg(Color::Rgb(0x07, 0x36, 0x42)),
+                    context: fg(base01),
+                    replacement: fg(Color::Rgb(0x85, 0x99, 0x00)),
+                    tag: fg(Color::Rgb(0xd3, 0x36, 0x82)),
+                    error: fg(Color::Rgb(0xdc, 0x32, 0x2f)),
+                    keyword: fg(Color::Rgb(0x85, 0x99, 0x00)),
+                    string: fg(Color::Rgb(0x2a, 0xa1, 0x98)),
+                    comment: fg(base01),
+                    number: fg(Color::Rgb(0xd3, 0x36, 0x82)),
+                }
+            }
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// Looks `name` up among the config file's themes, then the built-in
+    /// ones. A custom theme starts from its `base` (`default` if unset) and
+    /// overrides single styles, e.g. `matched = "bold black on yellow"`.
+    pub fn resolve(name: &str, custom: &BTreeMap<String, Table>) -> Result<Theme, String> {
+        let Some(table) = custom.get(name) else {
+            return Theme::builtin(name).ok_or_else(|| {
+                let mut known: Vec<&str> = BUILTIN.to_vec();
+                known.extend(custom.keys().map(String::as_str));
+                format!(
+                    "unknown theme '{}', expected one of {}",
+                    name,
+                    known.join(", ")
+                )
+            });
+        };
+        let error = |message: String| format!("theme '{}': {}", name, message);
+
+        let mut theme = match table.get("base") {
+            Some(Value::String(base)) => Theme::builtin(base)
+                .ok_or_else(|| error(format!("unknown base theme '{}'", base)))?,
+            Some(_) => return Err(error("'base' expects a theme name".to_string())),
+            None => Theme::default(),
+        };
+        for (key, value) in table {
+            if key == "base" {
+                continue;
+            }
+            let Some(style) = value.as_str() else {
+                return Err(error(format!(
+                    "'{}' expects a style like \"bold red on black\"",
+                    key
+                )));
+            };
+            let style = parse_style(style).map_err(error)?;
+            let slot = match key.as_str() {
+                "border" => &mut theme.border,
+                "title" => &mut theme.title,
+                "status" => &mut theme.status,
+                "gauge" => &mut theme.gauge,
+                "bar" => &mut theme.bar,
+                "path" => &mut theme.path,
+                "matched" => &mut theme.matched,
+                "selection" => &mut theme.selection,
+                "context" => &mut theme.context,
+                "replacement" => &mut theme.replacement,
+                "tag" => &mut theme.tag,
+                "error" => &mut theme.error,
+                "keyword" => &mut theme.keyword,
+                "string" => &mut theme.string,
+                "comment" => &mut theme.comment,
+                "number" => &mut theme.number,
+                _ => return Err(error(format!("unknown key '{}'", key))),
+            };
+            *slot = style;
+        }
+        Ok(theme)
+    }
+}
+
+/// Parses styles like `red`, `bold yellow`, `black on #b58900` or
+/// `reversed`: modifiers, a foreground colour and `on` a background one.
+/// Colours are names (`lightblue`), `#rrggbb` or 256-colour indices.
+fn parse_style(text: &str) -> Result<Style, String> {
+    let mut style = Style::default();
+    let mut words = text.split_whitespace();
+    while let Some(word) = words.next() {
+        let modifier = match word.to_ascii_lowercase().as_str() {
+            "bold" => Modifier::BOLD,
+            "dim" => Modifier::DIM,
+            "italic" => Modifier::ITALIC,
+            "underlined" => Modifier::UNDERLINED,
+            "reversed" => Modifier::REVERSED,
+            "on" => {
+                let color = words
+                    .next()
+                    .ok_or_else(|| format!("'on' needs a colour in \"{}\"", text))?;
+                style = style.bg(parse_color(color)?);
+                continue;
+            }
+            _ => {
+                style = style.fg(parse_color(word)?);
+                continue;
+            }
+        };
+        style = style.add_modifier(modifier);
+    }
+    Ok(style)
+}
+
+fn parse_color(name: &str) -> Result<Color, String> {
+    Color::from_str(name).map_err(|_| format!("unknown colour '{}'", name))
+}
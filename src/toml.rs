@@ -0,0 +1,412 @@
+//! A parser for the subset of TOML rstr's config files use: tables,
+//! dotted keys, strings, integers, floats, booleans and arrays.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+pub type Table = BTreeMap<String, Value>;
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    /// Formats scalars the way they'd be written on a command line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{}", s),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(|v| v.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Table(_) => write!(f, "{{...}}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub fn parse(input: &str) -> Result<Table, Error> {
+    Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+        line: 1,
+    }
+    .document()
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, Error> {
+        Err(Error {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skips spaces and tabs, plus newlines and comments when `newlines` is set.
+    fn skip_whitespace(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {
+                    self.bump();
+                }
+                '\n' if newlines => {
+                    self.bump();
+                }
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), Error> {
+        self.skip_whitespace(false);
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => self.error(format!("unexpected '{}' after value", c)),
+        }
+    }
+
+    fn document(&mut self) -> Result<Table, Error> {
+        let mut root = Table::new();
+        let mut current: Vec<String> = Vec::new();
+
+        loop {
+            self.skip_whitespace(true);
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') => {
+                    self.bump();
+                    if self.peek() == Some('[') {
+                        return self.error("arrays of tables are not supported");
+                    }
+                    current = self.key()?;
+                    self.skip_whitespace(false);
+                    if !self.eat(']') {
+                        return self.error("expected ']' after table name");
+                    }
+                    self.table_at(&mut root, &current)?;
+                    self.end_of_line()?;
+                }
+                Some(_) => {
+                    let key = self.key()?;
+                    self.skip_whitespace(false);
+                    if !self.eat('=') {
+                        return self.error("expected '=' after key");
+                    }
+                    self.skip_whitespace(false);
+                    let value = self.value()?;
+                    let (last, parents) = key.split_last().expect("keys are never empty");
+                    let path: Vec<String> = current.iter().chain(parents).cloned().collect();
+                    let table = self.table_at(&mut root, &path)?;
+                    if table.insert(last.clone(), value).is_some() {
+                        return self.error(format!("duplicate key '{}'", last));
+                    }
+                    self.end_of_line()?;
+                }
+            }
+        }
+    }
+
+    /// Walks (and creates) the nested tables along `path`.
+    fn table_at<'t>(&self, root: &'t mut Table, path: &[String]) -> Result<&'t mut Table, Error> {
+        let mut table = root;
+        for part in path {
+            let entry = table
+                .entry(part.clone())
+                .or_insert_with(|| Value::Table(Table::new()));
+            table = match entry {
+                Value::Table(inner) => inner,
+                _ => return self.error(format!("'{}' is not a table", part)),
+            };
+        }
+        Ok(table)
+    }
+
+    /// A possibly dotted key such as `colors.match`.
+    fn key(&mut self) -> Result<Vec<String>, Error> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_whitespace(false);
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let mut bare = String::new();
+                    while let Some(c) = self.peek() {
+                        if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                            bare.push(c);
+                            self.bump();
+                        } else {
+                            break;
+                        }
+                    }
+                    if bare.is_empty() {
+                        return self.error("expected a key");
+                    }
+                    bare
+                }
+            };
+            parts.push(part);
+            self.skip_whitespace(false);
+            if !self.eat('.') {
+                return Ok(parts);
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        match self.peek() {
+            Some('"') => Ok(Value::String(self.basic_string()?)),
+            Some('\'') => Ok(Value::String(self.literal_string()?)),
+            Some('[') => self.array(),
+            Some('{') => self.error("inline tables are not supported"),
+            Some(_) => self.scalar(),
+            None => self.error("expected a value"),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, Error> {
+        self.bump();
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace(true);
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_whitespace(true);
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+            if !self.eat(',') {
+                return self.error("expected ',' or ']' in array");
+            }
+        }
+    }
+
+    fn scalar(&mut self) -> Result<Value, Error> {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '_' | '.') {
+                word.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        let digits = word.replace('_', "");
+        match word.as_str() {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ if let Ok(i) = digits.parse::<i64>() => Ok(Value::Integer(i)),
+            _ if let Ok(x) = digits.parse::<f64>() => Ok(Value::Float(x)),
+            "" => self.error("expected a value"),
+            _ => self.error(format!("invalid value '{}'", word)),
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, Error> {
+        self.bump();
+        let mut out = String::new();
+        loop {
+            // reported on the string's own line rather than the next
+            if self.peek() == Some('\n') {
+                return self.error("unterminated string");
+            }
+            match self.bump() {
+                Some('\'') => return Ok(out),
+                None => return self.error("unterminated string"),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, Error> {
+        self.bump();
+        let mut out = String::new();
+        loop {
+            if self.peek() == Some('\n') {
+                return self.error("unterminated string");
+            }
+            match self.bump() {
+                Some('"') => return Ok(out),
+                None => return self.error("unterminated string"),
+                Some('\\') => match self.bump() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| self.bump()).collect();
+                        match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                            Some(c) => out.push(c),
+                            None => return self.error(format!("invalid unicode escape '{}'", hex)),
+                        }
+                    }
+                    Some(c) => return self.error(format!("invalid escape '\\{}'", c)),
+                    None => return self.error("unterminated string"),
+                },
+                Some(c) => out.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tables_keys_and_values() {
+        let table = parse(
+            r#"
+# defaults
+ignore-case = true
+threads = 4   # a comment after a value
+ratio = 0.5
+big = 1_000
+glob = ["!*.min.js", '*.rs',
+    "tail",
+]
+colors.match = "red"
+"quoted key" = 'C:\path'
+
+[themes.dark]
+fg = "white\t\"\u00e9\""
+"#,
+        )
+        .unwrap();
+        assert_eq!(table["ignore-case"], Value::Boolean(true));
+        assert_eq!(table["threads"], Value::Integer(4));
+        assert_eq!(table["ratio"], Value::Float(0.5));
+        assert_eq!(table["big"], Value::Integer(1000));
+        let globs = ["!*.min.js", "*.rs", "tail"].map(|glob| Value::String(glob.to_string()));
+        assert_eq!(table["glob"], Value::Array(globs.to_vec()));
+        let Value::Table(colors) = &table["colors"] else {
+            panic!("colors isn't a table");
+        };
+        assert_eq!(colors["match"].as_str(), Some("red"));
+        assert_eq!(table["quoted key"].as_str(), Some(r"C:\path"));
+        let Value::Table(themes) = &table["themes"] else {
+            panic!("themes isn't a table");
+        };
+        let Value::Table(dark) = &themes["dark"] else {
+            panic!("themes.dark isn't a table");
+        };
+        assert_eq!(dark["fg"].as_str(), Some("white\t\"é\""));
+    }
+
+    #[test]
+    fn formats_values_as_flags_take_them() {
+        assert_eq!(Value::String("a b".into()).to_string(), "a b");
+        assert_eq!(Value::Integer(-3).to_string(), "-3");
+        assert_eq!(Value::Float(2.5).to_string(), "2.5");
+        assert_eq!(Value::Boolean(false).to_string(), "false");
+        let array = Value::Array(vec![Value::Integer(1), Value::String("x".into())]);
+        assert_eq!(array.to_string(), "[1, x]");
+    }
+
+    #[test]
+    fn errors_name_their_line() {
+        let cases = [
+            ("a = 1\nb = ", 2, "expected a value"),
+            ("a = 1\na = 2", 2, "duplicate key 'a'"),
+            ("a = 1 2", 1, "unexpected '2' after value"),
+            ("\n\na 1", 3, "expected '=' after key"),
+            ("= 1", 1, "expected a key"),
+            ("a = 'open", 1, "unterminated string"),
+            ("a = \"open\nb = 1", 1, "unterminated string"),
+            ("a = 'open\nb = 1", 1, "unterminated string"),
+            ("a = \"\\q\"", 1, "invalid escape '\\q'"),
+            ("a = \"\\uzzzz\"", 1, "invalid unicode escape 'zzzz'"),
+            ("a = yes", 1, "invalid value 'yes'"),
+            ("a = [1 2]", 1, "expected ',' or ']' in array"),
+            ("a = { b = 1 }", 1, "inline tables are not supported"),
+            ("[[a]]", 1, "arrays of tables are not supported"),
+            ("[a\n", 1, "expected ']' after table name"),
+            ("a = 1\n[a]", 2, "'a' is not a table"),
+            ("a = 1\na.b = 2", 2, "'a' is not a table"),
+        ];
+        for (input, line, message) in cases {
+            let error = parse(input).unwrap_err();
+            assert_eq!(
+                (error.line, error.message.as_str()),
+                (line, message),
+                "{:?}",
+                input
+            );
+        }
+        assert_eq!(
+            parse("a = ").unwrap_err().to_string(),
+            "line 1: expected a value"
+        );
+    }
+}
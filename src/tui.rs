@@ -0,0 +1,3124 @@
+use crate::blame::{self, Blames};
+use crate::events::{EventLoop, UiEvent};
+use crate::history;
+use crate::input::Input;
+use crate::keys::{Command, Keymap};
+use crate::output::{self, MaxColumns, PathStyle, clip, format_bytes, throughput};
+use crate::preview::{self, Preview};
+use crate::replace::{self, Edit};
+use crate::sort::{FileMeta, SortBy, SortKey};
+use crate::syntax::Token;
+use crate::theme::Theme;
+use crate::{Format, clipboard, editor};
+use crossterm::{
+    cursor,
+    event::{
+        DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
+    execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+};
+use ratatui::{
+    Frame, Terminal,
+    backend::CrosstermBackend,
+    layout::{Alignment, Constraint, Direction, Layout, Position, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Clear, LineGauge, List, ListItem, ListState, Paragraph, Wrap},
+};
+use rstr::archive;
+use rstr::encoding::Encoding;
+use rstr::{Cancel, Match, STDIN_PATH, SearchEvent, SearchStats, Source};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Stdout};
+use std::mem;
+use std::ops::Range;
+use std::panic;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+pub type Tui = Terminal<CrosstermBackend<Stdout>>;
+
+/// Whether the TUI has the terminal in raw mode on the alternate screen.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// What a background thread panicked with while the TUI was up, to print
+/// once it's gone instead of over it.
+static DEFERRED_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+/// Makes panics give the terminal back before their message is printed,
+/// so a crash doesn't leave it in raw mode on the alternate screen.
+fn install_panic_hook() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if ACTIVE.load(Ordering::Relaxed) {
+                // a search thread's panic is reported as an error of its file
+                if thread::current().name() != Some("main") {
+                    *DEFERRED_PANIC.lock().unwrap_or_else(|e| e.into_inner()) =
+                        Some(info.to_string());
+                    return;
+                }
+                ACTIVE.store(false, Ordering::Relaxed);
+                let _ = disable_raw_mode();
+                let _ = execute!(
+                    io::stdout(),
+                    LeaveAlternateScreen,
+                    DisableMouseCapture,
+                    cursor::Show
+                );
+            }
+            previous(info);
+        }));
+    });
+}
+
+/// The message of a panic on a background thread while the TUI was up.
+pub fn take_deferred_panic() -> Option<String> {
+    DEFERRED_PANIC
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+}
+
+pub fn setup_terminal() -> Result<Tui, io::Error> {
+    install_panic_hook();
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    ACTIVE.store(true, Ordering::Relaxed);
+    let backend = CrosstermBackend::new(stdout);
+    Terminal::new(backend)
+}
+
+pub fn restore_terminal(terminal: &mut Tui) -> Result<(), io::Error> {
+    ACTIVE.store(false, Ordering::Relaxed);
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
+    Ok(())
+}
+
+/// Starts a search for a pattern in a path, or explains why it can't.
+pub type StartSearch = Box<dyn Fn(&str, Source) -> Result<Search, String>>;
+
+/// The results marked in the list, by their file and line number, with the
+/// note added to each; empty for none.
+pub type Marks = HashMap<(PathBuf, usize), String>;
+
+/// Compiles a pattern the way [`StartSearch`] would, saying what's wrong
+/// with it if it doesn't.
+pub type CheckPattern = Box<dyn Fn(&str) -> Option<PatternError>>;
+
+/// Why a pattern typed in the search prompt doesn't compile.
+pub struct PatternError {
+    pub message: String,
+    /// The characters of the pattern at fault, when the error points at
+    /// them.
+    pub span: Option<Range<usize>>,
+}
+
+pub struct UiOptions {
+    pub pattern: String,
+    /// The patterns given with `-e` when there are several, which results
+    /// name by their index in [`Match::patterns`].
+    pub patterns: Vec<String>,
+    /// `patterns` are the names of rules, such as `rstr secrets`' ones.
+    pub rules: bool,
+    /// Where the first search looks, `-` for stdin.
+    pub path: String,
+    pub editor: String,
+    /// Replacements may be written back to disk (`--write`).
+    pub allow_write: bool,
+    /// Appended to a file's name for the copy kept when rewriting it.
+    pub backup_suffix: Option<String>,
+    /// The `--encoding` files are read in by the preview, `None` to detect it.
+    pub encoding: Option<Encoding>,
+    /// The `--sort` order the TUI starts in, `None` for the order files
+    /// are found in.
+    pub sort: Option<SortBy>,
+    /// List just the files that match (`-l`).
+    pub files_only: bool,
+    /// How paths are shown to start with; `a` cycles through the styles.
+    pub paths: PathStyle,
+    /// Show the line numbers of results (unless `-N`).
+    pub line_numbers: bool,
+    /// Show just the text of each match instead of its whole line (`-o`).
+    pub only_matching: bool,
+    /// How long lines are cut down in the list and the preview.
+    pub max_columns: Option<MaxColumns>,
+    /// Add the throughput to the status bar's totals (`--stats`).
+    pub stats: bool,
+    pub theme: Theme,
+    pub keys: Keymap,
+    /// The flags that change what matches, for the status bar, e.g. `-i`.
+    pub flags: Vec<String>,
+    /// The searches keep running to pick up changed files (`--watch`).
+    pub watch: bool,
+    /// Patterns are taken literally (`-F`), so followed text isn't escaped.
+    pub literal: bool,
+    /// How many results keep their line in memory (`--results-in-memory`);
+    /// those of later ones are read again from their files when they're
+    /// needed. 0 keeps every result's.
+    pub results_in_memory: usize,
+    /// Starts a search for a pattern entered in the TUI, with the same
+    /// flags as the first one.
+    pub start: StartSearch,
+    /// Checks the search prompt's pattern as it's typed.
+    pub check: CheckPattern,
+    /// Earlier patterns, oldest first, for recall in the search prompt.
+    pub history: Vec<String>,
+}
+
+/// A running search as the UI sees it.
+pub struct Search {
+    pub events: Receiver<SearchEvent>,
+    pub stats: Arc<SearchStats>,
+    /// How many files the search will look at, once a counting pass is done.
+    pub file_count: Receiver<usize>,
+    pub cancel: Cancel,
+    /// The results marked when it was saved, for a session replayed.
+    pub marks: Marks,
+}
+
+impl Search {
+    /// A search that has already found `results` in `files` files of
+    /// `bytes` bytes, e.g. one saved earlier, replayed.
+    pub fn finished(results: Vec<Match>, files: usize, bytes: u64) -> Search {
+        let stats = SearchStats::with_totals(files, bytes, results.len());
+        let (tx, events) = mpsc::channel();
+        for result in results {
+            let _ = tx.send(SearchEvent::Match(result));
+        }
+        let _ = tx.send(SearchEvent::Done);
+        let (count_tx, file_count) = mpsc::channel();
+        let _ = count_tx.send(files);
+        Search {
+            events,
+            stats: Arc::new(stats),
+            file_count,
+            cancel: Cancel::default(),
+            marks: Marks::new(),
+        }
+    }
+}
+
+/// How often the screen is redrawn when nothing happens, for the clock in
+/// the status bar.
+const TICK: Duration = Duration::from_millis(100);
+/// Events handled at most between two redraws.
+const DRAIN_LIMIT: usize = 10_000;
+/// Two clicks on the same row within this long make a double-click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+/// How wide the blame column is, author and age together.
+const BLAME_WIDTH: usize = 18;
+
+/// What the help overlay lists, in order: what the keys bound to these
+/// commands do.
+const KEY_HELP: &[(&[Command], &str)] = &[
+    (&[Command::Up, Command::Down], "Move the selection"),
+    (
+        &[Command::PageUp, Command::PageDown],
+        "Move the selection by a page",
+    ),
+    (
+        &[Command::HalfPageUp, Command::HalfPageDown],
+        "Move the selection by half a page",
+    ),
+    (
+        &[Command::First, Command::Last],
+        "Jump to the first / last result",
+    ),
+    (
+        &[Command::Open],
+        "Open the result in the editor, or fold the file",
+    ),
+    (
+        &[Command::Mark, Command::Note],
+        "Mark the selected result / add a note to it",
+    ),
+    (&[Command::Fold], "Fold the selected file or directory"),
+    (&[Command::Tree], "Toggle a tree of the files' directories"),
+    (&[Command::Filter], "Filter the results"),
+    (
+        &[Command::Jump],
+        "Jump to the result with this number (or type 0-9)",
+    ),
+    (
+        &[Command::Files],
+        "Mark files (Space) to delete, copy or write out their paths",
+    ),
+    (
+        &[Command::Search],
+        "New search (Tab: path, Up / Down: history)",
+    ),
+    (
+        &[Command::Narrow, Command::Widen],
+        "Search the selected file's directory / back out again",
+    ),
+    (
+        &[Command::Follow],
+        "Search for the selected match (or its first group)",
+    ),
+    (
+        &[Command::Back, Command::Forward],
+        "Go back / forward through followed searches",
+    ),
+    (
+        &[Command::Sort],
+        "Cycle the file order: found, path, modified, size, matches",
+    ),
+    (&[Command::Preview], "Toggle the preview pane"),
+    (
+        &[Command::Paths],
+        "Cycle the paths: as found, absolute, relative",
+    ),
+    (
+        &[Command::Wrap],
+        "Wrap long lines instead of cutting them off",
+    ),
+    (
+        &[Command::ScrollLeft, Command::ScrollRight],
+        "Scroll long lines left / right",
+    ),
+    (
+        &[Command::Refine, Command::Unrefine],
+        "Search within the results (Tab: files / lines) / back out",
+    ),
+    (
+        &[Command::Blame],
+        "Toggle who last changed each line, and when (git blame)",
+    ),
+    (
+        &[Command::Copy, Command::CopyLine],
+        "Copy path:line:column / the matched line",
+    ),
+    (
+        &[Command::Export],
+        "Write the shown results, or just the marked ones, to a file (.json, .md, ...)",
+    ),
+    (
+        &[Command::Replace, Command::ReplaceFile],
+        "Replace in the match / file (needs --write)",
+    ),
+    (&[Command::Errors], "List the files that couldn't be read"),
+    (&[Command::Help], "Show this help"),
+    (
+        &[Command::Cancel],
+        "Stop the running search or --watch, keeping the results",
+    ),
+    (&[Command::Quit], "Quit"),
+];
+
+#[derive(PartialEq, Eq)]
+enum Mode {
+    Normal,
+    Filter,
+    /// Entering the pattern of a new search.
+    Search,
+    /// Waiting for y/n before writing the replacements of these results.
+    Confirm(Vec<usize>),
+    /// Entering the number of the result to jump to.
+    Jump(String),
+    /// Marking files with Space to act on them all at once.
+    Files,
+    /// Waiting for y/n before deleting the marked files.
+    ConfirmDelete,
+    /// Entering the file to write the marked paths to.
+    Export(Input),
+    /// Entering the file to export the shown results to.
+    ExportResults(Input),
+    /// Entering the note of the result on this line of this file.
+    Note((PathBuf, usize), Input),
+    /// Entering a pattern to search the results' files for, or with
+    /// `lines` just their lines.
+    Refine {
+        input: Input,
+        lines: bool,
+    },
+}
+
+struct Group {
+    path: PathBuf,
+    /// Indices into `results`, in line order.
+    matches: Vec<usize>,
+    collapsed: bool,
+    meta: FileMeta,
+}
+
+/// A directory of the tree view.
+struct Dir {
+    /// Where it is, which folding it goes by.
+    path: PathBuf,
+    /// What its row shows: its name, or the names down to it of the
+    /// directories that hold nothing but each other.
+    name: PathBuf,
+    depth: usize,
+    collapsed: bool,
+    /// How many results below it pass the filter.
+    shown: usize,
+}
+
+/// The directories of the files listed, as the tree view shows them.
+#[derive(Default)]
+struct TreeNode {
+    dirs: BTreeMap<OsString, TreeNode>,
+    /// The groups of the files right in it, in list order.
+    files: Vec<usize>,
+    shown: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Row {
+    Header(usize),
+    Result(usize),
+    Dir(usize),
+}
+
+struct App {
+    pattern: String,
+    patterns: Vec<String>,
+    rules: bool,
+    allow_write: bool,
+    backup_suffix: Option<String>,
+    encoding: Option<Encoding>,
+    results: Vec<Match>,
+    /// Results whose replacement has been written to disk.
+    applied: HashSet<usize>,
+    /// Whether each result passes the filter.
+    passes: Vec<bool>,
+    /// Whether each result's line was dropped to save memory, to be read
+    /// again from its file when it's needed (see [`App::result`]).
+    slim: Vec<bool>,
+    results_in_memory: usize,
+    /// The lines of the files slim results were last read from.
+    reread: RefCell<HashMap<PathBuf, Vec<String>>>,
+    /// The group each result belongs to.
+    result_group: Vec<usize>,
+    groups: Vec<Group>,
+    group_index: HashMap<PathBuf, usize>,
+    sort: Option<SortBy>,
+    files_only: bool,
+    paths: PathStyle,
+    line_numbers: bool,
+    only_matching: bool,
+    max_columns: Option<MaxColumns>,
+    show_stats: bool,
+    theme: Theme,
+    keys: Keymap,
+    flags: Vec<String>,
+    /// What the list shows, rebuilt from the groups whenever `dirty` is set.
+    rows: Vec<Row>,
+    /// The groups with results passing the filter in list order, those in
+    /// folded directories included.
+    listed: Vec<usize>,
+    /// Whether the list is a tree of the files' directories, and the
+    /// directories it has, how deep each file is in it and which
+    /// directories are folded.
+    tree: bool,
+    dirs: Vec<Dir>,
+    depths: HashMap<usize, usize>,
+    folded: HashSet<PathBuf>,
+    dirty: bool,
+    filter: String,
+    mode: Mode,
+    list_state: ListState,
+    /// Height of the list's inner area at the last draw.
+    page: u16,
+    /// Where the list and the preview were drawn, for mouse events.
+    list_area: Rect,
+    /// How wide the column of result numbers was when last drawn.
+    number_width: usize,
+    /// Whether lines too long for the list wrap rather than get cut off.
+    wrap: bool,
+    /// Columns the results' lines are scrolled sideways by when they don't
+    /// wrap, and how wide the widest of them was when last drawn.
+    scroll: usize,
+    widest: Cell<usize>,
+    preview_area: Option<Rect>,
+    /// Lines the preview is scrolled by from centring the selection, and
+    /// the row that was selected when it started scrolling.
+    preview_scroll: isize,
+    preview_row: Option<Row>,
+    /// When and on which row the last click landed, to spot double-clicks.
+    last_click: Option<(Instant, usize)>,
+    searching: bool,
+    watch: bool,
+    /// Whether the finished search is still sending updates.
+    watching: bool,
+    current_file: PathBuf,
+    stats: Arc<SearchStats>,
+    /// The result of the counting pass, for the progress gauge.
+    file_count: Option<usize>,
+    started: Instant,
+    /// How long the search took, once it's done.
+    finished: Option<Duration>,
+    /// Whether the search was stopped before it was done.
+    cancelled: bool,
+    /// One-off feedback shown in the header until the next key press.
+    message: Option<String>,
+    show_preview: bool,
+    preview: Option<Preview>,
+    search_input: Input,
+    /// The search prompt's pattern as last checked, and what's wrong with
+    /// it.
+    checked: String,
+    pattern_error: Option<PatternError>,
+    path_input: Input,
+    /// Where the current search looks, as the path field had it.
+    path: String,
+    /// The paths searched before narrowing down to `path`, outermost first.
+    scopes: Vec<String>,
+    literal: bool,
+    /// The searches followed away from, most recent last, and those gone
+    /// back from, most recent last.
+    trail: Vec<Visit>,
+    ahead: Vec<Visit>,
+    /// Where the next restart moves through `trail`, if it's a step.
+    step: Option<Step>,
+    /// The result to select once it comes in, after stepping back to a
+    /// search; any key gives up on it.
+    reselect: Option<(PathBuf, usize)>,
+    /// The searches refined from, innermost last.
+    refinements: Vec<Refinement>,
+    /// The lines a line refinement keeps; its other matches are dropped.
+    within: Option<Lines>,
+    refine_step: Option<RefineStep>,
+    /// Whether the search prompt's path field has the focus.
+    editing_path: bool,
+    /// Whether the keybinding overlay is open.
+    show_help: bool,
+    /// The files and directories that couldn't be read, with why.
+    errors: Vec<(PathBuf, String)>,
+    /// Whether the overlay listing `errors` is open.
+    show_errors: bool,
+    /// The groups of the files marked in file-action mode.
+    marked: HashSet<usize>,
+    /// The results marked in the list, kept across searches.
+    marks: Marks,
+    /// Whether results show the blame of their line.
+    show_blame: bool,
+    blames: Blames,
+}
+
+/// How many files' lines are kept for slim results at most, so scrolling
+/// through a file's results reads it once.
+const REREAD_FILES: usize = 16;
+
+/// A search on the trail of followed ones, with the result that was
+/// selected.
+#[derive(Clone)]
+struct Visit {
+    pattern: String,
+    path: String,
+    selected: Option<(PathBuf, usize)>,
+}
+
+#[derive(Clone, Copy)]
+enum Step {
+    Follow,
+    Back,
+    Forward,
+}
+
+enum Action {
+    None,
+    Quit,
+    /// Stop the running search.
+    Cancel,
+    Open(usize),
+    Copy(String),
+    /// Replace the results with a search for this pattern in this path.
+    Search(String, String),
+    /// Replace the results with a search for this pattern in these files.
+    Refine(String, Vec<PathBuf>),
+    /// Go back to the results refined from.
+    Unrefine(Refinement),
+}
+
+/// The lines a line refinement keeps, by file.
+type Lines = HashMap<PathBuf, HashSet<usize>>;
+
+/// A search refined from, kept to go back to as it was.
+struct Refinement {
+    pattern: String,
+    path: String,
+    results: Vec<Match>,
+    files: usize,
+    bytes: u64,
+    /// The lines it was refined to itself, if it was a line refinement.
+    within: Option<Lines>,
+}
+
+/// How the next restart moves through the refinements.
+enum RefineStep {
+    /// Into a refinement of this search, keeping these lines if given.
+    Push(Refinement, Option<Lines>),
+    /// Back out to a search refined to these lines, if any.
+    Pop(Option<Lines>),
+}
+
+/// Whether git could have blame for `path`: standard input and files in
+/// archives aren't in the working tree.
+fn has_blame(path: &Path) -> bool {
+    path != Path::new(STDIN_PATH) && archive::split_entry_path(path).is_none()
+}
+
+/// A file's row: its path with a fold marker and how many of its results
+/// are shown, or just the path when only files are listed.
+fn header_line<'a>(
+    group: &'a Group,
+    name: String,
+    shown: usize,
+    files_only: bool,
+    theme: &Theme,
+) -> Line<'a> {
+    let path = Span::styled(name, theme.path);
+    if files_only {
+        return Line::from(path);
+    }
+    let marker = if group.collapsed { "▸" } else { "▾" };
+    Line::from(vec![
+        Span::raw(format!("{} ", marker)),
+        path,
+        Span::raw(format!(" ({})", shown)),
+    ])
+}
+
+/// Renders a result with its matched span highlighted and the `patterns` it
+/// contains named, then its replacement preview and its context lines. The
+/// line numbers are left out without `line_numbers`, and with
+/// `only_matching` just the text of each match is shown. Lines longer than
+/// `max_columns` are cut down.
+fn result_text<'a>(
+    result: &'a Match,
+    patterns: &[String],
+    applied: bool,
+    line_numbers: bool,
+    only_matching: bool,
+    max_columns: Option<MaxColumns>,
+    theme: &Theme,
+) -> Text<'a> {
+    let context = |number: usize, line: &str| {
+        let line = clip(line, max_columns);
+        let text = if line_numbers {
+            format!("  {}- {}", number, line)
+        } else {
+            format!("  - {}", line)
+        };
+        Line::styled(text, theme.context)
+    };
+    let first = result.first_line_number();
+
+    let mut lines: Vec<Line> = result
+        .before
+        .iter()
+        .enumerate()
+        .map(|(i, line)| context(first + i, line))
+        .collect();
+    if only_matching {
+        lines.extend(matched_text_lines(result, line_numbers, theme));
+    } else {
+        lines.extend(match_lines(result, line_numbers, max_columns, theme));
+    }
+    if let Some(definition) = &result.definition
+        && let Some(line) = lines.get_mut(result.before.len())
+    {
+        line.spans.push(Span::styled(
+            format!("  in {}", definition.name),
+            theme.context,
+        ));
+    }
+    if !result.patterns.is_empty()
+        && let Some(line) = lines.get_mut(result.before.len())
+    {
+        let names: Vec<&str> = result
+            .patterns
+            .iter()
+            .filter_map(|&i| patterns.get(i).map(String::as_str))
+            .collect();
+        line.spans
+            .push(Span::styled(format!("  [{}]", names.join(", ")), theme.tag));
+    }
+    if let Some(replacement) = &result.replacement {
+        let marker = if applied { "✓" } else { "→" };
+        let indent = if line_numbers {
+            " ".repeat(result.line_number.to_string().len() + 1)
+        } else {
+            String::new()
+        };
+        for line in replacement.split('\n') {
+            lines.push(Line::styled(
+                format!("  {}{} {}", indent, marker, clip(line, max_columns)),
+                theme.replacement,
+            ));
+        }
+    }
+    let after_start = result.line_number + result.line_count();
+    lines.extend(
+        result
+            .after
+            .iter()
+            .enumerate()
+            .map(|(i, line)| context(after_start + i, line)),
+    );
+    Text::from(lines)
+}
+
+/// One line per line of the match itself (several with `-U`), with the
+/// part of the match that falls on each one highlighted.
+fn match_lines<'a>(
+    result: &'a Match,
+    line_numbers: bool,
+    max_columns: Option<MaxColumns>,
+    theme: &Theme,
+) -> Vec<Line<'a>> {
+    highlighted_lines(result, max_columns, theme)
+        .map(|(number, spans)| {
+            let location = if !line_numbers {
+                "  : ".to_string()
+            } else if number == result.line_number {
+                format!("  {}:{}: ", number, result.char_column())
+            } else {
+                format!("  {}: ", number)
+            };
+            let mut line = vec![Span::raw(location)];
+            line.extend(spans);
+            Line::from(line)
+        })
+        .collect()
+}
+
+/// One line per match on the line (per line of a match, with `-U`) for
+/// `-o`: its location and its text alone.
+fn matched_text_lines<'a>(result: &'a Match, line_numbers: bool, theme: &Theme) -> Vec<Line<'a>> {
+    result
+        .matched_pieces()
+        .map(|(offset, text)| {
+            let (number, _, column) = result.position(offset);
+            let location = if line_numbers {
+                format!("  {}:{}: ", number, column)
+            } else {
+                format!("  {}: ", column)
+            };
+            Line::from(vec![Span::raw(location), Span::styled(text, theme.matched)])
+        })
+        .collect()
+}
+
+/// Splits the lines of a match into spans, highlighting every match that
+/// falls on each line, paired with their line numbers. Lines longer than
+/// `max_columns` are cut down, and so are their highlights.
+fn highlighted_lines<'a>(
+    result: &'a Match,
+    max_columns: Option<MaxColumns>,
+    theme: &Theme,
+) -> impl Iterator<Item = (usize, Vec<Span<'a>>)> + use<'a> {
+    let (highlight, note_style) = (theme.matched, theme.context);
+    let matches = if result.spans_fit() {
+        &result.spans[..]
+    } else {
+        &[]
+    };
+    let mut offset = 0;
+
+    result.line.split('\n').enumerate().map(move |(i, line)| {
+        let end = offset + line.len();
+        let cut = max_columns.and_then(|max| max.cut(line));
+        let text = cut.as_ref().map_or(line, |(kept, _)| &line[..*kept]);
+        let mut spans = Vec::new();
+        let mut done = 0;
+        for matched in matches {
+            let from = matched.start.clamp(offset, end) - offset;
+            let to = matched.end.clamp(offset, end) - offset;
+            let (from, to) = (from.min(text.len()), to.min(text.len()));
+            if from == to {
+                continue;
+            }
+            spans.push(Span::raw(&text[done..from]));
+            spans.push(Span::styled(&text[from..to], highlight));
+            done = to;
+        }
+        spans.push(Span::raw(&text[done..]));
+        if let Some((_, note)) = cut {
+            spans.push(Span::styled(note, note_style));
+        }
+        offset = end + 1;
+        (result.line_number + i, spans)
+    })
+}
+
+/// The columns of the first span of `line` styled `matched`, from where it
+/// starts to where it ends.
+fn matched_columns(line: &Line<'_>, matched: Style) -> Option<(usize, usize)> {
+    let mut column = 0;
+    for span in &line.spans {
+        let width = span.width();
+        if span.style == matched {
+            return Some((column, column + width));
+        }
+        column += width;
+    }
+    None
+}
+
+/// `line` with the `shift` columns after its first `keep` cut out, and a
+/// `…` in place of the first column after them to show they are.
+fn shift_line(line: Line<'_>, keep: usize, shift: usize) -> Line<'_> {
+    let mut shifted = Line::default().style(line.style);
+    let mut column = 0;
+    let mut cut = false;
+    for span in line.spans {
+        let mut piece = String::new();
+        for c in span.content.chars() {
+            let start = column;
+            column += c.width().unwrap_or(0);
+            if start < keep || start > keep + shift {
+                piece.push(c);
+            } else if !cut {
+                cut = true;
+                shifted
+                    .spans
+                    .push(Span::styled(mem::take(&mut piece), span.style));
+                shifted.spans.push(Span::raw("…"));
+            }
+        }
+        shifted.spans.push(Span::styled(piece, span.style));
+    }
+    shifted
+}
+
+/// `text` with its own copy of what it shows.
+fn owned_text(text: Text<'_>) -> Text<'static> {
+    let lines: Vec<Line<'static>> = text
+        .lines
+        .into_iter()
+        .map(|line| {
+            let spans: Vec<Span<'static>> = line
+                .spans
+                .into_iter()
+                .map(|span| Span::styled(span.content.into_owned(), span.style))
+                .collect();
+            Line::from(spans).style(line.style)
+        })
+        .collect();
+    Text::from(lines)
+}
+
+/// Fits every line of `text` into `width` columns: a longer one is cut off
+/// with `…`, or with `wrap` continued on as many lines as it needs, each
+/// indented by `indent` columns. Wide characters take two columns and
+/// combining ones none.
+fn fit_text(text: Text<'_>, width: usize, wrap: bool, indent: usize) -> Text<'_> {
+    // nothing has been laid out before the first draw
+    if width == 0 {
+        return text;
+    }
+    let indent = if indent + 1 < width { indent } else { 0 };
+    // cut-off lines keep the last column for the ellipsis
+    let room = if wrap { width } else { width - 1 };
+    let mut lines = Vec::new();
+    for line in text.lines {
+        if line.width() <= width {
+            lines.push(line);
+            continue;
+        }
+        let mut fitted = Line::default().style(line.style);
+        let mut used = 0;
+        'spans: for span in line.spans {
+            let mut piece = String::new();
+            for c in span.content.chars() {
+                let columns = c.width().unwrap_or(0);
+                if used + columns > room {
+                    fitted
+                        .spans
+                        .push(Span::styled(std::mem::take(&mut piece), span.style));
+                    if !wrap {
+                        fitted.spans.push(Span::raw("…"));
+                        break 'spans;
+                    }
+                    lines.push(std::mem::replace(
+                        &mut fitted,
+                        Line::default().style(line.style),
+                    ));
+                    fitted.spans.push(Span::raw(" ".repeat(indent)));
+                    used = indent;
+                }
+                piece.push(c);
+                used += columns;
+            }
+            fitted.spans.push(Span::styled(piece, span.style));
+        }
+        lines.push(fitted);
+    }
+    Text::from(lines)
+}
+
+/// What a search of the paths in `text` looks through.
+fn source(text: &str) -> Source {
+    let mut paths = split_paths(text);
+    match paths.len() {
+        1 => Source::Path(paths.remove(0)),
+        _ => Source::Paths(paths),
+    }
+}
+
+/// The paths in the path field: several separated by spaces, unless the
+/// whole text names one that exists. Empty means the current directory.
+pub fn split_paths(text: &str) -> Vec<PathBuf> {
+    if Path::new(text).exists() {
+        return vec![PathBuf::from(text)];
+    }
+    let paths: Vec<PathBuf> = text.split_whitespace().map(PathBuf::from).collect();
+    if paths.is_empty() {
+        return vec![PathBuf::from(".")];
+    }
+    paths
+}
+
+fn format_result(result: &Match) -> String {
+    format!(
+        "{}:{} : {}",
+        result.path.display(),
+        result.line_number,
+        result.line
+    )
+}
+
+impl App {
+    fn new(options: &UiOptions, stats: Arc<SearchStats>) -> App {
+        App {
+            pattern: options.pattern.clone(),
+            patterns: options.patterns.clone(),
+            rules: options.rules,
+            allow_write: options.allow_write,
+            backup_suffix: options.backup_suffix.clone(),
+            encoding: options.encoding,
+            results: Vec::new(),
+            applied: HashSet::new(),
+            passes: Vec::new(),
+            result_group: Vec::new(),
+            groups: Vec::new(),
+            group_index: HashMap::new(),
+            sort: options.sort,
+            files_only: options.files_only,
+            paths: options.paths,
+            line_numbers: options.line_numbers,
+            only_matching: options.only_matching,
+            max_columns: options.max_columns,
+            show_stats: options.stats,
+            theme: options.theme,
+            keys: options.keys.clone(),
+            flags: options.flags.clone(),
+            rows: Vec::new(),
+            listed: Vec::new(),
+            tree: false,
+            dirs: Vec::new(),
+            depths: HashMap::new(),
+            folded: HashSet::new(),
+            dirty: false,
+            filter: String::new(),
+            mode: Mode::Normal,
+            list_state: ListState::default(),
+            page: 1,
+            list_area: Rect::default(),
+            number_width: 1,
+            wrap: false,
+            scroll: 0,
+            widest: Cell::new(0),
+            preview_area: None,
+            preview_scroll: 0,
+            preview_row: None,
+            last_click: None,
+            searching: true,
+            watch: options.watch,
+            watching: options.watch,
+            current_file: PathBuf::new(),
+            stats,
+            file_count: None,
+            started: Instant::now(),
+            finished: None,
+            cancelled: false,
+            message: None,
+            show_preview: false,
+            preview: None,
+            search_input: Input::with_history(options.history.clone()),
+            checked: String::new(),
+            pattern_error: None,
+            path_input: {
+                let mut input = Input::default();
+                input.set_text(&options.path);
+                input
+            },
+            path: options.path.clone(),
+            scopes: Vec::new(),
+            literal: options.literal,
+            trail: Vec::new(),
+            ahead: Vec::new(),
+            step: None,
+            reselect: None,
+            refinements: Vec::new(),
+            within: None,
+            refine_step: None,
+            editing_path: false,
+            show_help: false,
+            errors: Vec::new(),
+            show_errors: false,
+            marked: HashSet::new(),
+            marks: Marks::new(),
+            show_blame: false,
+            blames: Blames::new(),
+            slim: Vec::new(),
+            results_in_memory: options.results_in_memory,
+            reread: RefCell::default(),
+        }
+    }
+
+    /// Drops the current results in favour of a new search for `pattern`.
+    fn restart(&mut self, pattern: &str, path: &str, stats: Arc<SearchStats>) {
+        if self.scopes.last().is_some_and(|scope| scope == path) {
+            self.scopes.pop();
+        } else if path != self.path {
+            // a search below the current one narrows it, any other starts over
+            let roots = split_paths(&self.path);
+            let narrower = split_paths(path).iter().all(|new| {
+                roots
+                    .iter()
+                    .any(|root| new.starts_with(root) && new != root)
+            });
+            if narrower {
+                self.scopes.push(self.path.clone());
+            } else {
+                self.scopes.clear();
+            }
+        }
+        let visit = Visit {
+            pattern: self.pattern.clone(),
+            path: self.path.clone(),
+            selected: match self.selected_row() {
+                Some(Row::Result(i)) => {
+                    let result = &self.results[i];
+                    Some((result.path.clone(), result.line_number))
+                }
+                _ => None,
+            },
+        };
+        match self.step.take() {
+            Some(Step::Follow) => {
+                self.trail.push(visit);
+                self.ahead.clear();
+            }
+            Some(Step::Back) => {
+                self.reselect = self.trail.pop().and_then(|visit| visit.selected);
+                self.ahead.push(visit);
+            }
+            Some(Step::Forward) => {
+                self.reselect = self.ahead.pop().and_then(|visit| visit.selected);
+                self.trail.push(visit);
+            }
+            // a search of its own leaves where it came from on the trail
+            None => self.ahead.clear(),
+        }
+        match self.refine_step.take() {
+            Some(RefineStep::Push(refinement, within)) => {
+                self.refinements.push(refinement);
+                self.within = within;
+            }
+            Some(RefineStep::Pop(within)) => self.within = within,
+            None => {
+                self.refinements.clear();
+                self.within = None;
+            }
+        }
+        self.path = path.to_string();
+        self.pattern = pattern.to_string();
+        self.patterns.clear();
+        self.rules = false;
+        self.results.clear();
+        self.applied.clear();
+        self.passes.clear();
+        self.slim.clear();
+        self.reread.borrow_mut().clear();
+        self.result_group.clear();
+        self.groups.clear();
+        self.group_index.clear();
+        self.rows.clear();
+        self.dirty = true;
+        self.list_state = ListState::default();
+        self.searching = true;
+        self.watching = self.watch;
+        self.current_file = PathBuf::new();
+        self.stats = stats;
+        self.file_count = None;
+        self.started = Instant::now();
+        self.finished = None;
+        self.cancelled = false;
+        self.preview = None;
+        self.errors.clear();
+        self.marked.clear();
+    }
+
+    fn push_result(&mut self, mut result: Match) {
+        if let Some(within) = &self.within
+            && !within
+                .get(&result.path)
+                .is_some_and(|lines| lines.contains(&result.line_number))
+        {
+            return;
+        }
+        let i = self.results.len();
+        let group = match self.group_index.get(&result.path) {
+            Some(&group) => group,
+            None => {
+                self.group_index
+                    .insert(result.path.clone(), self.groups.len());
+                self.groups.push(Group {
+                    path: result.path.clone(),
+                    matches: Vec::new(),
+                    collapsed: false,
+                    meta: FileMeta::read(&result.path),
+                });
+                self.groups.len() - 1
+            }
+        };
+        self.groups[group].matches.push(i);
+        self.result_group.push(group);
+        // lines of multiline matches aren't told apart again, nor is stdin read again
+        let slim = self.results_in_memory > 0
+            && i >= self.results_in_memory
+            && result.line_count() == 1
+            && !result.is_stdin();
+        if slim && !result.line.is_empty() {
+            self.passes.push(self.passes_filter(&result));
+            result.line = String::new();
+        } else if slim {
+            // already slim, coming back from a refinement
+            self.passes.push(
+                self.filter.is_empty() || {
+                    let line = self.reread_line(&result.path, result.line_number);
+                    self.passes_filter(&Match {
+                        line,
+                        ..result.clone()
+                    })
+                },
+            );
+        } else {
+            self.passes.push(self.passes_filter(&result));
+        }
+        self.slim.push(slim);
+        self.results.push(result);
+        self.dirty = true;
+    }
+
+    /// Result `i` as it was found, with its line read again from the file
+    /// if it's slim, and without its spans if they no longer fit the line.
+    fn result(&self, i: usize) -> Cow<'_, Match> {
+        let result = &self.results[i];
+        if !self.slim[i] {
+            return Cow::Borrowed(result);
+        }
+        let mut reread = Match {
+            line: self.reread_line(&result.path, result.line_number),
+            ..result.clone()
+        };
+        if !reread.spans_fit() {
+            reread.spans.clear();
+        }
+        Cow::Owned(reread)
+    }
+
+    /// Forgets the lines read again from result `i`'s file, which the
+    /// editor may have changed.
+    fn edited(&self, i: usize) {
+        self.reread.borrow_mut().remove(&self.results[i].path);
+    }
+
+    /// Line `line_number` of `path`, read again for a slim result; empty if
+    /// the file has since lost it.
+    fn reread_line(&self, path: &Path, line_number: usize) -> String {
+        let mut reread = self.reread.borrow_mut();
+        if !reread.contains_key(path) {
+            if reread.len() >= REREAD_FILES {
+                reread.clear();
+            }
+            let lines = preview::read_lines(path, self.encoding).unwrap_or_default();
+            reread.insert(path.to_path_buf(), lines);
+        }
+        reread[path]
+            .get(line_number - 1)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Drops the results of a file that changed; its new ones, if any, go
+    /// into the same group. The old results stay in `results` so indices
+    /// into it remain valid, but nothing refers to them any more.
+    fn forget_file(&mut self, path: &Path) {
+        if let Some(&group) = self.group_index.get(path) {
+            self.marked.remove(&group);
+            let group = &mut self.groups[group];
+            group.matches.clear();
+            group.meta = FileMeta::read(path);
+            self.dirty = true;
+        }
+        if self.preview.as_ref().is_some_and(|p| p.path == path) {
+            self.preview = None;
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.searching {
+            self.searching = false;
+            self.finished = Some(self.started.elapsed());
+        }
+    }
+
+    fn passes_filter(&self, result: &Match) -> bool {
+        self.filter.is_empty()
+            || format_result(result)
+                .to_lowercase()
+                .contains(&self.filter.to_lowercase())
+    }
+
+    /// Rebuilds `rows`, keeping the selection on the same row when it's
+    /// still there.
+    fn refresh_rows(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        let selected = self.selected_row();
+
+        let mut order: Vec<usize> = (0..self.groups.len()).collect();
+        if let Some(sort) = self.sort {
+            let key = |g: usize| {
+                let group = &self.groups[g];
+                SortKey {
+                    path: &group.path,
+                    meta: group.meta,
+                    matches: group.matches.len(),
+                }
+            };
+            order.sort_by(|&a, &b| sort.compare(&key(a), &key(b)));
+        }
+
+        // directories are numbered afresh, so they're found again by path
+        let selected_dir = match selected {
+            Some(Row::Dir(d)) => self.dirs.get(d).map(|dir| dir.path.clone()),
+            _ => None,
+        };
+        self.rows.clear();
+        self.listed.clear();
+        self.dirs.clear();
+        self.depths.clear();
+        let shown: Vec<(usize, usize)> = order
+            .into_iter()
+            .map(|g| {
+                let matches = &self.groups[g].matches;
+                (g, matches.iter().filter(|&&i| self.passes[i]).count())
+            })
+            .filter(|&(_, n)| n > 0)
+            .collect();
+        if self.tree {
+            let mut root = TreeNode::default();
+            for &(g, n) in &shown {
+                let path = self.paths.show(&self.groups[g].path).into_owned();
+                let mut node = &mut root;
+                for component in path.parent().into_iter().flat_map(Path::components) {
+                    if component == Component::CurDir {
+                        continue;
+                    }
+                    node = node
+                        .dirs
+                        .entry(component.as_os_str().to_os_string())
+                        .or_default();
+                    node.shown += n;
+                }
+                node.files.push(g);
+            }
+            self.push_tree(&root, Path::new(""), 0, false);
+        } else {
+            for (g, _) in shown {
+                self.listed.push(g);
+                self.push_group(g);
+            }
+        }
+
+        let mut position = match &selected_dir {
+            Some(path) => self
+                .rows
+                .iter()
+                .position(|&row| matches!(row, Row::Dir(d) if self.dirs[d].path == *path)),
+            None => selected.and_then(|row| self.rows.iter().position(|&r| r == row)),
+        };
+        if let Some((path, line_number)) = &self.reselect
+            && let Some(found) = self.rows.iter().position(|&row| {
+                matches!(row, Row::Result(i)
+                    if self.results[i].path == *path && self.results[i].line_number == *line_number)
+            })
+        {
+            position = Some(found);
+            self.reselect = None;
+        }
+        match position {
+            Some(position) => self.list_state.select(Some(position)),
+            None if self.rows.is_empty() => self.list_state.select(None),
+            None => {
+                let clamped = self.list_state.selected().unwrap_or(0);
+                self.list_state
+                    .select(Some(clamped.min(self.rows.len() - 1)));
+            }
+        }
+    }
+
+    /// Lists group `g`'s file, and its results unless it's folded.
+    fn push_group(&mut self, g: usize) {
+        self.rows.push(Row::Header(g));
+        let group = &self.groups[g];
+        if !group.collapsed && !self.files_only {
+            let shown = group.matches.iter().copied().filter(|&i| self.passes[i]);
+            self.rows.extend(shown.map(Row::Result));
+        }
+    }
+
+    /// Lists `node`, the directory at `path` `depth` levels down: its
+    /// directories, each followed by what's in it unless it's folded, and
+    /// then its files. With `hidden` they're only counted as listed, being
+    /// in a folded directory.
+    fn push_tree(&mut self, node: &TreeNode, path: &Path, depth: usize, hidden: bool) {
+        for (name, child) in &node.dirs {
+            let mut child = child;
+            let mut path = path.join(name);
+            let mut name = PathBuf::from(name);
+            // a chain of directories holding only each other is one row
+            while child.files.is_empty()
+                && child.dirs.len() == 1
+                && let Some((next, only)) = child.dirs.iter().next()
+            {
+                path.push(next);
+                name.push(next);
+                child = only;
+            }
+            let collapsed = self.folded.contains(&path);
+            if !hidden {
+                self.dirs.push(Dir {
+                    path: path.clone(),
+                    name,
+                    depth,
+                    collapsed,
+                    shown: child.shown,
+                });
+                self.rows.push(Row::Dir(self.dirs.len() - 1));
+            }
+            self.push_tree(child, &path, depth + 1, hidden || collapsed);
+        }
+        for &g in &node.files {
+            self.listed.push(g);
+            if !hidden {
+                self.depths.insert(g, depth);
+                self.push_group(g);
+            }
+        }
+    }
+
+    fn selected_row(&self) -> Option<Row> {
+        let i = self.list_state.selected()?;
+        self.rows
+            .get(i.min(self.rows.len().checked_sub(1)?))
+            .copied()
+    }
+
+    /// Makes sure the preview holds the file of the current selection.
+    fn update_preview(&mut self) {
+        if !self.show_preview {
+            return;
+        }
+        // scrolling the preview lasts until the selection moves
+        let selected = self.selected_row();
+        if selected != self.preview_row {
+            self.preview_row = selected;
+            self.preview_scroll = 0;
+        }
+        let path = match self.selected_row() {
+            Some(Row::Header(g)) => &self.groups[g].path,
+            Some(Row::Result(i)) => &self.results[i].path,
+            Some(Row::Dir(_)) | None => {
+                self.preview = None;
+                return;
+            }
+        };
+        if self.preview.as_ref().is_none_or(|p| p.path != *path) {
+            self.preview = Some(Preview::load(path, self.encoding));
+        }
+    }
+
+    fn toggle_group(&mut self) {
+        let group = match self.selected_row() {
+            Some(Row::Header(g)) => g,
+            Some(Row::Result(i)) => self.result_group[i],
+            Some(Row::Dir(d)) => {
+                let path = &self.dirs[d].path;
+                if !self.folded.remove(path) {
+                    self.folded.insert(path.clone());
+                }
+                self.dirty = true;
+                return;
+            }
+            None => return,
+        };
+        self.groups[group].collapsed = !self.groups[group].collapsed;
+        // keep the cursor on the file, its result rows may be gone
+        if let Some(position) = self.rows.iter().position(|&r| r == Row::Header(group)) {
+            self.list_state.select(Some(position));
+        }
+        self.dirty = true;
+    }
+
+    /// Marks the selected result, or unmarks it and drops its note, and
+    /// moves on to the next result.
+    fn toggle_mark(&mut self) {
+        let Some(Row::Result(i)) = self.selected_row() else {
+            return;
+        };
+        let key = self.mark_key(i);
+        if self.marks.remove(&key).is_none() {
+            self.marks.insert(key, String::new());
+        }
+        let next = self.list_state.selected().and_then(|current| {
+            (current + 1..self.rows.len()).find(|&i| matches!(self.rows[i], Row::Result(_)))
+        });
+        if next.is_some() {
+            self.list_state.select(next);
+        }
+    }
+
+    /// What result `i` is marked by.
+    fn mark_key(&self, i: usize) -> (PathBuf, usize) {
+        (self.results[i].path.clone(), self.results[i].line_number)
+    }
+
+    /// Asks for confirmation before writing the replacements of `results`.
+    fn confirm_replace(&mut self, results: Vec<usize>) {
+        if self.results.first().is_none_or(|r| r.replacement.is_none()) {
+            self.message = Some("Nothing to apply, start rstr with --replace".to_string());
+            return;
+        }
+        if !self.allow_write {
+            self.message = Some(
+                "Replacements are preview-only, start rstr with --write to apply them".to_string(),
+            );
+            return;
+        }
+        let pending: Vec<usize> = results
+            .into_iter()
+            .filter(|i| !self.applied.contains(i))
+            .collect();
+        if pending.is_empty() {
+            self.message = Some("Already applied".to_string());
+        } else {
+            self.mode = Mode::Confirm(pending);
+        }
+    }
+
+    fn apply_replacements(&mut self, pending: &[usize]) {
+        let Some(&first) = pending.first() else {
+            return;
+        };
+        let path = self.results[first].path.clone();
+        let results: Vec<Cow<Match>> = pending.iter().map(|&i| self.result(i)).collect();
+        let edits: Vec<Edit> = results
+            .iter()
+            .filter_map(|result| {
+                Some(Edit {
+                    line_number: result.line_number,
+                    original: &result.line,
+                    replacement: result.replacement.as_deref()?,
+                })
+            })
+            .collect();
+
+        match replace::apply(&path, &edits, self.backup_suffix.as_deref()) {
+            Ok(applied) => {
+                let mut message = format!(
+                    "Applied {} replacement(s) in {}",
+                    applied.len(),
+                    path.display()
+                );
+                if applied.len() < edits.len() {
+                    message.push_str(&format!(
+                        ", skipped {} whose line changed since the search",
+                        edits.len() - applied.len()
+                    ));
+                }
+                self.message = Some(message);
+                // only mark what was actually rewritten
+                self.applied.extend(
+                    pending
+                        .iter()
+                        .copied()
+                        .filter(|&i| applied.contains(&self.results[i].line_number)),
+                );
+                self.reread.borrow_mut().remove(&path);
+                self.dirty = true;
+            }
+            Err(err) => {
+                self.message = Some(format!("Failed to write {}: {}", path.display(), err));
+            }
+        }
+    }
+
+    fn refilter(&mut self) {
+        self.passes = (0..self.results.len())
+            .map(|i| self.passes_filter(&self.result(i)))
+            .collect();
+        self.list_state = ListState::default();
+        self.dirty = true;
+    }
+
+    /// The header's line: feedback if there is any, otherwise what the
+    /// search is doing. The totals are in the status bar.
+    fn status(&self) -> String {
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+        if self.searching {
+            format!("Searching {}", self.current_file.display())
+        } else if self.cancelled {
+            "Search cancelled".to_string()
+        } else if self.watching {
+            "Search done, watching for changes".to_string()
+        } else {
+            "Search done".to_string()
+        }
+    }
+
+    /// The status bar's left side: the totals so far and how long it took.
+    fn totals(&self) -> String {
+        let elapsed = self.finished.unwrap_or_else(|| self.started.elapsed());
+        // results of files that changed under --watch don't count
+        let live = self.groups.iter().filter(|group| !group.matches.is_empty());
+        let matches: usize = live.clone().map(|group| group.matches.len()).sum();
+        let mut totals = format!(
+            "{} matches in {} files · {} files ({}) searched · {:.1}s",
+            matches,
+            live.count(),
+            self.stats.files(),
+            format_bytes(self.stats.bytes()),
+            elapsed.as_secs_f64()
+        );
+        if self.show_stats {
+            totals.push_str(&format!(" · {}", throughput(self.stats.bytes(), elapsed)));
+        }
+        if !self.filter.is_empty() {
+            let shown = self
+                .groups
+                .iter()
+                .flat_map(|group| &group.matches)
+                .filter(|&&i| self.passes[i])
+                .count();
+            totals.push_str(&format!(" · {} shown", shown));
+        }
+        if !self.errors.is_empty() {
+            totals.push_str(&format!(" · {} errors (e)", self.errors.len()));
+        }
+        totals
+    }
+
+    /// The status bar's right side: the order, the filter and the flags
+    /// the search runs with.
+    fn modes(&self) -> String {
+        let mut modes = Vec::new();
+        if let Some(sort) = self.sort {
+            modes.push(format!("sort: {}", sort.name()));
+        }
+        if self.paths != PathStyle::Found {
+            modes.push(format!("paths: {}", self.paths.name()));
+        }
+        if self.wrap {
+            modes.push("wrap".to_string());
+        }
+        if !self.filter.is_empty() {
+            modes.push(format!("filter: {}", self.filter));
+        }
+        if !self.refinements.is_empty() {
+            let patterns: Vec<&str> = self
+                .refinements
+                .iter()
+                .map(|refinement| refinement.pattern.as_str())
+                .collect();
+            modes.push(format!("within: {}", patterns.join(" › ")));
+        }
+        if !self.flags.is_empty() {
+            modes.push(self.flags.join(" "));
+        }
+        modes.join(" · ")
+    }
+
+    /// Searches for the selected match's text where the search looks now,
+    /// or for what its first group took when the pattern has groups.
+    fn follow(&mut self) -> Action {
+        let Some(Row::Result(i)) = self.selected_row() else {
+            self.message = Some("Select a match to search for".to_string());
+            return Action::None;
+        };
+        let result = self.result(i);
+        let Some(span) = result.spans.first() else {
+            self.message = Some("The selected line has no match to search for".to_string());
+            return Action::None;
+        };
+        let mut text = &result.line[span.clone()];
+        if !self.literal
+            && let Ok(regex) = regex::Regex::new(&self.pattern)
+            && let Some(captures) = regex.captures_at(&result.line, span.start)
+            && let Some(group) = captures.iter().skip(1).flatten().next()
+            && !group.is_empty()
+        {
+            text = group.as_str();
+        }
+        if text.is_empty() || text.contains('\n') {
+            self.message = Some("Only matches within a line can be searched for".to_string());
+            return Action::None;
+        }
+        let pattern = if self.literal {
+            text.to_string()
+        } else {
+            regex::escape(text)
+        };
+        self.step = Some(Step::Follow);
+        Action::Search(pattern, self.path.clone())
+    }
+
+    /// Goes back or forward to a search on the trail of followed ones.
+    fn step(&mut self, step: Step) -> Action {
+        let visit = match step {
+            Step::Back => self.trail.last(),
+            _ => self.ahead.last(),
+        };
+        let Some(visit) = visit.cloned() else {
+            self.message = Some(match step {
+                Step::Back => "No followed search to go back from".to_string(),
+                _ => "No search to go forward to".to_string(),
+            });
+            return Action::None;
+        };
+        self.path_input.set_text(&visit.path);
+        self.step = Some(step);
+        Action::Search(visit.pattern, visit.path)
+    }
+
+    /// Searches the files of the results that pass the filter for
+    /// `pattern`, keeping with `lines` only the matches on their lines.
+    fn refine(&mut self, pattern: String, lines: bool) -> Action {
+        let mut files = Vec::new();
+        let mut within = Lines::new();
+        let mut seen = HashSet::new();
+        for &g in &self.listed {
+            for &i in self.groups[g].matches.iter().filter(|&&i| self.passes[i]) {
+                let path = &self.results[i].path;
+                // the search goes through the archive again to get at the entry
+                let file =
+                    archive::split_entry_path(path).map_or(path.as_path(), |(archive, _)| archive);
+                if seen.insert(file.to_path_buf()) {
+                    files.push(file.to_path_buf());
+                }
+                within
+                    .entry(path.clone())
+                    .or_default()
+                    .insert(self.results[i].line_number);
+            }
+        }
+        if files.is_empty() {
+            self.message = Some("No results to search within".to_string());
+            return Action::None;
+        }
+        if files.iter().any(|file| file == Path::new(STDIN_PATH)) {
+            self.message = Some("Standard input can't be searched again".to_string());
+            return Action::None;
+        }
+        let refinement = Refinement {
+            pattern: self.pattern.clone(),
+            path: self.path.clone(),
+            results: self
+                .groups
+                .iter()
+                .flat_map(|group| &group.matches)
+                .map(|&i| self.results[i].clone())
+                .collect(),
+            files: self.stats.files(),
+            bytes: self.stats.bytes(),
+            within: self.within.clone(),
+        };
+        self.refine_step = Some(RefineStep::Push(refinement, lines.then_some(within)));
+        Action::Refine(pattern, files)
+    }
+
+    /// Searches again in the directory of the selected file, if that's
+    /// below where the current search looks.
+    fn narrow(&mut self) -> Action {
+        let Some(g) = self.selected_group() else {
+            return Action::None;
+        };
+        let path = &self.groups[g].path;
+        if path == Path::new(STDIN_PATH) {
+            self.message = Some("Standard input has no directory".to_string());
+            return Action::None;
+        }
+        let file = archive::split_entry_path(path).map_or(path.as_path(), |(archive, _)| archive);
+        let dir = match file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let roots = split_paths(&self.path);
+        if !roots
+            .iter()
+            .any(|root| dir.starts_with(root) && dir != root.as_path())
+        {
+            self.message = Some(format!("Already searching {}", dir.display()));
+            return Action::None;
+        }
+        let dir = dir.display().to_string();
+        self.path_input.set_text(&dir);
+        Action::Search(self.pattern.clone(), dir)
+    }
+
+    /// The scopes narrowed down through to the current one, each shown
+    /// below the one before, e.g. `. › src › parser`. `None` unless narrowed.
+    fn breadcrumb(&self) -> Option<String> {
+        let outermost = self.scopes.first()?;
+        let mut crumbs = vec![outermost.clone()];
+        let inner = self.scopes.iter().skip(1).chain([&self.path]);
+        for (outer, scope) in self.scopes.iter().zip(inner) {
+            let below = Path::new(scope).strip_prefix(outer).ok();
+            crumbs.push(below.map_or(scope.clone(), |below| below.display().to_string()));
+        }
+        Some(crumbs.join(" › "))
+    }
+
+    /// What `Enter` does: open the selected result, or fold the selected
+    /// file or directory.
+    fn activate(&mut self) -> Action {
+        let open = match self.selected_row() {
+            Some(Row::Result(i)) => i,
+            // a listed file opens where it first matched
+            Some(Row::Header(g)) if self.files_only => self.groups[g].matches[0],
+            Some(Row::Header(_) | Row::Dir(_)) => {
+                self.toggle_group();
+                return Action::None;
+            }
+            None => return Action::None,
+        };
+        if self.results[open].is_stdin() {
+            self.message = Some("Standard input can't be opened in an editor".to_string());
+            return Action::None;
+        }
+        if archive::split_entry_path(&self.results[open].path).is_some() {
+            self.message = Some("Files inside archives can't be opened in an editor".to_string());
+            return Action::None;
+        }
+        Action::Open(open)
+    }
+
+    /// Clicks select a row and double-clicks activate it; the wheel scrolls
+    /// the list or the preview, whichever it's over.
+    fn handle_mouse(&mut self, mouse: MouseEvent) -> Action {
+        let over = |area: Rect| area.contains(Position::new(mouse.column, mouse.row));
+        let over_preview = self.preview_area.is_some_and(over);
+        match mouse.kind {
+            MouseEventKind::ScrollDown if over_preview => self.scroll_preview(3),
+            MouseEventKind::ScrollUp if over_preview => self.scroll_preview(-3),
+            MouseEventKind::ScrollDown => self.list_state.scroll_down_by(3),
+            MouseEventKind::ScrollUp => self.list_state.scroll_up_by(3),
+            MouseEventKind::Down(MouseButton::Left) => {
+                self.message = None;
+                if self.show_help {
+                    self.show_help = false;
+                    return Action::None;
+                }
+                if self.mode != Mode::Normal || !over(self.list_area) {
+                    return Action::None;
+                }
+                let Some(row) = self.row_at(mouse.row) else {
+                    return Action::None;
+                };
+                self.list_state.select(Some(row));
+                let double = self
+                    .last_click
+                    .is_some_and(|(at, last)| last == row && at.elapsed() < DOUBLE_CLICK);
+                if double {
+                    self.last_click = None;
+                    return self.activate();
+                }
+                self.last_click = Some((Instant::now(), row));
+            }
+            _ => {}
+        }
+        Action::None
+    }
+
+    /// The index of the row drawn at screen line `y`, if any.
+    fn row_at(&self, y: u16) -> Option<usize> {
+        let top = self.list_area.y + 1;
+        if y < top || y + 1 >= self.list_area.bottom() {
+            return None;
+        }
+        let mut line = top as usize;
+        for (i, &row) in self.rows.iter().enumerate().skip(self.list_state.offset()) {
+            line += self.row_height(row);
+            if (y as usize) < line {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    fn row_height(&self, row: Row) -> usize {
+        self.row_text(row, 0).height()
+    }
+
+    /// How a row is drawn in the results list, with `number` in front if
+    /// it's numbered, fitted to the list's width.
+    fn row_text(&self, row: Row, number: usize) -> Text<'_> {
+        let width = self.number_width;
+        let number_span = Span::styled(format!("{:>width$}.", number), self.theme.context);
+        let text = match row {
+            Row::Header(g) => {
+                let group = &self.groups[g];
+                let shown = group.matches.iter().filter(|&&i| self.passes[i]).count();
+                // the tree has the directories already
+                let name = match self.tree {
+                    true => group.path.file_name().map_or_else(
+                        || group.path.display().to_string(),
+                        |name| name.to_string_lossy().into_owned(),
+                    ),
+                    false => self.paths.show(&group.path).display().to_string(),
+                };
+                let mut line = header_line(group, name, shown, self.files_only, &self.theme);
+                if self.marked.contains(&g) {
+                    line.spans.push(Span::styled(" [marked]", self.theme.tag));
+                }
+                let depth = self.depths.get(&g).copied().unwrap_or(0);
+                if depth > 0 {
+                    line.spans.insert(0, Span::raw("  ".repeat(depth)));
+                }
+                if self.files_only {
+                    line.spans.insert(0, number_span);
+                    line.spans.insert(1, Span::raw(" "));
+                }
+                Text::from(line)
+            }
+            // a slim result's line is gone again once drawn
+            Row::Result(i) if self.slim[i] => {
+                owned_text(self.result_row(&self.result(i), i, number_span))
+            }
+            Row::Result(i) => self.result_row(&self.results[i], i, number_span),
+            Row::Dir(d) => {
+                let dir = &self.dirs[d];
+                let marker = if dir.collapsed { "▸" } else { "▾" };
+                Text::from(Line::from(vec![
+                    Span::raw(format!("{}{} ", "  ".repeat(dir.depth), marker)),
+                    // `/` itself is `/`, not `//`
+                    Span::styled(
+                        format!("{}/", dir.name.display().to_string().trim_end_matches('/')),
+                        self.theme.path,
+                    ),
+                    Span::raw(format!(" ({})", dir.shown)),
+                ]))
+            }
+        };
+        let text = match row {
+            Row::Result(i) if self.wrap => self.mark_row(text, i),
+            Row::Result(i) => self.scroll_row(self.mark_row(text, i), row),
+            Row::Header(_) | Row::Dir(_) => text,
+        };
+        // inside the list's borders, wrapped lines indented past the numbers
+        let columns = self.list_area.width.saturating_sub(2) as usize;
+        fit_text(text, columns, self.wrap, width + 1)
+    }
+
+    /// How result `i`, which is `result`, is drawn in the list, before
+    /// it's fitted to its width.
+    fn result_row<'a>(&'a self, result: &'a Match, i: usize, number_span: Span<'a>) -> Text<'a> {
+        let width = self.number_width;
+        let applied = self.applied.contains(&i);
+        let mut text = result_text(
+            result,
+            &self.patterns,
+            applied,
+            self.line_numbers,
+            self.only_matching,
+            self.max_columns,
+            &self.theme,
+        );
+        let blank = " ".repeat(width + 1);
+        let blame = self.show_blame.then(|| {
+            let blame = if has_blame(&result.path) {
+                self.blames.get(&result.path, result.line_number)
+            } else {
+                Some(None)
+            };
+            blame::label(blame, BLAME_WIDTH)
+        });
+        for (k, line) in text.lines.iter_mut().enumerate() {
+            if let Some(blame) = &blame {
+                let column = if k == result.before.len() {
+                    format!(" {}", blame)
+                } else {
+                    " ".repeat(BLAME_WIDTH + 1)
+                };
+                line.spans
+                    .insert(0, Span::styled(column, self.theme.context));
+            }
+            let prefix = if k == result.before.len() {
+                number_span.clone()
+            } else {
+                Span::raw(blank.clone())
+            };
+            line.spans.insert(0, prefix);
+        }
+        text
+    }
+
+    /// Result `i`'s row with a `*` after its number if it's marked, and its
+    /// note after its line.
+    fn mark_row<'a>(&self, mut text: Text<'a>, i: usize) -> Text<'a> {
+        let Some(note) = self.marks.get(&self.mark_key(i)) else {
+            return text;
+        };
+        let line = &mut text.lines[self.results[i].before.len()];
+        if let Some(number) = line.spans.first_mut() {
+            let marked = number.content.replacen('.', "*", 1);
+            *number = Span::styled(marked, self.theme.tag);
+        }
+        if !note.is_empty() {
+            line.spans
+                .push(Span::styled(format!("  # {}", note), self.theme.tag));
+        }
+        text
+    }
+
+    /// Result `row`'s lines scrolled sideways by `scroll` columns past the
+    /// numbers in front, or when it's selected by as much more or less as
+    /// it takes to show its first match.
+    fn scroll_row<'a>(&self, text: Text<'a>, row: Row) -> Text<'a> {
+        let blame = if self.show_blame { BLAME_WIDTH + 1 } else { 0 };
+        let keep = self.number_width + 1 + blame;
+        let columns = self.list_area.width.saturating_sub(2) as usize;
+        let widest = text.lines.iter().map(Line::width).max().unwrap_or(0);
+        self.widest.set(self.widest.get().max(widest));
+        let mut shift = self.scroll;
+        if self.selected_row() == Some(row)
+            && let Some((start, end)) = text
+                .lines
+                .iter()
+                .find_map(|line| matched_columns(line, self.theme.matched))
+            && (shift > 0 || end >= columns)
+        {
+            // the match's end clear of the `…` cutting the line off, and
+            // its start of the one in front
+            shift = shift
+                .max((end + 2).saturating_sub(columns))
+                .min(start.saturating_sub(keep + 1));
+        }
+        if shift == 0 {
+            return text;
+        }
+        let lines: Vec<Line> = text
+            .lines
+            .into_iter()
+            .map(|line| shift_line(line, keep, shift))
+            .collect();
+        Text::from(lines)
+    }
+
+    /// Scrolls the results' lines sideways by a quarter of the list's
+    /// width, `right` or left, no further than the widest is long.
+    fn scroll_sideways(&mut self, right: bool) {
+        if self.wrap {
+            self.message = Some("Long lines wrap, there's nothing to scroll".to_string());
+            return;
+        }
+        let columns = self.list_area.width.saturating_sub(2) as usize;
+        let step = (columns / 4).max(1);
+        self.scroll = if right {
+            let most = (self.widest.get() + 2).saturating_sub(columns);
+            (self.scroll + step).min(most)
+        } else {
+            self.scroll.saturating_sub(step)
+        };
+    }
+
+    fn scroll_preview(&mut self, lines: isize) {
+        let len = self.preview.as_ref().map_or(0, |p| p.lines.len()) as isize;
+        self.preview_scroll = (self.preview_scroll + lines).clamp(-len, len);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        self.message = None;
+        self.reselect = None;
+        if self.show_help || self.show_errors {
+            // any key closes the overlays
+            self.show_help = false;
+            self.show_errors = false;
+            return Action::None;
+        }
+        if self.mode == Mode::Search {
+            return self.handle_search_key(key);
+        }
+        match key.code {
+            KeyCode::Down if self.mode != Mode::Normal => self.list_state.select_next(),
+            KeyCode::Up if self.mode != Mode::Normal => self.list_state.select_previous(),
+            KeyCode::PageDown if self.mode != Mode::Normal => {
+                self.list_state.scroll_down_by(self.page)
+            }
+            KeyCode::PageUp if self.mode != Mode::Normal => self.list_state.scroll_up_by(self.page),
+            _ if matches!(self.mode, Mode::Confirm(_)) => self.handle_confirm_key(key),
+            _ if self.mode == Mode::Filter => self.handle_filter_key(key),
+            _ if matches!(self.mode, Mode::Jump(_)) => self.handle_jump_key(key),
+            _ if self.mode == Mode::Files => return self.handle_files_key(key),
+            _ if self.mode == Mode::ConfirmDelete => self.handle_delete_key(key),
+            _ if matches!(self.mode, Mode::Export(_)) => self.handle_export_key(key),
+            _ if matches!(self.mode, Mode::ExportResults(_)) => self.handle_export_results_key(key),
+            _ if matches!(self.mode, Mode::Note(..)) => self.handle_note_key(key),
+            _ if matches!(self.mode, Mode::Refine { .. }) => return self.handle_refine_key(key),
+            _ => {
+                let running = self.searching || self.watching;
+                // marking is for results, files' rows fold
+                let on_result = matches!(self.selected_row(), Some(Row::Result(_)));
+                let command = self.keys.commands(key).find(|&command| match command {
+                    Command::Cancel => running,
+                    Command::Mark | Command::Note => on_result,
+                    _ => true,
+                });
+                if let Some(command) = command {
+                    return self.run_command(command);
+                }
+                if let KeyCode::Char(digit @ '0'..='9') = key.code {
+                    self.mode = Mode::Jump(digit.to_string());
+                }
+            }
+        }
+        Action::None
+    }
+
+    /// Does what a key bound to `command` in the results list does.
+    fn run_command(&mut self, command: Command) -> Action {
+        match command {
+            Command::Down => self.list_state.select_next(),
+            Command::Up => self.list_state.select_previous(),
+            Command::PageDown => self.list_state.scroll_down_by(self.page),
+            Command::PageUp => self.list_state.scroll_up_by(self.page),
+            Command::HalfPageDown => self.list_state.scroll_down_by((self.page / 2).max(1)),
+            Command::HalfPageUp => self.list_state.scroll_up_by((self.page / 2).max(1)),
+            Command::First => self.list_state.select_first(),
+            Command::Last => self.list_state.select_last(),
+            Command::Cancel => return Action::Cancel,
+            Command::Quit => return Action::Quit,
+            Command::Filter => self.mode = Mode::Filter,
+            Command::Jump => self.mode = Mode::Jump(String::new()),
+            Command::Files => self.mode = Mode::Files,
+            Command::Help => self.show_help = true,
+            Command::Errors => {
+                if self.errors.is_empty() {
+                    self.message = Some("No errors so far".to_string());
+                } else {
+                    self.show_errors = true;
+                }
+            }
+            Command::Search => {
+                let pattern = self.pattern.clone();
+                self.search_input.set_text(&pattern);
+                self.editing_path = false;
+                self.mode = Mode::Search;
+            }
+            Command::Narrow => return self.narrow(),
+            Command::Widen => {
+                let Some(scope) = self.scopes.last().cloned() else {
+                    self.message = Some("The search isn't narrowed down".to_string());
+                    return Action::None;
+                };
+                self.path_input.set_text(&scope);
+                return Action::Search(self.pattern.clone(), scope);
+            }
+            Command::Follow => return self.follow(),
+            Command::Back => return self.step(Step::Back),
+            Command::Forward => return self.step(Step::Forward),
+            Command::Open => return self.activate(),
+            Command::Mark => self.toggle_mark(),
+            Command::Note => {
+                if let Some(Row::Result(i)) = self.selected_row() {
+                    let key = self.mark_key(i);
+                    let mut input = Input::default();
+                    input.set_text(self.marks.get(&key).map_or("", String::as_str));
+                    self.mode = Mode::Note(key, input);
+                }
+            }
+            Command::Fold => self.toggle_group(),
+            Command::Tree => {
+                self.tree = !self.tree;
+                self.dirty = true;
+            }
+            Command::Sort => {
+                self.sort = SortBy::cycle(self.sort);
+                self.dirty = true;
+            }
+            Command::Paths => {
+                self.paths = self.paths.next();
+                self.message = Some(format!("Paths: {}", self.paths.name()));
+            }
+            Command::ScrollLeft => self.scroll_sideways(false),
+            Command::ScrollRight => self.scroll_sideways(true),
+            Command::Wrap => {
+                self.wrap = !self.wrap;
+                self.message = Some(if self.wrap {
+                    "Wrapping long lines".to_string()
+                } else {
+                    "Cutting off long lines".to_string()
+                });
+            }
+            Command::Blame => {
+                self.show_blame = !self.show_blame;
+                self.message = Some(if self.show_blame {
+                    "Showing who last changed each line".to_string()
+                } else {
+                    "Hiding the blame".to_string()
+                });
+            }
+            Command::Preview => {
+                self.show_preview = !self.show_preview;
+                if !self.show_preview {
+                    self.preview = None;
+                }
+            }
+            Command::Copy => match self.selected_row() {
+                Some(Row::Result(i)) => {
+                    let result = self.result(i);
+                    let location = format!(
+                        "{}:{}:{}",
+                        self.paths.show(&result.path).display(),
+                        result.line_number,
+                        result.char_column()
+                    );
+                    return Action::Copy(location);
+                }
+                Some(Row::Header(g)) => {
+                    let path = self.paths.show(&self.groups[g].path);
+                    return Action::Copy(path.display().to_string());
+                }
+                Some(Row::Dir(d)) => return Action::Copy(self.dirs[d].path.display().to_string()),
+                None => {}
+            },
+            Command::CopyLine => {
+                if let Some(Row::Result(i)) = self.selected_row() {
+                    return Action::Copy(self.result(i).into_owned().line);
+                }
+            }
+            Command::Export => self.mode = Mode::ExportResults(Input::default()),
+            Command::Refine => {
+                self.mode = Mode::Refine {
+                    input: Input::default(),
+                    lines: false,
+                }
+            }
+            Command::Unrefine => {
+                let Some(refinement) = self.refinements.pop() else {
+                    self.message = Some("The results aren't refined".to_string());
+                    return Action::None;
+                };
+                self.refine_step = Some(RefineStep::Pop(refinement.within.clone()));
+                return Action::Unrefine(refinement);
+            }
+            Command::Replace => {
+                if let Some(Row::Result(i)) = self.selected_row() {
+                    self.confirm_replace(vec![i]);
+                }
+            }
+            Command::ReplaceFile => {
+                let group = match self.selected_row() {
+                    Some(Row::Header(g)) => g,
+                    Some(Row::Result(i)) => self.result_group[i],
+                    Some(Row::Dir(_)) | None => return Action::None,
+                };
+                self.confirm_replace(self.groups[group].matches.clone());
+            }
+        }
+        Action::None
+    }
+
+    fn handle_confirm_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => {
+                if let Mode::Confirm(pending) = std::mem::replace(&mut self.mode, Mode::Normal) {
+                    self.apply_replacements(&pending);
+                }
+            }
+            KeyCode::Char('n') | KeyCode::Esc => {
+                self.mode = Mode::Normal;
+                self.message = Some("Cancelled".to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_search_key(&mut self, key: KeyEvent) -> Action {
+        match key.code {
+            KeyCode::Enter if !self.search_input.text().is_empty() => {
+                return Action::Search(
+                    self.search_input.text().to_string(),
+                    self.path_input.text().to_string(),
+                );
+            }
+            KeyCode::Esc => self.mode = Mode::Normal,
+            KeyCode::Tab | KeyCode::BackTab => self.editing_path = !self.editing_path,
+            _ if self.editing_path => {
+                self.path_input.handle_key(key);
+            }
+            _ => {
+                self.search_input.handle_key(key);
+            }
+        }
+        Action::None
+    }
+
+    /// Checks the search prompt's pattern again once it has changed, so
+    /// what's wrong with it shows while it's typed.
+    fn check_pattern(&mut self, check: &CheckPattern) {
+        if self.mode != Mode::Search || self.search_input.text() == self.checked {
+            return;
+        }
+        self.checked = self.search_input.text().to_string();
+        self.pattern_error = match self.checked.is_empty() {
+            true => None,
+            false => check(&self.checked),
+        };
+    }
+
+    /// The group of the selected row, header or result.
+    fn selected_group(&self) -> Option<usize> {
+        match self.selected_row()? {
+            Row::Header(g) => Some(g),
+            Row::Result(i) => Some(self.result_group[i]),
+            Row::Dir(_) => None,
+        }
+    }
+
+    /// The marked files' paths in list order, leaving out standard input and
+    /// files inside archives, which there's nothing to do with.
+    fn marked_paths(&self) -> Vec<PathBuf> {
+        self.listed
+            .iter()
+            .filter(|g| self.marked.contains(g))
+            .map(|&g| &self.groups[g].path)
+            .filter(|path| {
+                *path != Path::new(STDIN_PATH) && archive::split_entry_path(path).is_none()
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn handle_files_key(&mut self, key: KeyEvent) -> Action {
+        match key.code {
+            KeyCode::Char(' ') => {
+                if let Some(group) = self.selected_group()
+                    && !self.marked.remove(&group)
+                {
+                    self.marked.insert(group);
+                }
+                // on to the next file, a result row would toggle the same one
+                let next = self.list_state.selected().and_then(|current| {
+                    (current + 1..self.rows.len()).find(|&i| matches!(self.rows[i], Row::Header(_)))
+                });
+                if next.is_some() {
+                    self.list_state.select(next);
+                }
+            }
+            KeyCode::Char('a') => {
+                let shown: HashSet<usize> = self.listed.iter().copied().collect();
+                // all, or none if all of them already are
+                if shown.is_subset(&self.marked) {
+                    self.marked.clear();
+                } else {
+                    self.marked = shown;
+                }
+            }
+            KeyCode::Char('d' | 'y' | 'w') if self.marked.is_empty() => {
+                self.message = Some("Mark files with Space first".to_string());
+            }
+            KeyCode::Char('d') => self.mode = Mode::ConfirmDelete,
+            KeyCode::Char('y') => {
+                let paths: Vec<String> = self
+                    .marked_paths()
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                return Action::Copy(paths.join("\n"));
+            }
+            KeyCode::Char('w') => self.mode = Mode::Export(Input::default()),
+            KeyCode::Esc => {
+                self.mode = Mode::Normal;
+                self.marked.clear();
+            }
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn handle_delete_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => {
+                self.mode = Mode::Files;
+                let (mut deleted, mut failed) = (0, Vec::new());
+                for path in self.marked_paths() {
+                    match fs::remove_file(&path) {
+                        Ok(()) => {
+                            deleted += 1;
+                            self.forget_file(&path);
+                        }
+                        Err(err) => failed.push(format!("{}: {}", path.display(), err)),
+                    }
+                }
+                let mut message = format!("Deleted {} file(s)", deleted);
+                if !failed.is_empty() {
+                    message.push_str(&format!(", failed on {}", failed.join(", ")));
+                }
+                self.message = Some(message);
+            }
+            KeyCode::Char('n') | KeyCode::Esc => {
+                self.mode = Mode::Files;
+                self.message = Some("Cancelled".to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_export_key(&mut self, key: KeyEvent) {
+        let Mode::Export(input) = &mut self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Enter if !input.text().is_empty() => {
+                let file = PathBuf::from(input.text());
+                let mut list = String::new();
+                let paths = self.marked_paths();
+                for path in &paths {
+                    list.push_str(&format!("{}\n", path.display()));
+                }
+                self.message = Some(match fs::write(&file, list) {
+                    Ok(()) => format!("Wrote {} path(s) to {}", paths.len(), file.display()),
+                    Err(err) => format!("Failed to write {}: {}", file.display(), err),
+                });
+                self.mode = Mode::Files;
+            }
+            KeyCode::Esc => self.mode = Mode::Files,
+            _ => {
+                input.handle_key(key);
+            }
+        }
+    }
+
+    fn handle_refine_key(&mut self, key: KeyEvent) -> Action {
+        let Mode::Refine { input, lines } = &mut self.mode else {
+            return Action::None;
+        };
+        match key.code {
+            KeyCode::Enter if !input.text().is_empty() => {
+                let (pattern, lines) = (input.text().to_string(), *lines);
+                self.mode = Mode::Normal;
+                return self.refine(pattern, lines);
+            }
+            KeyCode::Tab => *lines = !*lines,
+            KeyCode::Esc => self.mode = Mode::Normal,
+            _ => {
+                input.handle_key(key);
+            }
+        }
+        Action::None
+    }
+
+    fn handle_export_results_key(&mut self, key: KeyEvent) {
+        let Mode::ExportResults(input) = &mut self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Enter if !input.text().is_empty() => {
+                let file = PathBuf::from(input.text());
+                self.mode = Mode::Normal;
+                self.message = Some(match self.export(&file) {
+                    Ok(count) => format!("Wrote {} result(s) to {}", count, file.display()),
+                    Err(err) => format!("Failed to write {}: {}", file.display(), err),
+                });
+            }
+            KeyCode::Esc => self.mode = Mode::Normal,
+            _ => {
+                input.handle_key(key);
+            }
+        }
+    }
+
+    /// Sets the note of a result, marking it, once it's entered; Enter on
+    /// an empty note keeps the mark without one.
+    fn handle_note_key(&mut self, key: KeyEvent) {
+        let Mode::Note(_, input) = &mut self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Enter => {
+                if let Mode::Note(result, input) = mem::replace(&mut self.mode, Mode::Normal) {
+                    self.marks.insert(result, input.text().trim().to_string());
+                }
+            }
+            KeyCode::Esc => self.mode = Mode::Normal,
+            _ => {
+                input.handle_key(key);
+            }
+        }
+    }
+
+    /// Writes the results that pass the filter to `file`, file by file in
+    /// the list's order, in the format its extension names and with the
+    /// list's paths and line numbers; just the marked ones if some of them
+    /// are. Returns how many there were.
+    fn export(&self, file: &Path) -> io::Result<usize> {
+        let shown: Vec<usize> = self
+            .listed
+            .iter()
+            .flat_map(|&g| &self.groups[g].matches)
+            .copied()
+            .filter(|&i| self.passes[i])
+            .collect();
+        let marked: Vec<usize> = shown
+            .iter()
+            .copied()
+            .filter(|&i| self.marks.contains_key(&self.mark_key(i)))
+            .collect();
+        let shown: Vec<Match> = match marked.is_empty() {
+            true => shown,
+            false => marked,
+        }
+        .into_iter()
+        .map(|i| self.result(i).into_owned())
+        .collect();
+        let count = shown.len();
+        let layout = output::Layout {
+            paths: self.paths,
+            line_numbers: self.line_numbers,
+            column: false,
+            null: false,
+            only_matching: self.only_matching,
+            max_columns: None,
+            hyperlinks: None,
+            rules: self.rules,
+        };
+        let patterns = match self.patterns.is_empty() {
+            true => vec![self.pattern.clone()],
+            false => self.patterns.clone(),
+        };
+        let mut out = output::open(Some(file), false)?;
+        output::write_results(
+            &mut *out,
+            shown.into_iter(),
+            Format::from_path(file),
+            layout,
+            &patterns,
+        )?;
+        Ok(count)
+    }
+
+    fn handle_jump_key(&mut self, key: KeyEvent) {
+        let Mode::Jump(number) = &mut self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Char(digit) if digit.is_ascii_digit() => number.push(digit),
+            KeyCode::Backspace => {
+                number.pop();
+            }
+            KeyCode::Enter => {
+                let number = number.parse().unwrap_or(0);
+                self.mode = Mode::Normal;
+                self.jump_to(number);
+            }
+            KeyCode::Esc => self.mode = Mode::Normal,
+            _ => {}
+        }
+    }
+
+    /// Rows that get a number to jump to: results, or files when only
+    /// files are listed.
+    fn is_numbered(&self, row: Row) -> bool {
+        match row {
+            Row::Header(_) => self.files_only,
+            Row::Result(_) => true,
+            Row::Dir(_) => false,
+        }
+    }
+
+    /// Selects the row numbered `number`, counting from 1 as the list does.
+    fn jump_to(&mut self, number: usize) {
+        let position = self
+            .rows
+            .iter()
+            .enumerate()
+            .filter(|&(_, &row)| self.is_numbered(row))
+            .nth(number.wrapping_sub(1))
+            .map(|(position, _)| position);
+        match position {
+            Some(position) => self.list_state.select(Some(position)),
+            None => self.message = Some(format!("There's no result {}", number)),
+        }
+    }
+
+    fn handle_filter_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Enter => self.mode = Mode::Normal,
+            KeyCode::Esc => {
+                self.mode = Mode::Normal;
+                self.filter.clear();
+                self.refilter();
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+                self.refilter();
+            }
+            KeyCode::Char(c) => {
+                self.filter.push(c);
+                self.refilter();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let show_filter = matches!(
+        app.mode,
+        Mode::Filter
+            | Mode::Search
+            | Mode::Jump(_)
+            | Mode::Files
+            | Mode::ConfirmDelete
+            | Mode::Export(_)
+            | Mode::ExportResults(_)
+            | Mode::Note(..)
+            | Mode::Refine { .. }
+    ) || !app.filter.is_empty();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            // the progress gauge sits under the status while searching
+            Constraint::Length(if app.searching { 4 } else { 3 }),
+            Constraint::Min(0),
+            Constraint::Length(if show_filter { 3 } else { 0 }),
+            Constraint::Length(1),
+        ])
+        .split(frame.area());
+
+    let scope = app
+        .breadcrumb()
+        .map(|crumbs| format!(" in {}", crumbs))
+        .unwrap_or_default();
+    let header = bordered(&app.theme).title(format!(
+        " Search term: '{}'{} (Exit: q) ",
+        app.pattern, scope
+    ));
+    let header_area = header.inner(chunks[0]);
+    frame.render_widget(header, chunks[0]);
+    frame.render_widget(
+        Paragraph::new(app.status()).style(app.theme.status),
+        header_area,
+    );
+    if app.searching && header_area.height > 1 {
+        let files = app.stats.files();
+        let (ratio, label) = match app.file_count {
+            Some(total) if total > 0 => {
+                let ratio = (files as f64 / total as f64).min(1.0);
+                (ratio, format!("{}/{} files", files.min(total), total))
+            }
+            _ => (0.0, "counting files...".to_string()),
+        };
+        let gauge = LineGauge::default()
+            .ratio(ratio)
+            .label(label)
+            .filled_style(app.theme.gauge);
+        let gauge_area = Rect {
+            y: header_area.y + 1,
+            height: 1,
+            ..header_area
+        };
+        frame.render_widget(gauge, gauge_area);
+    }
+
+    // the flags go first when the bar is too narrow for both sides
+    let modes = app.modes();
+    let bar = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(modes.width() as u16 + 1),
+        ])
+        .split(chunks[3]);
+    frame.render_widget(
+        Paragraph::new(format!(" {}", app.totals())).style(app.theme.bar),
+        bar[0],
+    );
+    frame.render_widget(
+        Paragraph::new(format!("{} ", modes))
+            .alignment(Alignment::Right)
+            .style(app.theme.bar),
+        bar[1],
+    );
+
+    let list_area = if app.show_preview {
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(chunks[1]);
+        draw_preview(frame, app, panes[1]);
+        app.preview_area = Some(panes[1]);
+        panes[0]
+    } else {
+        app.preview_area = None;
+        chunks[1]
+    };
+    app.list_area = list_area;
+
+    if app.show_blame {
+        // whichever rows the list can show, around the selection it scrolls to
+        let height = list_area.height as usize;
+        let offset = app.list_state.offset();
+        let selected = app.list_state.selected().unwrap_or(offset);
+        let visible = offset.min(selected.saturating_sub(height))..offset.max(selected) + height;
+        for &row in app
+            .rows
+            .get(visible.start..visible.end.min(app.rows.len()))
+            .unwrap_or(&[])
+        {
+            if let Row::Result(i) = row
+                && has_blame(&app.results[i].path)
+            {
+                app.blames
+                    .request(&app.results[i].path, app.results[i].line_number);
+            }
+        }
+    }
+
+    // numbers to jump to with `:`, right-aligned in a column of their own
+    let numbered = app.rows.iter().filter(|&&row| app.is_numbered(row)).count();
+    app.number_width = numbered.to_string().len();
+    app.widest.set(0);
+    let mut number = 0;
+    let items: Vec<ListItem> = app
+        .rows
+        .iter()
+        .map(|&row| {
+            if app.is_numbered(row) {
+                number += 1;
+            }
+            ListItem::new(app.row_text(row, number))
+        })
+        .collect();
+
+    // select_last() parks the selection at usize::MAX until the list clamps it
+    let order = app
+        .sort
+        .map(|sort| format!(", by {}", sort.name()))
+        .unwrap_or_default();
+    let title = match app.list_state.selected() {
+        Some(i) if !app.rows.is_empty() => {
+            format!(
+                " Results ({}/{}{}) ",
+                i.min(app.rows.len() - 1) + 1,
+                app.rows.len(),
+                order
+            )
+        }
+        _ => match app.sort {
+            Some(sort) => format!(" Results (by {}) ", sort.name()),
+            None => " Results ".to_string(),
+        },
+    };
+    let list = List::new(items)
+        .block(bordered(&app.theme).title(title))
+        .highlight_style(app.theme.selection);
+
+    // the items borrow the app, so the state is rendered from a copy
+    let mut list_state = app.list_state.clone();
+    frame.render_stateful_widget(list, list_area, &mut list_state);
+    app.list_state = list_state;
+    app.page = list_area.height.saturating_sub(2).max(1);
+
+    if app.mode == Mode::Search {
+        let fields = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+            .split(chunks[2]);
+        let focused = app.theme.title.add_modifier(Modifier::BOLD);
+        let title_style = |active: bool| if active { focused } else { app.theme.title };
+        let mut block = bordered(&app.theme)
+            .title(" New search (Enter: run, Esc: cancel, Up/Down: history) ")
+            .title_style(title_style(!app.editing_path));
+        let text = app.search_input.text();
+        let mut line = Line::raw(text);
+        if let Some(error) = &app.pattern_error {
+            block = block
+                .border_style(app.theme.error)
+                .title_bottom(Line::styled(
+                    format!(" {} ", error.message),
+                    app.theme.error,
+                ));
+            if let Some(span) = &error.span {
+                // the span counts characters
+                let byte = |n: usize| text.char_indices().nth(n).map_or(text.len(), |(i, _)| i);
+                let (start, end) = (byte(span.start), byte(span.end));
+                let underlined = app.theme.error.add_modifier(Modifier::UNDERLINED);
+                line = Line::from(vec![
+                    Span::raw(&text[..start]),
+                    Span::styled(&text[start..end], underlined),
+                    Span::raw(&text[end..]),
+                ]);
+            }
+        }
+        let pattern = Paragraph::new(line).block(block);
+        let path = Paragraph::new(app.path_input.text()).block(
+            bordered(&app.theme)
+                .title(" Path (Tab: switch) ")
+                .title_style(title_style(app.editing_path)),
+        );
+        frame.render_widget(pattern, fields[0]);
+        frame.render_widget(path, fields[1]);
+        let (area, input) = if app.editing_path {
+            (fields[1], &app.path_input)
+        } else {
+            (fields[0], &app.search_input)
+        };
+        let x = area.x + 1 + input.cursor_offset();
+        frame.set_cursor_position(Position::new(x, area.y + 1));
+    } else if let Mode::Export(input) = &app.mode {
+        let prompt = Paragraph::new(input.text()).block(
+            bordered(&app.theme).title(" Write the marked paths to (Enter: write, Esc: cancel) "),
+        );
+        frame.render_widget(prompt, chunks[2]);
+        let x = chunks[2].x + 1 + input.cursor_offset();
+        frame.set_cursor_position(Position::new(x, chunks[2].y + 1));
+    } else if let Mode::ExportResults(input) = &app.mode {
+        let prompt = Paragraph::new(input.text()).block(bordered(&app.theme).title(
+            " Export the results to (.json, .md, .csv, .tsv, .sarif or plain text; Enter: write, Esc: cancel) ",
+        ));
+        frame.render_widget(prompt, chunks[2]);
+        let x = chunks[2].x + 1 + input.cursor_offset();
+        frame.set_cursor_position(Position::new(x, chunks[2].y + 1));
+    } else if let Mode::Note(_, input) = &app.mode {
+        let prompt = Paragraph::new(input.text())
+            .block(bordered(&app.theme).title(" Note on the result (Enter: save, Esc: cancel) "));
+        frame.render_widget(prompt, chunks[2]);
+        let x = chunks[2].x + 1 + input.cursor_offset();
+        frame.set_cursor_position(Position::new(x, chunks[2].y + 1));
+    } else if let Mode::Refine { input, lines } = &app.mode {
+        let title = if *lines {
+            " Search the results' lines for (Tab: their files instead; Enter: search, Esc: cancel) "
+        } else {
+            " Search the results' files for (Tab: just their lines; Enter: search, Esc: cancel) "
+        };
+        let prompt = Paragraph::new(input.text()).block(bordered(&app.theme).title(title));
+        frame.render_widget(prompt, chunks[2]);
+        let x = chunks[2].x + 1 + input.cursor_offset();
+        frame.set_cursor_position(Position::new(x, chunks[2].y + 1));
+    } else if matches!(app.mode, Mode::Files | Mode::ConfirmDelete) {
+        let hint = Paragraph::new(format!("{} file(s) marked", app.marked.len()))
+            .block(bordered(&app.theme).title(
+            " Files (Space: mark, a: all, d: delete, y: copy paths, w: write paths, Esc: done) ",
+        ));
+        frame.render_widget(hint, chunks[2]);
+    } else if let Mode::Jump(number) = &app.mode {
+        let jump = Paragraph::new(number.as_str())
+            .block(bordered(&app.theme).title(" Jump to result (Enter: go, Esc: cancel) "));
+        frame.render_widget(jump, chunks[2]);
+        let x = chunks[2].x + 1 + number.len() as u16;
+        frame.set_cursor_position(Position::new(x, chunks[2].y + 1));
+    } else if show_filter {
+        let filter = Paragraph::new(app.filter.as_str())
+            .block(bordered(&app.theme).title(" Filter (Enter: keep, Esc: clear) "));
+        frame.render_widget(filter, chunks[2]);
+        if app.mode == Mode::Filter {
+            let x = chunks[2].x + 1 + app.filter.width() as u16;
+            frame.set_cursor_position(Position::new(x, chunks[2].y + 1));
+        }
+    }
+
+    if app.mode == Mode::ConfirmDelete {
+        let question = format!(
+            "Delete {} file(s) from disk?\n\n(y) delete   (n) cancel",
+            app.marked_paths().len()
+        );
+        let area = centered(frame.area(), 60, 5);
+        let popup = Paragraph::new(question)
+            .wrap(Wrap { trim: false })
+            .block(bordered(&app.theme).title(" Confirm "));
+        frame.render_widget(Clear, area);
+        frame.render_widget(popup, area);
+    }
+
+    if let Mode::Confirm(pending) = &app.mode {
+        let path = app.results[pending[0]].path.display();
+        let question = format!(
+            "Write {} replacement(s) to {}?\n\n(y) apply   (n) cancel",
+            pending.len(),
+            path
+        );
+        let area = centered(frame.area(), 60, 5);
+        let popup = Paragraph::new(question)
+            .wrap(Wrap { trim: false })
+            .block(bordered(&app.theme).title(" Confirm "));
+        frame.render_widget(Clear, area);
+        frame.render_widget(popup, area);
+    }
+
+    if app.show_help {
+        draw_help(frame, app);
+    } else if app.show_errors {
+        draw_errors(frame, app);
+    }
+}
+
+/// The overlay listing what couldn't be read, as many as fit.
+fn draw_errors(frame: &mut Frame, app: &App) {
+    let area = centered(frame.area(), 100, app.errors.len() as u16 + 2);
+    let lines: Vec<Line> = app
+        .errors
+        .iter()
+        .map(|(path, message)| {
+            Line::from(vec![
+                Span::styled(path.display().to_string(), app.theme.path),
+                Span::raw(format!(": {}", message)),
+            ])
+        })
+        .collect();
+    let title = format!(" Errors ({}) (any key closes) ", app.errors.len());
+    let errors = Paragraph::new(lines).block(bordered(&app.theme).title(title));
+    frame.render_widget(Clear, area);
+    frame.render_widget(errors, area);
+}
+
+/// The keybinding overlay, with a summary of the state the keys act on.
+fn draw_help(frame: &mut Frame, app: &App) {
+    let key_style = app.theme.path;
+    let mut state = match app.mode {
+        Mode::Normal => "normal".to_string(),
+        Mode::Filter => "filter".to_string(),
+        Mode::Search => "new search".to_string(),
+        Mode::Confirm(_) => "confirm".to_string(),
+        Mode::Jump(_) => "jump".to_string(),
+        Mode::ExportResults(_) => "export".to_string(),
+        Mode::Note(..) => "note".to_string(),
+        Mode::Refine { .. } => "refine".to_string(),
+        Mode::Files | Mode::Export(_) => "file actions".to_string(),
+        Mode::ConfirmDelete => "confirm delete".to_string(),
+    };
+    if !app.filter.is_empty() {
+        state.push_str(&format!(", filtered by '{}'", app.filter));
+    }
+    if app.show_preview {
+        state.push_str(", preview on");
+    }
+    if app.allow_write {
+        state.push_str(", writes allowed");
+    }
+
+    let mut lines = vec![Line::from(format!("Mode: {}", state)), Line::default()];
+    lines.extend(KEY_HELP.iter().map(|(commands, action)| {
+        let keys: Vec<String> = commands.iter().map(|&c| app.keys.label(c)).collect();
+        let keys = keys.join(" / ");
+        let padding = " ".repeat(16usize.saturating_sub(keys.width()).max(1));
+        Line::from(vec![
+            Span::styled(keys + &padding, key_style),
+            Span::raw(*action),
+        ])
+    }));
+
+    let area = centered(frame.area(), 78, lines.len() as u16 + 2);
+    let help = Paragraph::new(lines).block(bordered(&app.theme).title(" Keys (any key closes) "));
+    frame.render_widget(Clear, area);
+    frame.render_widget(help, area);
+}
+
+/// Shows the selected file around the selected match, or its beginning when
+/// a file header is selected.
+fn draw_preview(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(preview) = &app.preview else {
+        let empty = bordered(&app.theme).title(" Preview ");
+        frame.render_widget(empty, area);
+        return;
+    };
+    let result = match app.selected_row() {
+        Some(Row::Result(i)) => Some(app.result(i)),
+        _ => None,
+    };
+    let result = result.as_deref();
+
+    let height = area.height.saturating_sub(2) as usize;
+    let target = result.map_or(0, |r| r.line_number - 1);
+    let span = result.map_or(0, |r| r.line_count());
+    let centred = target.saturating_sub(height.saturating_sub(span) / 2) as isize;
+    let last = preview.lines.len().saturating_sub(1) as isize;
+    let start = (centred + app.preview_scroll).clamp(0, last.max(0)) as usize;
+    let width = preview.lines.len().to_string().len();
+    let number = |n: usize| format!("{:>width$} ", n, width = width);
+
+    let mut lines = Vec::new();
+    let mut n = start;
+    while n < preview.lines.len() && lines.len() < height {
+        if let Some(result) = result
+            && n == target
+        {
+            // the match itself comes from the result so the highlight lines up
+            for (line_number, spans) in highlighted_lines(result, app.max_columns, &app.theme) {
+                let mut line = vec![Span::styled(number(line_number), app.theme.matched)];
+                line.extend(spans);
+                lines.push(Line::from(line));
+            }
+            n += span;
+            continue;
+        }
+        let tokens = preview.tokens.get(n).map_or(&[][..], Vec::as_slice);
+        let mut line = vec![Span::styled(number(n + 1), app.theme.context)];
+        line.extend(syntax_spans(
+            &preview.lines[n],
+            tokens,
+            app.max_columns,
+            &app.theme,
+        ));
+        lines.push(Line::from(line));
+        n += 1;
+    }
+
+    let title = format!(" {} ", app.paths.show(&preview.path).display());
+    let paragraph = Paragraph::new(lines).block(bordered(&app.theme).title(title));
+    frame.render_widget(paragraph, area);
+}
+
+/// A preview line split into spans styled as its syntax `tokens` say, cut
+/// down to `max_columns` like the other lines.
+fn syntax_spans<'a>(
+    line: &'a str,
+    tokens: &[(Range<usize>, Token)],
+    max_columns: Option<MaxColumns>,
+    theme: &Theme,
+) -> Vec<Span<'a>> {
+    let cut = max_columns.and_then(|max| max.cut(line));
+    let end = cut.as_ref().map_or(line.len(), |(cut, _)| *cut);
+    let mut spans = Vec::new();
+    let mut at = 0;
+    for (range, token) in tokens {
+        let range = range.start.min(end)..range.end.min(end);
+        if range.is_empty() {
+            continue;
+        }
+        if at < range.start {
+            spans.push(Span::raw(&line[at..range.start]));
+        }
+        let style = match token {
+            Token::Keyword => theme.keyword,
+            Token::String => theme.string,
+            Token::Comment => theme.comment,
+            Token::Number => theme.number,
+        };
+        spans.push(Span::styled(&line[range.clone()], style));
+        at = range.end;
+    }
+    if at < end {
+        spans.push(Span::raw(&line[at..end]));
+    }
+    if let Some((_, note)) = cut {
+        spans.push(Span::styled(note, theme.context));
+    }
+    spans
+}
+
+/// A block with a border and title in the theme's colours.
+fn bordered(theme: &Theme) -> Block<'static> {
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(theme.border)
+        .title_style(theme.title)
+}
+
+/// A `width` x `height` rectangle in the middle of `area`, shrunk to fit.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// Hands the terminal to the editor and takes it back once it exits.
+fn open_in_editor(terminal: &mut Tui, editor: &str, result: &Match) -> io::Result<()> {
+    restore_terminal(terminal)?;
+    let opened = editor::open(
+        editor,
+        &result.path,
+        result.line_number,
+        result.char_column(),
+    );
+    enable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        EnterAlternateScreen,
+        EnableMouseCapture
+    )?;
+    ACTIVE.store(true, Ordering::Relaxed);
+    terminal.clear()?;
+    opened
+}
+
+/// Browses `search` until quit, returning the results marked by then.
+pub fn run_ui(terminal: &mut Tui, options: &UiOptions, search: Search) -> io::Result<Marks> {
+    let Search {
+        events,
+        stats,
+        file_count,
+        mut cancel,
+        marks,
+    } = search;
+    let mut app = App::new(options, stats);
+    app.marks = marks;
+    let events_loop = EventLoop::new();
+    let mut generation = events_loop.follow(events, file_count);
+
+    loop {
+        app.refresh_rows();
+        app.update_preview();
+        app.check_pattern(&options.check);
+        terminal.draw(|frame| draw(frame, &mut app))?;
+
+        // wait for something to happen, or for the clock in the status bar
+        // to move on, then take whatever else has piled up; the cap keeps a
+        // fast search from holding back the next redraw
+        let Some(first) = events_loop.next(TICK) else {
+            continue;
+        };
+        let mut pending = Some(first);
+        let mut taken = 0;
+        while let Some(event) = pending.take() {
+            match event {
+                UiEvent::Search(g, event) if g == generation => match event {
+                    SearchEvent::Scanning(path) => app.current_file = path,
+                    SearchEvent::Match(result) => app.push_result(result),
+                    SearchEvent::Changed(path) => app.forget_file(&path),
+                    SearchEvent::Error(path, message) => app.errors.push((path, message)),
+                    SearchEvent::Done => app.finish(),
+                },
+                UiEvent::SearchEnded(g) if g == generation => {
+                    app.finish();
+                    app.watching = false;
+                }
+                UiEvent::FileCount(g, count) if g == generation => app.file_count = Some(count),
+                UiEvent::Search(..) | UiEvent::SearchEnded(_) | UiEvent::FileCount(..) => {}
+                UiEvent::InputError(err) => return Err(err),
+                UiEvent::Terminal(Event::Key(key)) => match app.handle_key(key) {
+                    Action::Quit => return Ok(app.marks),
+                    Action::Cancel => {
+                        // the results stay, the search sends Done once it has stopped
+                        cancel.cancel();
+                        app.cancelled = true;
+                    }
+                    Action::Open(i) => {
+                        let _paused = events_loop.pause_input();
+                        if let Err(err) = open_in_editor(terminal, &options.editor, &app.result(i))
+                        {
+                            app.message = Some(format!("Failed to open editor: {}", err));
+                        }
+                        app.edited(i);
+                    }
+                    Action::Copy(text) => {
+                        app.message = Some(match clipboard::copy(terminal.backend_mut(), &text) {
+                            Ok(()) if text.contains('\n') => {
+                                format!("Copied {} lines", text.lines().count())
+                            }
+                            Ok(()) => format!("Copied {}", text),
+                            Err(err) => format!("Failed to copy: {}", err),
+                        });
+                    }
+                    Action::Search(pattern, path) => {
+                        match (options.start)(&pattern, source(&path)) {
+                            Ok(search) => {
+                                cancel.cancel();
+                                generation = events_loop.follow(search.events, search.file_count);
+                                cancel = search.cancel;
+                                app.restart(&pattern, &path, search.stats);
+                                app.mode = Mode::Normal;
+                                app.search_input.remember(&pattern);
+                                history::add(&pattern);
+                            }
+                            Err(err) => {
+                                app.message = Some(err);
+                                app.step = None;
+                            }
+                        }
+                    }
+                    Action::Refine(pattern, files) => {
+                        match (options.start)(&pattern, Source::Files(files)) {
+                            Ok(search) => {
+                                cancel.cancel();
+                                generation = events_loop.follow(search.events, search.file_count);
+                                cancel = search.cancel;
+                                let path = app.path.clone();
+                                app.restart(&pattern, &path, search.stats);
+                                history::add(&pattern);
+                            }
+                            Err(err) => {
+                                app.message = Some(err);
+                                app.refine_step = None;
+                            }
+                        }
+                    }
+                    Action::Unrefine(refinement) => {
+                        let search = Search::finished(
+                            refinement.results,
+                            refinement.files,
+                            refinement.bytes,
+                        );
+                        cancel.cancel();
+                        generation = events_loop.follow(search.events, search.file_count);
+                        cancel = search.cancel;
+                        app.restart(&refinement.pattern, &refinement.path, search.stats);
+                    }
+                    Action::None => {}
+                },
+                UiEvent::Terminal(Event::Mouse(mouse)) => {
+                    if let Action::Open(i) = app.handle_mouse(mouse) {
+                        let _paused = events_loop.pause_input();
+                        if let Err(err) = open_in_editor(terminal, &options.editor, &app.result(i))
+                        {
+                            app.message = Some(format!("Failed to open editor: {}", err));
+                        }
+                        app.edited(i);
+                    }
+                }
+                // the next draw lays the lists and panes out to the new size
+                UiEvent::Terminal(Event::Resize(..)) => terminal.autoresize()?,
+                UiEvent::Terminal(_) => {}
+            }
+            taken += 1;
+            if taken < DRAIN_LIMIT {
+                pending = events_loop.try_next();
+            }
+        }
+    }
+}
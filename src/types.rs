@@ -0,0 +1,139 @@
+use crate::glob;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Built-in file types and the file-name globs that select them.
+const BUILTIN: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    (
+        "cpp",
+        &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx", "*.h"],
+    ),
+    ("cs", &["*.cs"]),
+    ("css", &["*.css", "*.scss", "*.sass", "*.less"]),
+    ("csv", &["*.csv", "*.tsv"]),
+    ("docker", &["Dockerfile", "*.dockerfile", "Dockerfile.*"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm", "*.xhtml"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.mjs", "*.cjs", "*.jsx"]),
+    ("json", &["*.json", "*.jsonl"]),
+    ("kotlin", &["*.kt", "*.kts"]),
+    ("lua", &["*.lua"]),
+    (
+        "make",
+        &["Makefile", "makefile", "GNUmakefile", "*.mk", "*.mak"],
+    ),
+    ("md", &["*.md", "*.markdown"]),
+    ("php", &["*.php"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("ruby", &["*.rb", "Gemfile", "Rakefile", "*.gemspec"]),
+    ("rust", &["*.rs"]),
+    (
+        "sh",
+        &["*.sh", "*.bash", "*.zsh", ".bashrc", ".zshrc", ".profile"],
+    ),
+    ("sql", &["*.sql"]),
+    ("swift", &["*.swift"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx", "*.mts", "*.cts"]),
+    ("txt", &["*.txt"]),
+    ("xml", &["*.xml", "*.xsd", "*.xsl", "*.svg"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// The known file types: the built-ins plus any `--type-add` definitions.
+pub struct TypeRegistry {
+    types: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for TypeRegistry {
+    fn default() -> TypeRegistry {
+        TypeRegistry::new()
+    }
+}
+
+impl TypeRegistry {
+    pub fn new() -> TypeRegistry {
+        let types = BUILTIN
+            .iter()
+            .map(|(name, globs)| {
+                let globs = globs.iter().map(|g| g.to_string()).collect();
+                (name.to_string(), globs)
+            })
+            .collect();
+        TypeRegistry { types }
+    }
+
+    /// Adds globs to a type from a `name:glob[,glob...]` definition,
+    /// creating the type if it doesn't exist yet.
+    pub fn add(&mut self, definition: &str) -> Result<(), String> {
+        let (name, globs) = definition
+            .split_once(':')
+            .filter(|(name, globs)| !name.is_empty() && !globs.is_empty())
+            .ok_or_else(|| {
+                format!(
+                    "invalid type definition '{}', expected NAME:GLOB",
+                    definition
+                )
+            })?;
+        self.types
+            .entry(name.to_string())
+            .or_default()
+            .extend(globs.split(',').map(str::to_string));
+        Ok(())
+    }
+
+    /// One `name: glob, glob` line per type, sorted by name.
+    pub fn list(&self) -> Vec<String> {
+        self.types
+            .iter()
+            .map(|(name, globs)| format!("{}: {}", name, globs.join(", ")))
+            .collect()
+    }
+
+    /// The names of the types, sorted.
+    pub fn names(&self) -> Vec<String> {
+        self.types.keys().cloned().collect()
+    }
+
+    /// Builds a filter that accepts files of any of the `selected` types.
+    pub fn select(&self, selected: &[String]) -> Result<TypeFilter, String> {
+        let mut globs = Vec::new();
+        for name in selected {
+            let patterns = self
+                .types
+                .get(name)
+                .ok_or_else(|| format!("unknown file type '{}' (see --type-list)", name))?;
+            for pattern in patterns {
+                let regex = glob::to_regex(pattern).map_err(|err| {
+                    format!("invalid glob '{}' for type {}: {}", pattern, name, err)
+                })?;
+                globs.push(regex);
+            }
+        }
+        Ok(TypeFilter { globs })
+    }
+}
+
+/// Matches file names against the globs of the `-t` types. With no types
+/// selected every file passes.
+#[derive(Debug, Default)]
+pub struct TypeFilter {
+    globs: Vec<Regex>,
+}
+
+impl TypeFilter {
+    pub fn is_empty(&self) -> bool {
+        self.globs.is_empty()
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name() else {
+            return false;
+        };
+        let name = name.to_string_lossy();
+        self.is_empty() || self.globs.iter().any(|glob| glob.is_match(&name))
+    }
+}
@@ -0,0 +1,559 @@
+use crate::ignore::{Gitignore, IgnoreStack, Overrides, Verdict};
+use crate::types::TypeFilter;
+use regex::Regex;
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// Descend into hidden files and directories.
+    pub hidden: bool,
+    /// Skip the ignore-file checks entirely.
+    pub no_ignore: bool,
+    /// Extra gitignore rules below every ignore file, relative to the root.
+    pub ignore: Arc<Gitignore>,
+    /// `--glob` filters; they win over ignore files.
+    pub overrides: Arc<Overrides>,
+    /// `-t` file types; files must match one unless a glob selected them.
+    pub types: Arc<TypeFilter>,
+    /// How many levels below the root to descend; files directly in the root
+    /// are at depth 1.
+    pub max_depth: Option<usize>,
+    /// Descend into symlinked directories, skipping any that lead back to
+    /// one of their own ancestors.
+    pub follow: bool,
+    /// Skip files larger than this many bytes.
+    pub max_filesize: Option<u64>,
+    /// Skip files smaller than this many bytes.
+    pub min_filesize: Option<u64>,
+    /// Only files last modified after this.
+    pub newer_than: Option<SystemTime>,
+    /// Only files last modified before this.
+    pub older_than: Option<SystemTime>,
+    /// Only files owned by this user ID.
+    #[cfg(unix)]
+    pub owner: Option<u32>,
+    /// Only files whose permission bits match.
+    #[cfg(unix)]
+    pub mode: Option<Mode>,
+    /// Only files whose path below the root (see [`below_root`]) matches.
+    pub path_regex: Option<Regex>,
+    /// Only these files, such as those git tracks (see [`crate::git`]),
+    /// each the root joined with its path below it.
+    pub only: Option<Arc<HashSet<PathBuf>>>,
+    pub order: Order,
+}
+
+/// Which permission bits a file needs, as with `find -perm`.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Exactly these, e.g. `644`.
+    Exactly(u32),
+    /// At least all of these, e.g. `-u+x` or `-100`.
+    All(u32),
+    /// Any of these, e.g. `/o+w` or `/002`.
+    Any(u32),
+}
+
+#[cfg(unix)]
+impl Mode {
+    /// Parses octal modes like `644`, or symbolic ones like `u+x,g+w`, with
+    /// `-` in front for all of their bits and `/` for any of them.
+    pub fn parse(text: &str) -> Result<Mode, String> {
+        let invalid = || format!("'{}' isn't a mode like 644, -u+x or /o+w", text);
+        let (make, bits): (fn(u32) -> Mode, &str) = match text.as_bytes().first() {
+            Some(b'-') => (Mode::All, &text[1..]),
+            Some(b'/') => (Mode::Any, &text[1..]),
+            _ => (Mode::Exactly, text),
+        };
+        if !bits.is_empty() && bits.bytes().all(|b| b.is_ascii_digit()) {
+            return u32::from_str_radix(bits, 8)
+                .ok()
+                .filter(|&bits| bits <= 0o7777)
+                .map(make)
+                .ok_or_else(invalid);
+        }
+        let mut mode = 0;
+        for clause in bits.split(',') {
+            let (who, what) = clause.split_once(['+', '=']).ok_or_else(invalid)?;
+            let mut mask = 0;
+            for c in if who.is_empty() { "a" } else { who }.chars() {
+                mask |= match c {
+                    'u' => 0o4700,
+                    'g' => 0o2070,
+                    'o' => 0o1007,
+                    'a' => 0o7777,
+                    _ => return Err(invalid()),
+                };
+            }
+            let mut set = 0;
+            for c in what.chars() {
+                set |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    's' => 0o6000,
+                    't' => 0o1000,
+                    _ => return Err(invalid()),
+                };
+            }
+            mode |= mask & set;
+        }
+        Ok(make(mode))
+    }
+
+    fn matches(self, mode: u32) -> bool {
+        let mode = mode & 0o7777;
+        match self {
+            Mode::Exactly(bits) => mode == bits,
+            Mode::All(bits) => mode & bits == bits,
+            Mode::Any(bits) => bits == 0 || mode & bits != 0,
+        }
+    }
+}
+
+/// The order a walk visits files in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Order {
+    /// Whichever the workers get to first, deeper directories before
+    /// shallower ones.
+    #[default]
+    Any,
+    /// The files of shallower directories before those of deeper ones,
+    /// still over all the workers.
+    BreadthFirst,
+    /// By path, on a single worker, so a walk of the same tree visits its
+    /// files in the same order every time.
+    Sorted,
+}
+
+/// The part of `path` below `root`, or its file name when `path` is the
+/// root itself, which is what path filters look at.
+pub fn below_root<'a>(root: &Path, path: &'a Path) -> &'a Path {
+    path.strip_prefix(root)
+        .ok()
+        .filter(|relative| !relative.as_os_str().is_empty())
+        .or_else(|| path.file_name().map(Path::new))
+        .unwrap_or(path)
+}
+
+impl WalkOptions {
+    /// These options for walking `root`, which the extra ignore rules and
+    /// the globs are then relative to.
+    pub fn rooted_at(&self, root: &Path) -> WalkOptions {
+        WalkOptions {
+            ignore: Arc::new(self.ignore.rooted_at(root)),
+            overrides: Arc::new(self.overrides.rooted_at(root)),
+            ..self.clone()
+        }
+    }
+
+    /// Whether the size, modification time, owner and permission limits
+    /// let a file with `metadata` through. Files whose time isn't known
+    /// aren't held to the time limits.
+    fn metadata_matches(&self, metadata: &Metadata) -> bool {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if self.owner.is_some_and(|owner| metadata.uid() != owner)
+                || self.mode.is_some_and(|mode| !mode.matches(metadata.mode()))
+            {
+                return false;
+            }
+        }
+        let size = metadata.len();
+        let modified = metadata.modified().ok();
+        self.max_filesize.is_none_or(|max| size <= max)
+            && self.min_filesize.is_none_or(|min| size >= min)
+            && self
+                .newer_than
+                .is_none_or(|since| modified.is_none_or(|modified| modified > since))
+            && self
+                .older_than
+                .is_none_or(|before| modified.is_none_or(|modified| modified < before))
+    }
+
+    fn has_metadata_limits(&self) -> bool {
+        #[cfg(unix)]
+        if self.owner.is_some() || self.mode.is_some() {
+            return true;
+        }
+        self.max_filesize.is_some()
+            || self.min_filesize.is_some()
+            || self.newer_than.is_some()
+            || self.older_than.is_some()
+    }
+
+    fn path_matches(&self, root: &Path, path: &Path) -> bool {
+        self.path_regex
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(&below_root(root, path).to_string_lossy()))
+    }
+}
+
+/// Walks `root`, spreading directories over `threads` workers, and calls
+/// `visit` for every file that survives the hidden and ignore-file filters.
+/// `visit` runs concurrently on all workers; returning `false` from it stops
+/// the walk.
+pub fn walk(
+    root: &Path,
+    options: &WalkOptions,
+    threads: usize,
+    visit: &(dyn Fn(&Path) -> bool + Sync),
+) {
+    walk_with_errors(root, options, threads, visit, &|_, _| {});
+}
+
+/// Like [`walk`], but also reports the directories and entries that
+/// couldn't be read to `on_error` instead of quietly skipping them.
+pub fn walk_with_errors(
+    root: &Path,
+    options: &WalkOptions,
+    threads: usize,
+    visit: &(dyn Fn(&Path) -> bool + Sync),
+    on_error: &(dyn Fn(&Path, io::Error) + Sync),
+) {
+    if !root.is_dir() {
+        // files named on the command line are held to the same limits
+        let limited = options.has_metadata_limits()
+            && fs::metadata(root).is_ok_and(|m| !options.metadata_matches(&m));
+        if options.path_matches(root, root) && !limited {
+            visit(root);
+        }
+        return;
+    }
+
+    if options.max_depth == Some(0) {
+        return;
+    }
+
+    let job = Job {
+        dir: root.to_path_buf(),
+        ignores: if options.no_ignore {
+            IgnoreStack::with_base(options.ignore.clone())
+        } else {
+            IgnoreStack::with_base(options.ignore.clone()).with_repository_above(root)
+        },
+        depth: 0,
+        ancestors: None,
+    };
+    if options.order == Order::Sorted {
+        walk_sorted(root, job, options, visit, on_error);
+        return;
+    }
+    let queue = WorkQueue::new(job, options.order == Order::BreadthFirst);
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                while let Some(job) = queue.pop() {
+                    let ancestors = if options.follow {
+                        match enter(&job) {
+                            Some(ancestors) => Some(ancestors),
+                            None => {
+                                queue.finish(std::iter::empty());
+                                continue;
+                            }
+                        }
+                    } else {
+                        None
+                    };
+
+                    let (files, subdirs, ignores) =
+                        read_dir(root, &job.dir, options, &job.ignores, on_error);
+                    for file in files {
+                        if !visit(&file) {
+                            queue.stop();
+                            return;
+                        }
+                    }
+                    let depth = job.depth + 1;
+                    let descend = options.max_depth.is_none_or(|max| depth < max);
+                    queue.finish(subdirs.into_iter().filter(|_| descend).map(|dir| Job {
+                        dir,
+                        ignores: ignores.clone(),
+                        depth,
+                        ancestors: ancestors.clone(),
+                    }));
+                }
+            });
+        }
+    });
+}
+
+/// Walks `job`'s directory for [`Order::Sorted`] on this thread, visiting
+/// files and descending into subdirectories as they come by path. Returns
+/// `false` once `visit` has stopped the walk.
+fn walk_sorted(
+    root: &Path,
+    job: Job,
+    options: &WalkOptions,
+    visit: &(dyn Fn(&Path) -> bool + Sync),
+    on_error: &(dyn Fn(&Path, io::Error) + Sync),
+) -> bool {
+    let ancestors = if options.follow {
+        match enter(&job) {
+            Some(ancestors) => Some(ancestors),
+            None => return true,
+        }
+    } else {
+        None
+    };
+    let (files, subdirs, ignores) = read_dir(root, &job.dir, options, &job.ignores, on_error);
+    let depth = job.depth + 1;
+    let descend = options.max_depth.is_none_or(|max| depth < max);
+    let mut entries: Vec<(PathBuf, bool)> = files
+        .into_iter()
+        .map(|file| (file, false))
+        .chain(
+            subdirs
+                .into_iter()
+                .filter(|_| descend)
+                .map(|dir| (dir, true)),
+        )
+        .collect();
+    entries.sort();
+    for (path, is_dir) in entries {
+        let going = if is_dir {
+            let job = Job {
+                dir: path,
+                ignores: ignores.clone(),
+                depth,
+                ancestors: ancestors.clone(),
+            };
+            walk_sorted(root, job, options, visit, on_error)
+        } else {
+            visit(&path)
+        };
+        if !going {
+            return false;
+        }
+    }
+    true
+}
+
+/// Returns the ancestor chain for `job`'s children, or `None` if its
+/// directory is one of its own ancestors (a symlink loop) or is gone.
+fn enter(job: &Job) -> Option<Arc<Ancestor>> {
+    let path = fs::canonicalize(&job.dir).ok()?;
+    let mut ancestor = job.ancestors.as_deref();
+    while let Some(current) = ancestor {
+        if current.path == path {
+            return None;
+        }
+        ancestor = current.parent.as_deref();
+    }
+    Some(Arc::new(Ancestor {
+        path,
+        parent: job.ancestors.clone(),
+    }))
+}
+
+/// Lists the files and subdirectories of `dir` that pass the filters, along
+/// with the ignore stack that applies to its children.
+fn read_dir(
+    root: &Path,
+    dir: &Path,
+    options: &WalkOptions,
+    parent: &IgnoreStack,
+    on_error: &(dyn Fn(&Path, io::Error) + Sync),
+) -> (Vec<PathBuf>, Vec<PathBuf>, IgnoreStack) {
+    let ignores = if options.no_ignore {
+        parent.clone()
+    } else {
+        parent.descend(dir)
+    };
+    let mut files = Vec::new();
+    let mut subdirs = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            on_error(dir, err);
+            return (files, subdirs, ignores);
+        }
+    };
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                on_error(dir, err);
+                continue;
+            }
+        };
+        let path = entry.path();
+        if !options.hidden && entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(err) => {
+                on_error(&path, err);
+                continue;
+            }
+        };
+        // symlinked directories are only followed on request, symlinked files
+        // are always searched
+        let is_dir =
+            file_type.is_dir() || (options.follow && file_type.is_symlink() && path.is_dir());
+        let is_file = file_type.is_file() || (file_type.is_symlink() && path.is_file());
+
+        match options.overrides.matched(&path, is_dir) {
+            Verdict::Ignore => continue,
+            Verdict::Whitelist => {}
+            Verdict::None => {
+                if !options.no_ignore && ignores.is_ignored(&path, is_dir) {
+                    continue;
+                }
+                if is_file && !options.types.matches(&path) {
+                    continue;
+                }
+            }
+        }
+        if is_dir {
+            subdirs.push(path);
+        } else if is_file {
+            if !options.path_matches(root, &path)
+                || options
+                    .only
+                    .as_ref()
+                    .is_some_and(|only| !only.contains(&path))
+            {
+                continue;
+            }
+            // the stat is only worth it when there's a limit to check
+            if options.has_metadata_limits()
+                && fs::metadata(&path).is_ok_and(|m| !options.metadata_matches(&m))
+            {
+                continue;
+            }
+            files.push(path);
+        }
+    }
+    (files, subdirs, ignores)
+}
+
+/// A directory waiting to be read.
+struct Job {
+    dir: PathBuf,
+    ignores: IgnoreStack,
+    /// 0 for the root.
+    depth: usize,
+    /// Where the directory was reached from, when following symlinks.
+    ancestors: Option<Arc<Ancestor>>,
+}
+
+/// A canonicalized directory on the way down from the root.
+struct Ancestor {
+    path: PathBuf,
+    parent: Option<Arc<Ancestor>>,
+}
+
+struct QueueState {
+    jobs: VecDeque<Job>,
+    /// Take the oldest job rather than the newest.
+    breadth_first: bool,
+    active: usize,
+    stopped: bool,
+}
+
+/// Pending directories shared by the walker threads. The walk is over once
+/// the queue is empty and no worker is still reading a directory that could
+/// add more.
+struct WorkQueue {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+}
+
+impl WorkQueue {
+    fn new(root: Job, breadth_first: bool) -> WorkQueue {
+        WorkQueue {
+            state: Mutex::new(QueueState {
+                jobs: VecDeque::from([root]),
+                breadth_first,
+                active: 0,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn pop(&self) -> Option<Job> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.stopped {
+                return None;
+            }
+            let job = match state.breadth_first {
+                true => state.jobs.pop_front(),
+                false => state.jobs.pop_back(),
+            };
+            if let Some(job) = job {
+                state.active += 1;
+                return Some(job);
+            }
+            if state.active == 0 {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    fn finish(&self, jobs: impl Iterator<Item = Job>) {
+        let mut state = self.state.lock().unwrap();
+        state.jobs.extend(jobs);
+        state.active -= 1;
+        self.changed.notify_all();
+    }
+
+    fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// The files a walk from `root` visits.
+    fn visited(root: &Path, options: &WalkOptions) -> Vec<PathBuf> {
+        let found = Mutex::new(Vec::new());
+        walk(root, options, 1, &|path| {
+            found.lock().unwrap().push(path.to_path_buf());
+            true
+        });
+        found.into_inner().unwrap()
+    }
+
+    #[test]
+    fn file_roots_are_held_to_the_metadata_limits() {
+        let file = env::temp_dir().join(format!("rstr-walk-{}.log", std::process::id()));
+        fs::write(&file, "ERROR\n").unwrap();
+        let limited = |options: WalkOptions| visited(&file, &options).is_empty();
+        assert!(!limited(WalkOptions::default()));
+        assert!(limited(WalkOptions {
+            min_filesize: Some(100),
+            ..WalkOptions::default()
+        }));
+        assert!(limited(WalkOptions {
+            max_filesize: Some(2),
+            ..WalkOptions::default()
+        }));
+        assert!(limited(WalkOptions {
+            newer_than: Some(SystemTime::now() + std::time::Duration::from_secs(60)),
+            ..WalkOptions::default()
+        }));
+        assert!(!limited(WalkOptions {
+            older_than: Some(SystemTime::now() + std::time::Duration::from_secs(60)),
+            ..WalkOptions::default()
+        }));
+        fs::remove_file(file).unwrap();
+    }
+}